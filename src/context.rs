@@ -1,7 +1,9 @@
+use crate::layer::{BlendMode, Filter, MaskMode};
+use crate::raster::{stroke_outline, FillRule, TOLERANCE};
 use crate::renderer::WgpuRenderer;
-use crate::text::{WgpuText, WgpuTextLayout};
+use crate::text::{PathTextOptions, WgpuText, WgpuTextLayout};
 use piet::{
-    kurbo::{Affine, Point, Rect, Shape, Size},
+    kurbo::{Affine, PathEl, Point, Rect, Shape, Size},
     Color, Error, FixedGradient, Image, ImageFormat, InterpolationMode, IntoBrush, RenderContext,
     StrokeStyle,
 };
@@ -35,25 +37,249 @@ impl Image for WgpuImage {
 
 pub struct WgpuRenderContext<'a> {
     pub(crate) renderer: &'a WgpuRenderer<'a>,
+    text: WgpuText,
 
     /// The context state stack. There is always at least one, until finishing.
     ctx_stack: Vec<CtxState>,
+    /// The first error of a drawing operation, see [`status`](RenderContext::status).
+    err: Result<(), Error>,
 }
 
 #[derive(Default)]
 struct CtxState {
     transform: Affine,
+    /// The clip rectangle in device pixels.
+    clip: Option<Rect>,
+    blend_mode: BlendMode,
 }
 
 impl<'a> WgpuRenderContext<'a> {
     pub fn new(renderer: &'a WgpuRenderer<'a>) -> Self {
+        // a new context replaces whatever the previous one drew
+        renderer.text_renderer.borrow_mut().clear();
+        renderer.layer_renderer.borrow_mut().stack.clear();
+        if let Some(rasterizer) = renderer.path_rasterizer.borrow_mut().as_mut() {
+            rasterizer.clear();
+        }
         let mut context = Self {
             renderer,
+            text: renderer.text.clone(),
             ctx_stack: vec![CtxState::default()],
+            err: Ok(()),
         };
         context
     }
 
+    /// Draws a text layout along `path`, placing each glyph by arc length and
+    /// turning it to follow the direction of the path.
+    ///
+    /// The baseline of the first line sits on the path, following lines are
+    /// stacked below it. Underlines and strikethroughs are not drawn.
+    pub fn draw_text_on_path(
+        &mut self,
+        layout: &WgpuTextLayout,
+        path: impl Shape,
+        options: PathTextOptions,
+    ) {
+        layout.draw_on_path(self, path, options);
+        self.end_draw();
+    }
+
+    /// Sets how the following drawings are combined with the content below
+    /// them. The mode is part of the state saved by [`save`](RenderContext::save).
+    ///
+    /// Each drawing with a mode other than [`BlendMode::SourceOver`] is
+    /// composited on its own, like a layer.
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.record_glyphs();
+        self.ctx_stack.last_mut().unwrap().blend_mode = blend_mode;
+    }
+
+    /// The blend mode of the following drawings.
+    pub fn blend_mode(&self) -> BlendMode {
+        self.ctx_stack.last().unwrap().blend_mode
+    }
+
+    /// Starts drawing into an offscreen layer, which is composited with
+    /// `opacity` and `blend_mode` when it is popped by [`pop_layer`](Self::pop_layer).
+    ///
+    /// This fades whole groups of drawings without the overlaps showing, as
+    /// opposed to drawing each of them with the opacity. The layer is clipped
+    /// to `clip`, in user space, and to the current clip, with the limits of
+    /// [`clip`](RenderContext::clip).
+    ///
+    /// The layer saves the context state like [`save`](RenderContext::save);
+    /// states saved inside it must be restored before it is popped.
+    pub fn push_layer(
+        &mut self,
+        opacity: f64,
+        blend_mode: BlendMode,
+        clip: impl Into<Option<Rect>>,
+    ) {
+        self.push_layer_with_filters(opacity, blend_mode, clip, &[]);
+    }
+
+    /// Starts drawing into an offscreen layer like [`push_layer`](Self::push_layer),
+    /// whose contents go through `filters`, in order, when it is popped.
+    ///
+    /// The distances of the filters are in user space. Filters can't draw
+    /// outside of the clip of the layer, so it should leave room for blurs
+    /// and shadows.
+    pub fn push_layer_with_filters(
+        &mut self,
+        opacity: f64,
+        blend_mode: BlendMode,
+        clip: impl Into<Option<Rect>>,
+        filters: &[Filter],
+    ) {
+        self.record_glyphs();
+        let _ = self.save();
+        if let Some(clip) = clip.into() {
+            self.clip(clip);
+        }
+        let transform = self.current_transform();
+        self.renderer.layer_renderer.borrow_mut().stack.push(
+            opacity.clamp(0.0, 1.0) as f32,
+            blend_mode,
+            self.current_clip(),
+            filters
+                .iter()
+                .map(|filter| filter.to_device(transform))
+                .collect(),
+            self.ctx_stack.len(),
+        );
+    }
+
+    /// Composites the layer pushed by [`push_layer`](Self::push_layer) and
+    /// restores the state from before it.
+    ///
+    /// Fails with [`Error::StackUnbalance`] if no layer is pushed, or if a
+    /// state saved inside the layer was not restored.
+    pub fn pop_layer(&mut self) -> Result<(), Error> {
+        if Some(self.ctx_stack.len()) != self.layer_state_depth() || self.is_mask_open() {
+            return Err(Error::StackUnbalance);
+        }
+        self.record_glyphs();
+        self.renderer.layer_renderer.borrow_mut().stack.pop();
+        self.pop_state();
+        Ok(())
+    }
+
+    /// Starts drawing the mask of the current layer, which hides the layer
+    /// where the mask is transparent or, with [`MaskMode::Luminance`], dark.
+    /// Drawing goes to the layer again after [`end_mask`](Self::end_mask).
+    ///
+    /// The mask is drawn with `transform` instead of the current transform,
+    /// so that the mask and the content of the layer are placed independently.
+    /// It saves the context state like [`save`](RenderContext::save), which
+    /// `end_mask` restores. Where nothing is drawn into the mask, the layer is
+    /// hidden; masking a layer again draws more into its mask.
+    ///
+    /// Fails with [`Error::StackUnbalance`] if no layer is pushed, or if a
+    /// mask is already being drawn.
+    pub fn begin_mask(&mut self, mode: MaskMode, transform: Affine) -> Result<(), Error> {
+        if self.layer_state_depth().is_none() || self.is_mask_open() {
+            return Err(Error::StackUnbalance);
+        }
+        self.record_glyphs();
+        self.save()?;
+        self.ctx_stack.last_mut().unwrap().transform = transform;
+        self.renderer
+            .layer_renderer
+            .borrow_mut()
+            .stack
+            .push_mask(mode, self.ctx_stack.len());
+        Ok(())
+    }
+
+    /// Finishes the mask started by [`begin_mask`](Self::begin_mask) and
+    /// restores the state from before it.
+    ///
+    /// Fails with [`Error::StackUnbalance`] if no mask is being drawn, or if
+    /// a state saved inside the mask was not restored.
+    pub fn end_mask(&mut self) -> Result<(), Error> {
+        if Some(self.ctx_stack.len()) != self.layer_state_depth() || !self.is_mask_open() {
+            return Err(Error::StackUnbalance);
+        }
+        self.record_glyphs();
+        self.renderer.layer_renderer.borrow_mut().stack.pop();
+        self.pop_state();
+        Ok(())
+    }
+
+    fn is_mask_open(&self) -> bool {
+        self.renderer.layer_renderer.borrow().stack.is_mask_open()
+    }
+
+    /// The depth of the state stack inside the current layer, if one is pushed.
+    fn layer_state_depth(&self) -> Option<usize> {
+        let layers = self.renderer.layer_renderer.borrow();
+        (layers.stack.depth() > 0).then(|| layers.stack.top().state_depth)
+    }
+
+    fn current_clip(&self) -> Option<Rect> {
+        self.ctx_stack.last().unwrap().clip
+    }
+
+    /// Assigns the glyphs drawn since the last call to the current layer, with
+    /// the current clip and blend mode. Called before the layer, the clip or
+    /// the blend mode change.
+    fn record_glyphs(&self) {
+        let end = self.renderer.text_renderer.borrow().len();
+        self.renderer.layer_renderer.borrow_mut().stack.add_glyphs(
+            end,
+            self.current_clip(),
+            self.blend_mode(),
+        );
+    }
+
+    /// Records a path drawn with the current transform, clip and blend mode,
+    /// if paths are drawn with [`PathRendering::Compute`](crate::PathRendering::Compute).
+    fn draw_path(
+        &self,
+        path: impl IntoIterator<Item = PathEl>,
+        brush: &Brush,
+        fill_rule: FillRule,
+    ) {
+        let mut rasterizer = self.renderer.path_rasterizer.borrow_mut();
+        let Some(rasterizer) = rasterizer.as_mut() else {
+            return;
+        };
+        // the glyphs drawn before are below the path
+        self.record_glyphs();
+        let Brush::Solid(color) = brush;
+        let index = rasterizer.push(
+            path,
+            self.current_transform(),
+            color,
+            fill_rule,
+            self.current_clip(),
+        );
+        self.renderer.layer_renderer.borrow_mut().stack.add_path(
+            index,
+            self.current_clip(),
+            self.blend_mode(),
+        );
+    }
+
+    /// The flattening tolerance in user space, for curves that are flattened
+    /// before they are transformed.
+    fn user_tolerance(&self) -> f64 {
+        let scale = self.current_transform().determinant().abs().sqrt();
+        if scale > 0.0 {
+            TOLERANCE / scale
+        } else {
+            TOLERANCE
+        }
+    }
+
+    /// Separates the drawing from the following ones if it is blended on its own.
+    fn end_draw(&self) {
+        if self.blend_mode() != BlendMode::SourceOver {
+            self.record_glyphs();
+        }
+    }
+
     fn pop_state(&mut self) {
         // This is an unwrap because we protect the invariant.
         let old_state = self.ctx_stack.pop().unwrap();
@@ -67,7 +293,7 @@ impl<'a> RenderContext for WgpuRenderContext<'a> {
     type TextLayout = WgpuTextLayout;
 
     fn status(&mut self) -> Result<(), Error> {
-        Ok(())
+        std::mem::replace(&mut self.err, Ok(()))
     }
 
     fn solid_brush(&mut self, color: Color) -> Self::Brush {
@@ -80,48 +306,90 @@ impl<'a> RenderContext for WgpuRenderContext<'a> {
 
     fn clear(&mut self, _: impl Into<Option<Rect>>, _color: Color) {}
 
-    fn stroke(&mut self, _shape: impl Shape, _brush: &impl IntoBrush<Self>, _width: f64) {}
+    fn stroke(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>, width: f64) {
+        self.stroke_styled(shape, brush, width, &StrokeStyle::new());
+    }
 
     fn stroke_styled(
         &mut self,
-        _shape: impl Shape,
-        _brush: &impl IntoBrush<Self>,
-        _width: f64,
-        _style: &StrokeStyle,
+        shape: impl Shape,
+        brush: &impl IntoBrush<Self>,
+        width: f64,
+        style: &StrokeStyle,
     ) {
+        if self.renderer.path_rasterizer.borrow().is_none() {
+            return;
+        }
+        let brush = brush.make_brush(self, || shape.bounding_box()).into_owned();
+        let outline = stroke_outline(shape, width, style, self.user_tolerance());
+        self.draw_path(outline, &brush, FillRule::NonZero);
     }
 
     fn fill(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
-        if let Some(rect) = shape.as_rect() {
-            let brush = brush.make_brush(self, || shape.bounding_box()).into_owned();
-            let Brush::Solid(color) = brush;
-            // self.renderer.fill_rect(rect, color);
-        }
+        let brush = brush.make_brush(self, || shape.bounding_box()).into_owned();
+        self.draw_path(
+            shape.path_elements(self.user_tolerance()),
+            &brush,
+            FillRule::NonZero,
+        );
     }
 
-    fn fill_even_odd(&mut self, _shape: impl Shape, _brush: &impl IntoBrush<Self>) {}
+    fn fill_even_odd(&mut self, shape: impl Shape, brush: &impl IntoBrush<Self>) {
+        let brush = brush.make_brush(self, || shape.bounding_box()).into_owned();
+        self.draw_path(
+            shape.path_elements(self.user_tolerance()),
+            &brush,
+            FillRule::EvenOdd,
+        );
+    }
 
-    fn clip(&mut self, _shape: impl Shape) {}
+    /// Clips to `shape`, which must be a rectangle that stays aligned with
+    /// the device axes under the current transform.
+    ///
+    /// Other shapes clip to their bounding box in device space, and make
+    /// [`status`](RenderContext::status) and [`finish`](RenderContext::finish)
+    /// fail with [`Error::NotSupported`].
+    fn clip(&mut self, shape: impl Shape) {
+        self.record_glyphs();
+        let state = self.ctx_stack.last_mut().unwrap();
+        let bounds = device_rect(&shape, state.transform).unwrap_or_else(|| {
+            if self.err.is_ok() {
+                self.err = Err(Error::NotSupported);
+            }
+            state.transform.transform_rect_bbox(shape.bounding_box())
+        });
+        state.clip = Some(match state.clip {
+            Some(clip) => clip.intersect(bounds),
+            None => bounds,
+        });
+    }
 
     fn text(&mut self) -> &mut Self::Text {
-        // &mut self.0
-        todo!()
+        &mut self.text
     }
 
-    fn draw_text(&mut self, _layout: &Self::TextLayout, _pos: impl Into<Point>) {}
+    fn draw_text(&mut self, layout: &Self::TextLayout, pos: impl Into<Point>) {
+        let pos = pos.into();
+        layout.draw_text(self, [pos.x as f32, pos.y as f32]);
+        self.end_draw();
+    }
 
     fn save(&mut self) -> Result<(), Error> {
         let new_state = CtxState {
             transform: self.current_transform(),
+            clip: self.current_clip(),
+            blend_mode: self.blend_mode(),
         };
         self.ctx_stack.push(new_state);
         Ok(())
     }
 
     fn restore(&mut self) -> Result<(), Error> {
-        if self.ctx_stack.len() <= 1 {
+        // the state saved by a layer is restored by popping it
+        if self.ctx_stack.len() <= 1 || Some(self.ctx_stack.len()) == self.layer_state_depth() {
             return Err(Error::StackUnbalance);
         }
+        self.record_glyphs();
         self.pop_state();
         Ok(())
     }
@@ -133,9 +401,11 @@ impl<'a> RenderContext for WgpuRenderContext<'a> {
         if self.ctx_stack.len() != 1 {
             return Err(Error::StackUnbalance);
         }
+        self.record_glyphs();
         self.pop_state();
-        Ok(())
-        // std::mem::replace(&mut self.err, Ok(()))
+        // the layout cache ages once per frame
+        self.text.end_frame();
+        std::mem::replace(&mut self.err, Ok(()))
     }
 
     fn transform(&mut self, transform: Affine) {
@@ -181,3 +451,40 @@ impl<'a> RenderContext for WgpuRenderContext<'a> {
 
     fn blurred_rect(&mut self, _rect: Rect, _blur_radius: f64, _brush: &impl IntoBrush<Self>) {}
 }
+
+/// Returns `shape` in device space if it is a rectangle there, that is a
+/// rectangle turned by a multiple of 90 degrees at most.
+fn device_rect(shape: &impl Shape, transform: Affine) -> Option<Rect> {
+    let rect = shape.as_rect()?;
+    // rotations by multiples of 90 degrees round their sines and cosines
+    let is_zero = |x: f64| x.abs() < 1e-9;
+    let [a, b, c, d, _, _] = transform.as_coeffs();
+    ((is_zero(b) && is_zero(c)) || (is_zero(a) && is_zero(d)))
+        .then(|| transform.transform_rect_bbox(rect))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piet::kurbo::{Circle, RoundedRect};
+
+    #[test]
+    fn only_axis_aligned_rects_clip_exactly() {
+        let rect = Rect::new(10.0, 20.0, 30.0, 40.0);
+        let transform = Affine::translate((5.0, 5.0)) * Affine::scale(2.0);
+        assert_eq!(
+            device_rect(&rect, transform),
+            Some(Rect::new(25.0, 45.0, 65.0, 85.0))
+        );
+        let quarter_turn = Affine::rotate(std::f64::consts::FRAC_PI_2);
+        let turned = device_rect(&rect, quarter_turn).unwrap();
+        assert!((turned.x0 + 40.0).abs() < 1e-9 && (turned.y1 - 30.0).abs() < 1e-9);
+
+        assert_eq!(device_rect(&rect, Affine::rotate(0.5)), None);
+        assert_eq!(device_rect(&rect, Affine::skew(0.5, 0.0)), None);
+        let rounded = RoundedRect::from_rect(rect, 4.0);
+        assert_eq!(device_rect(&rounded, Affine::IDENTITY), None);
+        let circle = Circle::new((0.0, 0.0), 5.0);
+        assert_eq!(device_rect(&circle, Affine::IDENTITY), None);
+    }
+}
@@ -0,0 +1,178 @@
+// Composites an offscreen layer into the target below it.
+//
+// A single triangle covers the viewport and the layer is read at the pixel
+// being shaded, as layers have the size of the target. The clip of the layer
+// is applied with a scissor rectangle.
+//
+// Blend modes without a fixed-function equivalent set BLEND_MODE, and blend
+// against a copy of the target in `backdrop`, following the W3C Compositing
+// and Blending spec. The result replaces the target.
+//
+// Masked layers set MASK, and are multiplied with the alpha or the luminance
+// of their mask in `mask`.
+//
+// Rasterized paths set REGION, as their texture only covers the region they
+// touch. The draw's viewport is set to that region, and the texture is read
+// relative to it.
+
+@group(0) @binding(0) var layer: texture_2d<f32>;
+
+#ifdef BLEND_MODE
+@group(0) @binding(1) var backdrop: texture_2d<f32>;
+
+const MULTIPLY: u32 = 1u;
+const OVERLAY: u32 = 2u;
+const DARKEN: u32 = 3u;
+const LIGHTEN: u32 = 4u;
+const COLOR_DODGE: u32 = 5u;
+const HUE: u32 = 6u;
+const SATURATION: u32 = 7u;
+const COLOR: u32 = 8u;
+const LUMINOSITY: u32 = 9u;
+
+const BLEND_MODE: u32 = #{BLEND_MODE}u;
+#endif
+
+#ifdef MASK
+@group(1) @binding(0) var mask: texture_2d<f32>;
+
+const ALPHA_MASK: u32 = 1u;
+const LUMINANCE_MASK: u32 = 2u;
+
+const MASK: u32 = #{MASK}u;
+#endif
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) @interpolate(flat) opacity: f32,
+    // the position within the viewport, from (0, 0) at the top left to (1, 1)
+    @location(1) uv: vec2<f32>,
+}
+
+@vertex
+fn vertex(@builtin(vertex_index) vertex_index: u32, @location(0) opacity: f32) -> VertexOutput {
+    // (-1, -1), (3, -1), (-1, 3)
+    let corner = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    out.opacity = opacity;
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+#ifdef REGION
+    let position = vec2<i32>(in.uv * vec2<f32>(textureDimensions(layer)));
+#else
+    let position = vec2<i32>(in.position.xy);
+#endif
+    // premultiplied, so opacity scales all channels
+    var source = textureLoad(layer, position, 0) * in.opacity;
+#ifdef MASK
+    let mask_color = textureLoad(mask, position, 0);
+    if MASK == LUMINANCE_MASK {
+        // premultiplied, so this is the luminance times the alpha
+        source *= dot(mask_color.rgb, vec3<f32>(0.2125, 0.7154, 0.0721));
+    } else {
+        source *= mask_color.a;
+    }
+#endif
+#ifdef BLEND_MODE
+    return blend(source, textureLoad(backdrop, position, 0));
+#else
+    return source;
+#endif
+}
+
+#ifdef BLEND_MODE
+fn blend(source: vec4<f32>, backdrop: vec4<f32>) -> vec4<f32> {
+    let cs = unpremultiply(source);
+    let cb = unpremultiply(backdrop);
+    let mixed = blend_color(cb, cs);
+    // source-over, with the blended color where both are covered
+    let color = source.a * (1.0 - backdrop.a) * cs
+        + source.a * backdrop.a * mixed
+        + (1.0 - source.a) * backdrop.rgb;
+    return vec4<f32>(color, source.a + backdrop.a * (1.0 - source.a));
+}
+
+fn unpremultiply(color: vec4<f32>) -> vec3<f32> {
+    if color.a <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    return color.rgb / color.a;
+}
+
+fn blend_color(cb: vec3<f32>, cs: vec3<f32>) -> vec3<f32> {
+    if BLEND_MODE == MULTIPLY {
+        return cb * cs;
+    } else if BLEND_MODE == OVERLAY {
+        return hard_light(cs, cb);
+    } else if BLEND_MODE == DARKEN {
+        return min(cb, cs);
+    } else if BLEND_MODE == LIGHTEN {
+        return max(cb, cs);
+    } else if BLEND_MODE == COLOR_DODGE {
+        return color_dodge(cb, cs);
+    } else if BLEND_MODE == HUE {
+        return set_lum(set_sat(cs, sat(cb)), lum(cb));
+    } else if BLEND_MODE == SATURATION {
+        return set_lum(set_sat(cb, sat(cs)), lum(cb));
+    } else if BLEND_MODE == COLOR {
+        return set_lum(cs, lum(cb));
+    } else if BLEND_MODE == LUMINOSITY {
+        return set_lum(cb, lum(cs));
+    }
+    return cs;
+}
+
+fn hard_light(cb: vec3<f32>, cs: vec3<f32>) -> vec3<f32> {
+    let multiply = cb * 2.0 * cs;
+    let screen = cb + (2.0 * cs - 1.0) - cb * (2.0 * cs - 1.0);
+    return select(screen, multiply, cs <= vec3<f32>(0.5));
+}
+
+fn color_dodge(cb: vec3<f32>, cs: vec3<f32>) -> vec3<f32> {
+    let dodged = min(vec3<f32>(1.0), cb / max(1.0 - cs, vec3<f32>(1e-6)));
+    let full = select(dodged, vec3<f32>(1.0), cs >= vec3<f32>(1.0));
+    return select(full, vec3<f32>(0.0), cb <= vec3<f32>(0.0));
+}
+
+fn lum(c: vec3<f32>) -> f32 {
+    return dot(c, vec3<f32>(0.3, 0.59, 0.11));
+}
+
+fn clip_color(c: vec3<f32>) -> vec3<f32> {
+    let l = lum(c);
+    let n = min(c.r, min(c.g, c.b));
+    let x = max(c.r, max(c.g, c.b));
+    var clipped = c;
+    if n < 0.0 {
+        clipped = l + (clipped - l) * l / (l - n);
+    }
+    if x > 1.0 {
+        clipped = l + (clipped - l) * (1.0 - l) / (x - l);
+    }
+    return clipped;
+}
+
+fn set_lum(c: vec3<f32>, l: f32) -> vec3<f32> {
+    return clip_color(c + (l - lum(c)));
+}
+
+fn sat(c: vec3<f32>) -> f32 {
+    return max(c.r, max(c.g, c.b)) - min(c.r, min(c.g, c.b));
+}
+
+fn set_sat(c: vec3<f32>, s: f32) -> vec3<f32> {
+    // stretches the channels so that the smallest is 0 and the largest `s`
+    let n = min(c.r, min(c.g, c.b));
+    let range = sat(c);
+    if range <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+    return (c - n) * s / range;
+}
+#endif
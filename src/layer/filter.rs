@@ -0,0 +1,698 @@
+use bytemuck::{Pod, Zeroable};
+use piet::{
+    kurbo::{Affine, Vec2 as KurboVec2},
+    Color,
+};
+use std::ops::Range;
+use wgpu::{
+    BindGroupLayoutEntry, BindingType, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder,
+    LoadOp, MultisampleState, Operations, RenderPassColorAttachment, RenderPassDescriptor,
+    SamplerBindingType, ShaderStages, StoreOp, TextureFormat, TextureSampleType, TextureUsages,
+    TextureViewDimension, VertexFormat, VertexStepMode,
+};
+
+use super::{render::target_descriptor, Layer, LayerRenderContext};
+use crate::{
+    render_resource::{
+        BindGroupEntries, BindGroupLayout, BufferVec, CachedRenderPipelineId, CachedTexture,
+        FragmentState, PipelineCache, RenderDevice, RenderPipelineDescriptor, RenderQueue, Sampler,
+        Shader, SpecializedRenderPipeline, SpecializedRenderPipelines, VertexBufferLayout,
+        VertexState,
+    },
+    text::linear_premultiplied,
+    HashMap,
+};
+
+/// Blurs with a larger standard deviation, in pixels, are done on a
+/// downsampled copy of the layer.
+const MAX_STD_DEVIATION: f32 = 4.0;
+
+/// The most times a layer is halved in size for a blur.
+const MAX_LEVEL: u32 = 6;
+
+/// An effect applied to the contents of a layer when it is popped, see
+/// [`WgpuRenderContext::push_layer_with_filters`](crate::WgpuRenderContext::push_layer_with_filters).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// A Gaussian blur with the standard deviation in user space units.
+    Blur { std_deviation: f64 },
+    /// Draws the shape of the contents below them, filled with `color`, moved
+    /// by `offset` and blurred with the standard deviation.
+    DropShadow {
+        offset: KurboVec2,
+        std_deviation: f64,
+        color: Color,
+    },
+    /// Transforms the color of each pixel.
+    ColorMatrix(ColorMatrix),
+}
+
+impl Filter {
+    /// Converts the distances of the filter from user space into device pixels.
+    pub(crate) fn to_device(&self, transform: Affine) -> Filter {
+        let scale = transform.determinant().abs().sqrt();
+        match self {
+            Filter::Blur { std_deviation } => Filter::Blur {
+                std_deviation: std_deviation * scale,
+            },
+            Filter::DropShadow {
+                offset,
+                std_deviation,
+                color,
+            } => {
+                let [a, b, c, d, _, _] = transform.as_coeffs();
+                Filter::DropShadow {
+                    offset: KurboVec2::new(
+                        a * offset.x + c * offset.y,
+                        b * offset.x + d * offset.y,
+                    ),
+                    std_deviation: std_deviation * scale,
+                    color: *color,
+                }
+            }
+            Filter::ColorMatrix(matrix) => Filter::ColorMatrix(*matrix),
+        }
+    }
+}
+
+/// A 4×5 matrix transforming unpremultiplied RGBA colors, in row-major order.
+///
+/// Each row computes one channel from the red, green, blue and alpha channels
+/// of the pixel, plus the last column as an offset, like SVG's `feColorMatrix`.
+/// Colors are transformed in linear RGB.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorMatrix(pub [f32; 20]);
+
+impl ColorMatrix {
+    /// Keeps colors as they are.
+    #[rustfmt::skip]
+    pub const IDENTITY: Self = Self([
+        1.0, 0.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0, 0.0,
+    ]);
+
+    /// Desaturates colors, fully at an `amount` of 1, like the CSS
+    /// `grayscale()` filter.
+    #[rustfmt::skip]
+    pub fn grayscale(amount: f32) -> Self {
+        let a = 1.0 - amount.clamp(0.0, 1.0);
+        Self([
+            0.2126 + 0.7874 * a, 0.7152 - 0.7152 * a, 0.0722 - 0.0722 * a, 0.0, 0.0,
+            0.2126 - 0.2126 * a, 0.7152 + 0.2848 * a, 0.0722 - 0.0722 * a, 0.0, 0.0,
+            0.2126 - 0.2126 * a, 0.7152 - 0.7152 * a, 0.0722 + 0.9278 * a, 0.0, 0.0,
+            0.0, 0.0, 0.0, 1.0, 0.0,
+        ])
+    }
+
+    /// Turns colors into shades of brown, fully at an `amount` of 1, like the
+    /// CSS `sepia()` filter.
+    #[rustfmt::skip]
+    pub fn sepia(amount: f32) -> Self {
+        let a = 1.0 - amount.clamp(0.0, 1.0);
+        Self([
+            0.393 + 0.607 * a, 0.769 - 0.769 * a, 0.189 - 0.189 * a, 0.0, 0.0,
+            0.349 - 0.349 * a, 0.686 + 0.314 * a, 0.168 - 0.168 * a, 0.0, 0.0,
+            0.272 - 0.272 * a, 0.534 - 0.534 * a, 0.131 + 0.869 * a, 0.0, 0.0,
+            0.0, 0.0, 0.0, 1.0, 0.0,
+        ])
+    }
+
+    /// Multiplies colors with `color`, including its alpha.
+    #[rustfmt::skip]
+    pub fn tint(color: Color) -> Self {
+        let [r, g, b, a] = linear_premultiplied(&color);
+        // the color channels are unpremultiplied
+        let [r, g, b] = if a > 0.0 { [r / a, g / a, b / a] } else { [0.0; 3] };
+        Self([
+            r, 0.0, 0.0, 0.0, 0.0,
+            0.0, g, 0.0, 0.0, 0.0,
+            0.0, 0.0, b, 0.0, 0.0,
+            0.0, 0.0, 0.0, a, 0.0,
+        ])
+    }
+
+    /// The matrix without the offsets, by columns.
+    fn columns(&self) -> [[f32; 4]; 4] {
+        let m = &self.0;
+        std::array::from_fn(|column| std::array::from_fn(|row| m[row * 5 + column]))
+    }
+
+    fn offsets(&self) -> [f32; 4] {
+        std::array::from_fn(|row| self.0[row * 5 + 4])
+    }
+}
+
+impl Default for ColorMatrix {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// One fullscreen pass of a filter, drawing the current texture of the chain
+/// into a new one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FilterPass {
+    /// Resamples into a texture of the viewport size divided by `2^level`.
+    Resample {
+        level: u32,
+    },
+    /// Blurs along `direction`, which is one pixel along an axis.
+    Blur {
+        direction: [i32; 2],
+        std_deviation: f32,
+    },
+    ColorMatrix(ColorMatrix),
+    /// Fills the alpha of the texture, moved by `offset` pixels, with the
+    /// premultiplied `color`. The texture is kept for the next [`Merge`](Self::Merge).
+    Shadow {
+        offset: [f32; 2],
+        color: [f32; 4],
+    },
+    /// Draws the kept texture over the current one.
+    Merge,
+}
+
+impl FilterPass {
+    fn pipeline_key(&self) -> FilterPipelineKey {
+        match self {
+            FilterPass::Resample { .. } => FilterPipelineKey::Resample,
+            FilterPass::Blur { .. } => FilterPipelineKey::Blur,
+            FilterPass::ColorMatrix(_) => FilterPipelineKey::ColorMatrix,
+            FilterPass::Shadow { .. } => FilterPipelineKey::Shadow,
+            FilterPass::Merge => FilterPipelineKey::Merge,
+        }
+    }
+
+    fn instance(&self) -> FilterInstance {
+        let mut instance = FilterInstance {
+            matrix: ColorMatrix::IDENTITY.columns(),
+            vector: [0.0; 4],
+            offset: [0.0; 2],
+            std_deviation: 0.0,
+        };
+        match *self {
+            FilterPass::Resample { .. } | FilterPass::Merge => {}
+            FilterPass::Blur {
+                direction,
+                std_deviation,
+            } => {
+                instance.offset = direction.map(|d| d as f32);
+                instance.std_deviation = std_deviation;
+            }
+            FilterPass::ColorMatrix(matrix) => {
+                instance.matrix = matrix.columns();
+                instance.vector = matrix.offsets();
+            }
+            FilterPass::Shadow { offset, color } => {
+                instance.offset = offset;
+                instance.vector = color;
+            }
+        }
+        instance
+    }
+}
+
+/// The parameters of a [`FilterPass`], as the instance read by `filter.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct FilterInstance {
+    /// The color matrix without the offsets, by columns.
+    matrix: [[f32; 4]; 4],
+    /// The offsets of the color matrix, or the color of a shadow.
+    vector: [f32; 4],
+    /// The direction of a blur, or the offset of a shadow in pixels.
+    offset: [f32; 2],
+    std_deviation: f32,
+}
+
+/// Appends the passes applying `filters`, in device pixels, to `passes`.
+pub(crate) fn filter_passes(filters: &[Filter], passes: &mut Vec<FilterPass>) {
+    for filter in filters {
+        match filter {
+            Filter::Blur { std_deviation } => blur_passes(*std_deviation as f32, passes),
+            Filter::DropShadow {
+                offset,
+                std_deviation,
+                color,
+            } => {
+                passes.push(FilterPass::Shadow {
+                    offset: [offset.x as f32, offset.y as f32],
+                    color: linear_premultiplied(color),
+                });
+                blur_passes(*std_deviation as f32, passes);
+                passes.push(FilterPass::Merge);
+            }
+            Filter::ColorMatrix(matrix) => passes.push(FilterPass::ColorMatrix(*matrix)),
+        }
+    }
+}
+
+/// Blurs along each axis, on a copy halved in size until the standard
+/// deviation is small enough to keep the kernel short.
+fn blur_passes(std_deviation: f32, passes: &mut Vec<FilterPass>) {
+    if std_deviation.is_nan() || std_deviation <= 0.0 {
+        return;
+    }
+    let mut level = 0;
+    while std_deviation / (1 << level) as f32 > MAX_STD_DEVIATION && level < MAX_LEVEL {
+        level += 1;
+        passes.push(FilterPass::Resample { level });
+    }
+    let std_deviation = (std_deviation / (1 << level) as f32).min(MAX_STD_DEVIATION);
+    for direction in [[1, 0], [0, 1]] {
+        passes.push(FilterPass::Blur {
+            direction,
+            std_deviation,
+        });
+    }
+    if level > 0 {
+        passes.push(FilterPass::Resample { level: 0 });
+    }
+}
+
+fn filter_shader() -> Shader {
+    Shader::from_wgsl(include_str!("filter.wgsl"), "piet_wgpu/layer/filter.wgsl")
+}
+
+/// The pipeline of a kind of [`FilterPass`], one per entry point of
+/// `filter.wgsl`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum FilterPipelineKey {
+    Resample,
+    Blur,
+    ColorMatrix,
+    Shadow,
+    Merge,
+}
+
+impl FilterPipelineKey {
+    const ALL: [Self; 5] = [
+        Self::Resample,
+        Self::Blur,
+        Self::ColorMatrix,
+        Self::Shadow,
+        Self::Merge,
+    ];
+
+    fn entry_point(self) -> &'static str {
+        match self {
+            Self::Resample => "resample",
+            Self::Blur => "blur",
+            Self::ColorMatrix => "color_matrix",
+            Self::Shadow => "shadow",
+            Self::Merge => "merge",
+        }
+    }
+}
+
+struct FilterPipeline {
+    layout: BindGroupLayout,
+    shader: Shader,
+    format: TextureFormat,
+}
+
+impl SpecializedRenderPipeline for FilterPipeline {
+    type Key = FilterPipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let entry_point = key.entry_point();
+        RenderPipelineDescriptor {
+            label: Some(format!("layer_filter_{entry_point}_pipeline").into()),
+            layout: vec![self.layout.clone()],
+            push_constant_ranges: Vec::new(),
+            vertex: VertexState {
+                shader: self.shader.clone(),
+                shader_defs: Vec::new(),
+                entry_point: "vertex".into(),
+                buffers: vec![VertexBufferLayout::from_vertex_formats(
+                    VertexStepMode::Instance,
+                    [
+                        VertexFormat::Float32x4,
+                        VertexFormat::Float32x4,
+                        VertexFormat::Float32x4,
+                        VertexFormat::Float32x4,
+                        VertexFormat::Float32x4,
+                        VertexFormat::Float32x2,
+                        VertexFormat::Float32,
+                    ],
+                )],
+            },
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                shader: self.shader.clone(),
+                shader_defs: Vec::new(),
+                entry_point: entry_point.into(),
+                targets: vec![Some(ColorTargetState {
+                    format: self.format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+        }
+    }
+}
+
+/// Applies the filters of layers, before they are composited.
+pub(crate) struct FilterRenderer {
+    pipeline: FilterPipeline,
+    pipelines: SpecializedRenderPipelines<FilterPipeline>,
+    /// The pipelines of the passes in this frame, queued again if they were
+    /// evicted.
+    pipeline_ids: HashMap<FilterPipelineKey, CachedRenderPipelineId>,
+    sampler: Sampler,
+    /// The passes of all filtered layers in this frame.
+    passes: Vec<FilterPass>,
+    /// The range of `passes` of each layer.
+    layer_passes: Vec<Range<usize>>,
+    /// The parameters of each pass, as the instance of its draw.
+    instances: BufferVec<FilterInstance>,
+}
+
+impl FilterRenderer {
+    pub(crate) fn new(
+        device: &RenderDevice,
+        pipeline_cache: &mut PipelineCache,
+        format: TextureFormat,
+    ) -> Self {
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("layer_filter_bind_group_layout"),
+            entries: &[
+                texture_entry(0),
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(2),
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("layer_filter_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let shader = filter_shader();
+        pipeline_cache.set_shader(shader.id, &shader);
+        let pipeline = FilterPipeline {
+            layout,
+            shader,
+            format,
+        };
+        // created ahead of the first filtered layer
+        let mut pipelines = SpecializedRenderPipelines::default();
+        pipelines.warm_up(pipeline_cache, &pipeline, FilterPipelineKey::ALL);
+
+        let mut instances = BufferVec::new(BufferUsages::VERTEX);
+        instances.set_label(Some("layer_filter_instances"));
+
+        Self {
+            pipeline,
+            pipelines,
+            pipeline_ids: HashMap::default(),
+            sampler,
+            passes: Vec::new(),
+            layer_passes: Vec::new(),
+            instances,
+        }
+    }
+
+    /// Plans the filter passes of `layers`, queues their pipelines and uploads
+    /// their parameters.
+    pub(crate) fn prepare(
+        &mut self,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        pipeline_cache: &PipelineCache,
+        layers: &[Layer],
+    ) {
+        self.passes.clear();
+        self.layer_passes.clear();
+        for layer in layers {
+            let start = self.passes.len();
+            filter_passes(&layer.filters, &mut self.passes);
+            self.layer_passes.push(start..self.passes.len());
+        }
+
+        self.pipeline_ids.clear();
+        for pass in &self.passes {
+            let key = pass.pipeline_key();
+            let id = self
+                .pipelines
+                .specialize(pipeline_cache, &self.pipeline, key);
+            self.pipeline_ids.insert(key, id);
+        }
+
+        self.instances.clear();
+        for pass in &self.passes {
+            self.instances.push(pass.instance());
+        }
+        self.instances.write_buffer(device, queue);
+    }
+
+    /// Applies the filters of the layer at `index` to its `texture`, returning
+    /// the filtered texture. Until all pipelines are compiled, the texture is
+    /// returned as it is.
+    pub(crate) fn apply(
+        &self,
+        ctx: &mut LayerRenderContext,
+        encoder: &mut CommandEncoder,
+        index: usize,
+        texture: CachedTexture,
+    ) -> CachedTexture {
+        let (device, pipeline_cache) = (ctx.device, ctx.pipeline_cache);
+        let Some(range) = self
+            .layer_passes
+            .get(index)
+            .filter(|range| !range.is_empty())
+        else {
+            return texture;
+        };
+        let passes = &self.passes[range.clone()];
+        let pipelines: Option<Vec<_>> = passes
+            .iter()
+            .map(|pass| {
+                let id = self.pipeline_ids.get(&pass.pipeline_key())?;
+                pipeline_cache.get_render_pipeline(*id)
+            })
+            .collect();
+        let (Some(pipelines), Some(instances)) = (pipelines, self.instances.buffer()) else {
+            return texture;
+        };
+
+        let mut current = texture;
+        let mut level = 0;
+        let mut kept: Option<CachedTexture> = None;
+        for ((pass, pipeline), instance) in passes.iter().zip(pipelines).zip(range.clone()) {
+            if let FilterPass::Resample { level: to } = pass {
+                level = *to;
+            }
+            let size = ctx.viewport.map(|length| (length >> level).max(1));
+            let output = ctx.texture_pool.get(
+                device,
+                target_descriptor(
+                    "layer_filter",
+                    self.pipeline.format,
+                    size,
+                    TextureUsages::RENDER_ATTACHMENT
+                        | TextureUsages::TEXTURE_BINDING
+                        | TextureUsages::COPY_SRC,
+                ),
+            );
+
+            let bind_group = device.create_bind_group(
+                "layer_filter_bind_group",
+                &self.pipeline.layout,
+                &BindGroupEntries::sequential((
+                    &current.default_view,
+                    &self.sampler,
+                    &kept.as_ref().unwrap_or(&current).default_view,
+                )),
+            );
+            {
+                let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some("layer_filter_pass"),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: &output.default_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, &bind_group, &[]);
+                render_pass.set_vertex_buffer(0, *instances.slice(..));
+                render_pass.draw(0..3, instance as u32..instance as u32 + 1);
+            }
+
+            match pass {
+                FilterPass::Shadow { .. } => kept = Some(current),
+                FilterPass::Merge => {
+                    ctx.texture_pool.release(&current);
+                    if let Some(kept) = kept.take() {
+                        ctx.texture_pool.release(&kept);
+                    }
+                }
+                _ => ctx.texture_pool.release(&current),
+            }
+            current = output;
+        }
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        render_resource::{test_device, validate_shader},
+        BlendMode,
+    };
+    use wgpu::Features;
+
+    #[test]
+    fn filter_shader_validates() {
+        validate_shader(&[], &filter_shader(), &[], Features::empty()).unwrap();
+    }
+
+    #[test]
+    fn downsamples_large_blurs() {
+        let mut passes = Vec::new();
+        filter_passes(&[Filter::Blur { std_deviation: 3.0 }], &mut passes);
+        assert_eq!(
+            passes,
+            [
+                FilterPass::Blur {
+                    direction: [1, 0],
+                    std_deviation: 3.0
+                },
+                FilterPass::Blur {
+                    direction: [0, 1],
+                    std_deviation: 3.0
+                },
+            ]
+        );
+
+        passes.clear();
+        filter_passes(
+            &[Filter::Blur {
+                std_deviation: 20.0,
+            }],
+            &mut passes,
+        );
+        assert_eq!(
+            passes,
+            [
+                FilterPass::Resample { level: 1 },
+                FilterPass::Resample { level: 2 },
+                FilterPass::Resample { level: 3 },
+                FilterPass::Blur {
+                    direction: [1, 0],
+                    std_deviation: 2.5
+                },
+                FilterPass::Blur {
+                    direction: [0, 1],
+                    std_deviation: 2.5
+                },
+                FilterPass::Resample { level: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn drop_shadow_merges_with_the_source() {
+        let mut passes = Vec::new();
+        let filters = [
+            Filter::DropShadow {
+                offset: KurboVec2::new(2.0, 3.0),
+                std_deviation: 0.0,
+                color: Color::BLACK,
+            },
+            Filter::ColorMatrix(ColorMatrix::IDENTITY),
+        ];
+        filter_passes(&filters, &mut passes);
+        assert_eq!(
+            passes,
+            [
+                FilterPass::Shadow {
+                    offset: [2.0, 3.0],
+                    color: [0.0, 0.0, 0.0, 1.0]
+                },
+                FilterPass::Merge,
+                FilterPass::ColorMatrix(ColorMatrix::IDENTITY),
+            ]
+        );
+    }
+
+    #[test]
+    fn color_matrices() {
+        let is_identity = |matrix: ColorMatrix| {
+            (matrix.0.iter().zip(ColorMatrix::IDENTITY.0)).all(|(a, b)| (a - b).abs() < 1e-6)
+        };
+        assert!(is_identity(ColorMatrix::grayscale(0.0)));
+        assert!(is_identity(ColorMatrix::sepia(0.0)));
+        // the columns and offsets are what the shader multiplies a color with
+        let gray = ColorMatrix::grayscale(1.0);
+        let color = [0.2, 0.5, 0.9, 1.0];
+        let mut out = gray.offsets();
+        for (column, channel) in gray.columns().iter().zip(color) {
+            for (out, coefficient) in out.iter_mut().zip(column) {
+                *out += coefficient * channel;
+            }
+        }
+        assert!((out[0] - out[1]).abs() < 1e-6 && (out[1] - out[2]).abs() < 1e-6);
+        assert_eq!(out[3], 1.0);
+    }
+
+    #[test]
+    fn evicted_pipelines_are_queued_again() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let mut pipeline_cache = PipelineCache::new(device.clone());
+        let mut filters =
+            FilterRenderer::new(&device, &mut pipeline_cache, TextureFormat::Rgba8Unorm);
+        pipeline_cache.block_on_queue();
+
+        pipeline_cache.set_eviction_age(Some(0));
+        pipeline_cache.process_queue();
+        pipeline_cache.process_queue();
+        assert_eq!(pipeline_cache.stats().evicted, FilterPipelineKey::ALL.len());
+        pipeline_cache.set_eviction_age(None);
+
+        let layer = Layer {
+            opacity: 1.0,
+            blend_mode: BlendMode::SourceOver,
+            clip: None,
+            items: Vec::new(),
+            filters: vec![Filter::Blur { std_deviation: 1.0 }],
+            mask: None,
+            state_depth: 1,
+        };
+        filters.prepare(&device, &queue, &pipeline_cache, &[layer]);
+        pipeline_cache.block_on_queue();
+        let id = filters.pipeline_ids[&FilterPipelineKey::Blur];
+        assert!(pipeline_cache.get_render_pipeline(id).is_some());
+    }
+}
@@ -0,0 +1,109 @@
+// The passes of the filters applied to layers.
+//
+// Each pass draws a triangle covering its target and reads the texture drawn
+// by the previous pass, which has the same size unless the pass resamples it.
+// Colors are premultiplied, and transparent outside of the textures.
+
+@group(0) @binding(0) var input: texture_2d<f32>;
+@group(0) @binding(1) var input_sampler: sampler;
+// the texture kept by a shadow pass, for the merge pass
+@group(0) @binding(2) var kept: texture_2d<f32>;
+
+// the parameters of the pass
+struct FilterInstance {
+    // the color matrix, without the offsets
+    @location(0) matrix_0: vec4<f32>,
+    @location(1) matrix_1: vec4<f32>,
+    @location(2) matrix_2: vec4<f32>,
+    @location(3) matrix_3: vec4<f32>,
+    // the offsets of the color matrix, or the color of a shadow
+    @location(4) vector: vec4<f32>,
+    // the direction of a blur, or the offset of a shadow in pixels
+    @location(5) offset: vec2<f32>,
+    @location(6) std_deviation: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) matrix_0: vec4<f32>,
+    @location(2) @interpolate(flat) matrix_1: vec4<f32>,
+    @location(3) @interpolate(flat) matrix_2: vec4<f32>,
+    @location(4) @interpolate(flat) matrix_3: vec4<f32>,
+    @location(5) @interpolate(flat) vector: vec4<f32>,
+    @location(6) @interpolate(flat) offset: vec2<f32>,
+    @location(7) @interpolate(flat) std_deviation: f32,
+}
+
+@vertex
+fn vertex(@builtin(vertex_index) vertex_index: u32, instance: FilterInstance) -> VertexOutput {
+    // (-1, -1), (3, -1), (-1, 3)
+    let corner = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(corner * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    out.matrix_0 = instance.matrix_0;
+    out.matrix_1 = instance.matrix_1;
+    out.matrix_2 = instance.matrix_2;
+    out.matrix_3 = instance.matrix_3;
+    out.vector = instance.vector;
+    out.offset = instance.offset;
+    out.std_deviation = instance.std_deviation;
+    return out;
+}
+
+fn load(texture: texture_2d<f32>, position: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(texture));
+    if any(position < vec2<i32>(0)) || any(position >= size) {
+        return vec4<f32>(0.0);
+    }
+    return textureLoad(texture, position, 0);
+}
+
+@fragment
+fn resample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(input, input_sampler, in.uv);
+}
+
+@fragment
+fn blur(in: VertexOutput) -> @location(0) vec4<f32> {
+    let center = vec2<i32>(in.position.xy);
+    let direction = vec2<i32>(in.offset);
+    let radius = i32(ceil(in.std_deviation * 3.0));
+    let scale = -0.5 / (in.std_deviation * in.std_deviation);
+
+    var sum = vec4<f32>(0.0);
+    var weights = 0.0;
+    for (var i = -radius; i <= radius; i++) {
+        let weight = exp(f32(i * i) * scale);
+        sum += load(input, center + direction * i) * weight;
+        weights += weight;
+    }
+    return sum / weights;
+}
+
+@fragment
+fn color_matrix(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(input, vec2<i32>(in.position.xy), 0);
+    var straight = vec4<f32>(0.0);
+    if color.a > 0.0 {
+        straight = vec4<f32>(color.rgb / color.a, color.a);
+    }
+    let matrix = mat4x4<f32>(in.matrix_0, in.matrix_1, in.matrix_2, in.matrix_3);
+    let transformed = clamp(matrix * straight + in.vector, vec4<f32>(0.0), vec4<f32>(1.0));
+    return vec4<f32>(transformed.rgb * transformed.a, transformed.a);
+}
+
+@fragment
+fn shadow(in: VertexOutput) -> @location(0) vec4<f32> {
+    let position = vec2<i32>(floor(in.position.xy - in.offset));
+    return in.vector * load(input, position).a;
+}
+
+@fragment
+fn merge(in: VertexOutput) -> @location(0) vec4<f32> {
+    let position = vec2<i32>(in.position.xy);
+    let source = textureLoad(kept, position, 0);
+    return source + textureLoad(input, position, 0) * (1.0 - source.a);
+}
@@ -0,0 +1,517 @@
+use piet::kurbo::Rect;
+use std::ops::Range;
+
+mod filter;
+mod render;
+
+pub use filter::{ColorMatrix, Filter};
+pub(crate) use render::{LayerRenderContext, LayerRenderer, LayerTarget};
+
+/// How drawings or a layer are combined with the content below them.
+///
+/// The Porter-Duff operators and [`Screen`](Self::Screen) map to fixed-function
+/// blending. The other modes are composited in a shader, which reads a copy of
+/// the content below, as defined by the W3C Compositing and Blending spec.
+/// Like layers, modes other than [`SourceOver`](Self::SourceOver) apply to the
+/// whole clip, so modes such as [`Copy`](Self::Copy) also clear the content
+/// below outside of the drawings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Clears the content below.
+    Clear,
+    /// Replaces the content below.
+    Copy,
+    /// Draws over the content below.
+    #[default]
+    SourceOver,
+    /// Draws under the content below.
+    DestinationOver,
+    /// Draws where the content below is, replacing it.
+    SourceIn,
+    /// Keeps the content below where it is drawn over.
+    DestinationIn,
+    /// Draws where the content below is not, replacing it.
+    SourceOut,
+    /// Keeps the content below where it is not drawn over.
+    DestinationOut,
+    /// Draws over the content below, only where it is.
+    SourceAtop,
+    /// Draws under the content below, keeping it only where it is drawn over.
+    DestinationAtop,
+    /// Keeps the parts of the drawing and the content below that don't overlap.
+    Xor,
+    /// Adds the colors to the content below.
+    Plus,
+    /// Multiplies the colors with the content below, darkening it.
+    Multiply,
+    /// Multiplies the inverted colors with the content below, lightening it.
+    Screen,
+    /// Multiplies or screens the colors, depending on the content below.
+    Overlay,
+    /// Keeps the darker of the colors and the content below.
+    Darken,
+    /// Keeps the lighter of the colors and the content below.
+    Lighten,
+    /// Brightens the content below to reflect the colors.
+    ColorDodge,
+    /// Takes the hue of the colors, and the saturation and luminosity of the
+    /// content below.
+    Hue,
+    /// Takes the saturation of the colors, and the hue and luminosity of the
+    /// content below.
+    Saturation,
+    /// Takes the hue and saturation of the colors, and the luminosity of the
+    /// content below.
+    Color,
+    /// Takes the luminosity of the colors, and the hue and saturation of the
+    /// content below.
+    Luminosity,
+}
+
+/// How the drawings of a mask hide the layer they mask, see
+/// [`WgpuRenderContext::begin_mask`](crate::WgpuRenderContext::begin_mask).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MaskMode {
+    /// The layer is as opaque as the mask.
+    #[default]
+    Alpha,
+    /// The layer is as opaque as the mask is bright, like SVG's `<mask>`.
+    Luminance,
+}
+
+/// The mask of a [`Layer`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Mask {
+    pub(crate) mode: MaskMode,
+    /// The layer holding the drawings of the mask, by its index in the
+    /// [`LayerStack`]. It is not an item of any layer.
+    pub(crate) layer: usize,
+}
+
+/// Something drawn into a [`Layer`], in drawing order.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum LayerItem {
+    /// Glyph instances of the text renderer, clipped to a rectangle in device pixels.
+    Glyphs {
+        range: Range<u32>,
+        clip: Option<Rect>,
+    },
+    /// Paths of the path rasterizer, which clips each of them on its own.
+    Paths { range: Range<u32> },
+    /// A nested layer, by its index in the [`LayerStack`].
+    Layer(usize),
+}
+
+/// A group of drawings which is rendered offscreen and composited as a whole.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Layer {
+    pub(crate) opacity: f32,
+    pub(crate) blend_mode: BlendMode,
+    /// The area the layer is composited into, in device pixels.
+    pub(crate) clip: Option<Rect>,
+    pub(crate) items: Vec<LayerItem>,
+    /// The filters applied before compositing, in device pixels.
+    pub(crate) filters: Vec<Filter>,
+    pub(crate) mask: Option<Mask>,
+    /// The depth of the context state stack while the layer is open.
+    pub(crate) state_depth: usize,
+}
+
+impl Layer {
+    /// Whether compositing the layer has no effect.
+    pub(crate) fn is_invisible(&self) -> bool {
+        self.items.is_empty()
+            || self.opacity <= 0.0
+            || self.clip.is_some_and(|clip| clip.is_zero_area())
+    }
+}
+
+/// The tree of layers drawn into during a frame. The first layer is the root,
+/// which is drawn straight into the surface.
+pub(crate) struct LayerStack {
+    layers: Vec<Layer>,
+    /// The indices of the layers which are drawn into, the last one being current.
+    open: Vec<usize>,
+    /// The number of glyph instances assigned to layers so far.
+    glyphs_end: u32,
+}
+
+impl Default for LayerStack {
+    fn default() -> Self {
+        Self {
+            layers: vec![Layer {
+                opacity: 1.0,
+                blend_mode: BlendMode::SourceOver,
+                clip: None,
+                items: Vec::new(),
+                filters: Vec::new(),
+                mask: None,
+                state_depth: 1,
+            }],
+            open: vec![0],
+            glyphs_end: 0,
+        }
+    }
+}
+
+impl LayerStack {
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(crate) fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// The number of layers pushed and not yet popped.
+    pub(crate) fn depth(&self) -> usize {
+        self.open.len() - 1
+    }
+
+    /// The current layer.
+    pub(crate) fn top(&self) -> &Layer {
+        &self.layers[*self.open.last().unwrap()]
+    }
+
+    /// Assigns the glyph instances recorded since the last call, up to `end`,
+    /// to the current layer.
+    ///
+    /// Glyphs drawn with a mode other than [`BlendMode::SourceOver`] are put
+    /// in a layer of their own, which is composited with that mode.
+    pub(crate) fn add_glyphs(&mut self, end: u32, clip: Option<Rect>, blend_mode: BlendMode) {
+        if end <= self.glyphs_end {
+            return;
+        }
+        let start = std::mem::replace(&mut self.glyphs_end, end);
+        let item = LayerItem::Glyphs {
+            range: start..end,
+            clip,
+        };
+        let Some(items) = self.blended_items(item, clip, blend_mode) else {
+            return;
+        };
+        match items.last_mut() {
+            Some(LayerItem::Glyphs {
+                range,
+                clip: last_clip,
+            }) if range.end == start && *last_clip == clip => range.end = end,
+            _ => items.push(LayerItem::Glyphs {
+                range: start..end,
+                clip,
+            }),
+        }
+    }
+
+    /// Adds the path of the path rasterizer at `index` to the current layer,
+    /// like [`add_glyphs`](Self::add_glyphs).
+    pub(crate) fn add_path(&mut self, index: u32, clip: Option<Rect>, blend_mode: BlendMode) {
+        let item = LayerItem::Paths {
+            range: index..index + 1,
+        };
+        let Some(items) = self.blended_items(item, clip, blend_mode) else {
+            return;
+        };
+        match items.last_mut() {
+            Some(LayerItem::Paths { range }) if range.end == index => range.end += 1,
+            _ => items.push(LayerItem::Paths {
+                range: index..index + 1,
+            }),
+        }
+    }
+
+    /// Puts `item` in a layer of its own if it is drawn with a mode other than
+    /// [`BlendMode::SourceOver`], or else returns the items of the current
+    /// layer to add it to.
+    fn blended_items(
+        &mut self,
+        item: LayerItem,
+        clip: Option<Rect>,
+        blend_mode: BlendMode,
+    ) -> Option<&mut Vec<LayerItem>> {
+        if blend_mode != BlendMode::SourceOver {
+            self.push(1.0, blend_mode, clip, Vec::new(), 0);
+            self.layers.last_mut().unwrap().items.push(item);
+            self.open.pop();
+            return None;
+        }
+        let index = *self.open.last().unwrap();
+        Some(&mut self.layers[index].items)
+    }
+
+    /// Opens a new layer inside the current one.
+    pub(crate) fn push(
+        &mut self,
+        opacity: f32,
+        blend_mode: BlendMode,
+        clip: Option<Rect>,
+        filters: Vec<Filter>,
+        state_depth: usize,
+    ) {
+        let index = self.layers.len();
+        self.layers.push(Layer {
+            opacity,
+            blend_mode,
+            clip,
+            items: Vec::new(),
+            filters,
+            mask: None,
+            state_depth,
+        });
+        let parent = *self.open.last().unwrap();
+        self.layers[parent].items.push(LayerItem::Layer(index));
+        self.open.push(index);
+    }
+
+    /// Opens the mask of the current layer, which is drawn into until it is
+    /// closed by [`pop`](Self::pop). Reopening a mask draws more into it.
+    pub(crate) fn push_mask(&mut self, mode: MaskMode, state_depth: usize) {
+        let parent = *self.open.last().unwrap();
+        let index = match self.layers[parent].mask {
+            Some(mask) => mask.layer,
+            None => {
+                self.layers.push(Layer {
+                    opacity: 1.0,
+                    blend_mode: BlendMode::SourceOver,
+                    clip: None,
+                    items: Vec::new(),
+                    filters: Vec::new(),
+                    mask: None,
+                    state_depth,
+                });
+                self.layers.len() - 1
+            }
+        };
+        self.layers[index].state_depth = state_depth;
+        self.layers[parent].mask = Some(Mask { mode, layer: index });
+        self.open.push(index);
+    }
+
+    /// Whether the current layer is a mask.
+    pub(crate) fn is_mask_open(&self) -> bool {
+        match self.open[..] {
+            [.., parent, top] => self.layers[parent]
+                .mask
+                .is_some_and(|mask| mask.layer == top),
+            _ => false,
+        }
+    }
+
+    /// Closes the current layer, returning false if only the root is open.
+    pub(crate) fn pop(&mut self) -> bool {
+        if self.open.len() <= 1 {
+            return false;
+        }
+        self.open.pop();
+        true
+    }
+}
+
+/// Converts a clip rectangle in device pixels into a scissor rectangle
+/// `[x, y, width, height]` within the viewport, or `None` if nothing is visible.
+pub(crate) fn scissor_rect(clip: Option<Rect>, viewport: [u32; 2]) -> Option<[u32; 4]> {
+    let viewport_rect = Rect::new(0.0, 0.0, viewport[0] as f64, viewport[1] as f64);
+    let rect = match clip {
+        Some(clip) => clip.expand().intersect(viewport_rect),
+        None => viewport_rect,
+    };
+    if rect.width() <= 0.0 || rect.height() <= 0.0 {
+        return None;
+    }
+    Some([
+        rect.x0 as u32,
+        rect.y0 as u32,
+        rect.width() as u32,
+        rect.height() as u32,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_layer_tree() {
+        let mut stack = LayerStack::default();
+        stack.add_glyphs(2, None, BlendMode::SourceOver);
+        stack.push(0.5, BlendMode::SourceOver, None, Vec::new(), 2);
+        stack.add_glyphs(3, None, BlendMode::SourceOver);
+        let clip = Some(Rect::new(0.0, 0.0, 10.0, 10.0));
+        stack.add_glyphs(5, clip, BlendMode::SourceOver);
+        assert_eq!(stack.depth(), 1);
+        assert!(stack.pop());
+        // drawing continues in the root after the layer
+        stack.add_glyphs(6, None, BlendMode::SourceOver);
+        assert!(!stack.pop());
+
+        let layers = stack.layers();
+        assert_eq!(
+            layers[0].items,
+            [
+                LayerItem::Glyphs {
+                    range: 0..2,
+                    clip: None
+                },
+                LayerItem::Layer(1),
+                LayerItem::Glyphs {
+                    range: 5..6,
+                    clip: None
+                },
+            ]
+        );
+        assert_eq!(
+            layers[1].items,
+            [
+                LayerItem::Glyphs {
+                    range: 2..3,
+                    clip: None
+                },
+                LayerItem::Glyphs { range: 3..5, clip },
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_glyph_runs() {
+        let mut stack = LayerStack::default();
+        stack.add_glyphs(2, None, BlendMode::SourceOver);
+        stack.add_glyphs(2, None, BlendMode::SourceOver);
+        stack.add_glyphs(4, None, BlendMode::SourceOver);
+        assert_eq!(
+            stack.top().items,
+            [LayerItem::Glyphs {
+                range: 0..4,
+                clip: None
+            }]
+        );
+    }
+
+    #[test]
+    fn merges_path_runs() {
+        let mut stack = LayerStack::default();
+        stack.add_path(0, None, BlendMode::SourceOver);
+        stack.add_path(1, None, BlendMode::SourceOver);
+        stack.add_glyphs(1, None, BlendMode::SourceOver);
+        stack.add_path(2, None, BlendMode::SourceOver);
+        stack.add_path(3, None, BlendMode::Screen);
+        assert_eq!(
+            stack.top().items,
+            [
+                LayerItem::Paths { range: 0..2 },
+                LayerItem::Glyphs {
+                    range: 0..1,
+                    clip: None
+                },
+                LayerItem::Paths { range: 2..3 },
+                LayerItem::Layer(1),
+            ]
+        );
+        assert_eq!(stack.layers()[1].items, [LayerItem::Paths { range: 3..4 }]);
+    }
+
+    #[test]
+    fn blended_draws_get_their_own_layer() {
+        let mut stack = LayerStack::default();
+        stack.add_glyphs(2, None, BlendMode::SourceOver);
+        stack.add_glyphs(3, None, BlendMode::Multiply);
+        stack.add_glyphs(4, None, BlendMode::Multiply);
+        stack.add_glyphs(5, None, BlendMode::SourceOver);
+        assert_eq!(stack.depth(), 0);
+
+        let layers = stack.layers();
+        assert_eq!(
+            layers[0].items,
+            [
+                LayerItem::Glyphs {
+                    range: 0..2,
+                    clip: None
+                },
+                LayerItem::Layer(1),
+                LayerItem::Layer(2),
+                LayerItem::Glyphs {
+                    range: 4..5,
+                    clip: None
+                },
+            ]
+        );
+        assert_eq!(layers[2].blend_mode, BlendMode::Multiply);
+        assert_eq!(
+            layers[2].items,
+            [LayerItem::Glyphs {
+                range: 3..4,
+                clip: None
+            }]
+        );
+    }
+
+    #[test]
+    fn records_masks_apart_from_the_content() {
+        let mut stack = LayerStack::default();
+        stack.push(1.0, BlendMode::SourceOver, None, Vec::new(), 2);
+        stack.add_glyphs(1, None, BlendMode::SourceOver);
+        stack.push_mask(MaskMode::Alpha, 3);
+        assert!(stack.is_mask_open());
+        stack.add_glyphs(2, None, BlendMode::SourceOver);
+        assert!(stack.pop());
+        assert!(!stack.is_mask_open());
+        stack.add_glyphs(3, None, BlendMode::SourceOver);
+        // drawing into the mask again adds to it
+        stack.push_mask(MaskMode::Luminance, 3);
+        stack.add_glyphs(4, None, BlendMode::SourceOver);
+        assert!(stack.pop());
+        assert!(stack.pop());
+
+        let layers = stack.layers();
+        assert_eq!(layers.len(), 3);
+        assert_eq!(layers[0].items, [LayerItem::Layer(1)]);
+        assert_eq!(
+            layers[1].mask,
+            Some(Mask {
+                mode: MaskMode::Luminance,
+                layer: 2
+            })
+        );
+        assert_eq!(
+            layers[1].items,
+            [
+                LayerItem::Glyphs {
+                    range: 0..1,
+                    clip: None
+                },
+                LayerItem::Glyphs {
+                    range: 2..3,
+                    clip: None
+                },
+            ]
+        );
+        assert_eq!(
+            layers[2].items,
+            [
+                LayerItem::Glyphs {
+                    range: 1..2,
+                    clip: None
+                },
+                LayerItem::Glyphs {
+                    range: 3..4,
+                    clip: None
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn scissor_rects() {
+        assert_eq!(scissor_rect(None, [100, 50]), Some([0, 0, 100, 50]));
+        assert_eq!(
+            scissor_rect(Some(Rect::new(10.5, -5.0, 20.2, 30.0)), [100, 50]),
+            Some([10, 0, 11, 30])
+        );
+        assert_eq!(
+            scissor_rect(Some(Rect::new(90.0, 10.0, 120.0, 60.0)), [100, 50]),
+            Some([90, 10, 10, 40])
+        );
+        assert_eq!(
+            scissor_rect(Some(Rect::new(200.0, 0.0, 300.0, 10.0)), [100, 50]),
+            None
+        );
+    }
+}
@@ -0,0 +1,658 @@
+use wgpu::{
+    BindGroupLayoutEntry, BindingType, BlendComponent, BlendFactor, BlendOperation, BlendState,
+    BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, LoadOp, MultisampleState,
+    Operations, RenderPass, RenderPassColorAttachment, RenderPassDescriptor, ShaderStages, StoreOp,
+    TextureFormat, TextureSampleType, TextureUsages, TextureViewDimension, VertexFormat,
+    VertexStepMode,
+};
+
+use super::{filter::FilterRenderer, scissor_rect, BlendMode, LayerItem, LayerStack, MaskMode};
+use crate::{
+    raster::PathRasterizer,
+    render_resource::{
+        BindGroupEntries, BindGroupLayout, BufferVec, CachedRenderPipelineId, FragmentState,
+        PipelineCache, RenderDevice, RenderPipelineDescriptor, RenderQueue, Shader, ShaderDefVal,
+        SpecializedRenderPipeline, SpecializedRenderPipelines, TexturePool, TextureView,
+        VertexBufferLayout, VertexState,
+    },
+    text::TextRenderer,
+    HashMap,
+};
+use std::ops::Range;
+
+impl BlendMode {
+    /// The fixed-function blend state of the mode, for premultiplied colors.
+    fn blend_state(self) -> Option<BlendState> {
+        use BlendFactor::*;
+
+        let (src_factor, dst_factor) = match self {
+            BlendMode::Clear => (Zero, Zero),
+            BlendMode::Copy => (One, Zero),
+            BlendMode::SourceOver => (One, OneMinusSrcAlpha),
+            BlendMode::DestinationOver => (OneMinusDstAlpha, One),
+            BlendMode::SourceIn => (DstAlpha, Zero),
+            BlendMode::DestinationIn => (Zero, SrcAlpha),
+            BlendMode::SourceOut => (OneMinusDstAlpha, Zero),
+            BlendMode::DestinationOut => (Zero, OneMinusSrcAlpha),
+            BlendMode::SourceAtop => (DstAlpha, OneMinusSrcAlpha),
+            BlendMode::DestinationAtop => (OneMinusDstAlpha, SrcAlpha),
+            BlendMode::Xor => (OneMinusDstAlpha, OneMinusSrcAlpha),
+            BlendMode::Plus => (One, One),
+            // cs + cb - cs * cb
+            BlendMode::Screen => {
+                return Some(BlendState {
+                    color: BlendComponent {
+                        src_factor: One,
+                        dst_factor: OneMinusSrc,
+                        operation: BlendOperation::Add,
+                    },
+                    alpha: BlendComponent::OVER,
+                })
+            }
+            _ => return None,
+        };
+        let component = BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: BlendOperation::Add,
+        };
+        Some(BlendState {
+            color: component,
+            alpha: component,
+        })
+    }
+
+    /// The `BLEND_MODE` of the composite shader, for the modes that have no
+    /// fixed-function blend state.
+    fn shader_blend_mode(self) -> Option<u32> {
+        match self {
+            BlendMode::Multiply => Some(1),
+            BlendMode::Overlay => Some(2),
+            BlendMode::Darken => Some(3),
+            BlendMode::Lighten => Some(4),
+            BlendMode::ColorDodge => Some(5),
+            BlendMode::Hue => Some(6),
+            BlendMode::Saturation => Some(7),
+            BlendMode::Color => Some(8),
+            BlendMode::Luminosity => Some(9),
+            _ => None,
+        }
+    }
+}
+
+impl MaskMode {
+    /// The `MASK` of the composite shader.
+    fn shader_mask_mode(self) -> u32 {
+        match self {
+            MaskMode::Alpha => 1,
+            MaskMode::Luminance => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct CompositePipelineKey {
+    blend_mode: BlendMode,
+    mask: Option<MaskMode>,
+    /// Whether the texture only covers the viewport of the draw, for
+    /// rasterized paths.
+    region: bool,
+}
+
+/// The pipeline compositing rasterized paths into their layer.
+const PATHS_KEY: CompositePipelineKey = CompositePipelineKey {
+    blend_mode: BlendMode::SourceOver,
+    mask: None,
+    region: true,
+};
+
+/// The pipeline compositing a layer into its parent, specialized by blend mode
+/// and mask.
+struct CompositePipeline {
+    layout: BindGroupLayout,
+    /// The layout with a copy of the target, for blend modes in the shader.
+    backdrop_layout: BindGroupLayout,
+    /// The layout of the second bind group, holding the mask of the layer.
+    mask_layout: BindGroupLayout,
+    shader: Shader,
+    format: TextureFormat,
+}
+
+impl SpecializedRenderPipeline for CompositePipeline {
+    type Key = CompositePipelineKey;
+
+    fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+        let mut shader_defs = Vec::new();
+        let mut layout = match key.blend_mode.shader_blend_mode() {
+            Some(mode) => {
+                shader_defs.push(ShaderDefVal::UInt("BLEND_MODE".into(), mode));
+                vec![self.backdrop_layout.clone()]
+            }
+            None => vec![self.layout.clone()],
+        };
+        if let Some(mask) = key.mask {
+            shader_defs.push(ShaderDefVal::UInt("MASK".into(), mask.shader_mask_mode()));
+            layout.push(self.mask_layout.clone());
+        }
+        if key.region {
+            shader_defs.push("REGION".into());
+        }
+        RenderPipelineDescriptor {
+            label: Some(format!("layer_composite_pipeline_{key:?}").into()),
+            layout,
+            push_constant_ranges: Vec::new(),
+            vertex: VertexState {
+                shader: self.shader.clone(),
+                shader_defs: shader_defs.clone(),
+                entry_point: "vertex".into(),
+                buffers: vec![VertexBufferLayout::from_vertex_formats(
+                    VertexStepMode::Instance,
+                    [VertexFormat::Float32],
+                )],
+            },
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                shader: self.shader.clone(),
+                shader_defs,
+                entry_point: "fragment".into(),
+                targets: vec![Some(ColorTargetState {
+                    format: self.format,
+                    // the shader blends the other modes and replaces the target
+                    blend: key.blend_mode.blend_state(),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+        }
+    }
+}
+
+fn composite_shader() -> Shader {
+    Shader::from_wgsl(
+        include_str!("composite.wgsl"),
+        "piet_wgpu/layer/composite.wgsl",
+    )
+}
+
+/// What [`LayerRenderer::render`] draws with.
+pub(crate) struct LayerRenderContext<'a> {
+    pub(crate) device: &'a RenderDevice,
+    pub(crate) pipeline_cache: &'a PipelineCache,
+    pub(crate) text_renderer: &'a TextRenderer,
+    /// The path rasterizer, if paths are drawn with compute shaders.
+    pub(crate) rasterizer: Option<&'a PathRasterizer>,
+    pub(crate) texture_pool: &'a mut TexturePool,
+    pub(crate) viewport: [u32; 2],
+}
+
+/// A texture which [`LayerRenderer::render`] draws into.
+#[derive(Clone, Copy)]
+pub(crate) struct LayerTarget<'a> {
+    pub(crate) view: &'a TextureView,
+    /// The texture of the view, if it can be copied from. Without it, blend
+    /// modes that are composited in a shader fall back to
+    /// [`BlendMode::SourceOver`].
+    pub(crate) texture: Option<&'a wgpu::Texture>,
+}
+
+/// Renders the [`LayerStack`] of a frame, drawing each pushed layer into a
+/// texture of the [`TexturePool`] and compositing it into its parent.
+pub(crate) struct LayerRenderer {
+    pub(crate) stack: LayerStack,
+    composite: CompositePipeline,
+    filters: FilterRenderer,
+    pipelines: SpecializedRenderPipelines<CompositePipeline>,
+    pipeline_ids: HashMap<CompositePipelineKey, CachedRenderPipelineId>,
+    /// The opacity of each layer, as the instance of its composite draw.
+    opacities: BufferVec<f32>,
+}
+
+impl LayerRenderer {
+    pub(crate) fn new(
+        device: &RenderDevice,
+        pipeline_cache: &mut PipelineCache,
+        format: TextureFormat,
+    ) -> Self {
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: false },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("layer_composite_bind_group_layout"),
+            entries: &[texture_entry(0)],
+        });
+        let backdrop_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("layer_composite_backdrop_bind_group_layout"),
+            entries: &[texture_entry(0), texture_entry(1)],
+        });
+        let mask_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("layer_composite_mask_bind_group_layout"),
+            entries: &[texture_entry(0)],
+        });
+        let shader = composite_shader();
+        pipeline_cache.set_shader(shader.id, &shader);
+
+        let mut opacities = BufferVec::new(BufferUsages::VERTEX);
+        opacities.set_label(Some("layer_opacities"));
+
+        Self {
+            stack: LayerStack::default(),
+            composite: CompositePipeline {
+                layout,
+                backdrop_layout,
+                mask_layout,
+                shader,
+                format,
+            },
+            filters: FilterRenderer::new(device, pipeline_cache, format),
+            pipelines: SpecializedRenderPipelines::default(),
+            pipeline_ids: HashMap::default(),
+            opacities,
+        }
+    }
+
+    /// Queues the composite pipelines of the recorded layers and uploads their
+    /// opacities and filter parameters.
+    pub(crate) fn prepare(
+        &mut self,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        pipeline_cache: &PipelineCache,
+    ) {
+        self.opacities.clear();
+        for layer in self.stack.layers() {
+            self.opacities.push(layer.opacity);
+        }
+        self.opacities.write_buffer(device, queue);
+        self.filters
+            .prepare(device, queue, pipeline_cache, self.stack.layers());
+
+        let id = self
+            .pipelines
+            .specialize(pipeline_cache, &self.composite, PATHS_KEY);
+        self.pipeline_ids.insert(PATHS_KEY, id);
+        for layer in &self.stack.layers()[1..] {
+            let mask = layer.mask.map(|mask| mask.mode);
+            let mut keys = vec![CompositePipelineKey {
+                blend_mode: layer.blend_mode,
+                mask,
+                region: false,
+            }];
+            if layer.blend_mode.shader_blend_mode().is_some() {
+                // the fallback for targets that can't be copied
+                keys.push(CompositePipelineKey {
+                    blend_mode: BlendMode::SourceOver,
+                    mask,
+                    region: false,
+                });
+            }
+            for key in keys {
+                let id = self
+                    .pipelines
+                    .specialize(pipeline_cache, &self.composite, key);
+                self.pipeline_ids.insert(key, id);
+            }
+        }
+    }
+
+    /// Draws the recorded layers into `target`, which is cleared with `clear`.
+    pub(crate) fn render(
+        &self,
+        ctx: &mut LayerRenderContext,
+        encoder: &mut CommandEncoder,
+        target: LayerTarget,
+        clear: wgpu::Color,
+    ) {
+        self.render_layer(ctx, encoder, 0, target, LoadOp::Clear(clear));
+    }
+
+    fn render_layer(
+        &self,
+        ctx: &mut LayerRenderContext,
+        encoder: &mut CommandEncoder,
+        index: usize,
+        target: LayerTarget,
+        load: LoadOp<wgpu::Color>,
+    ) {
+        let (device, pipeline_cache, text_renderer) =
+            (ctx.device, ctx.pipeline_cache, ctx.text_renderer);
+        let items = &self.stack.layers()[index].items;
+        // the first pass into the target clears it
+        let mut load = Some(load);
+        let mut i = 0;
+        while i < items.len() {
+            if let LayerItem::Layer(child) = items[i] {
+                i += 1;
+                let layer = &self.stack.layers()[child];
+                let Some(scissor) = scissor_rect(layer.clip, ctx.viewport) else {
+                    continue;
+                };
+                let backdrop = layer.blend_mode.shader_blend_mode().and(target.texture);
+                let blend_mode = match (layer.blend_mode.shader_blend_mode(), backdrop) {
+                    (Some(_), None) => BlendMode::SourceOver,
+                    _ => layer.blend_mode,
+                };
+                let key = CompositePipelineKey {
+                    blend_mode,
+                    mask: layer.mask.map(|mask| mask.mode),
+                    region: false,
+                };
+                let pipeline = self
+                    .pipeline_ids
+                    .get(&key)
+                    .and_then(|id| pipeline_cache.get_render_pipeline(*id));
+                let (Some(pipeline), Some(opacities), false) =
+                    (pipeline, self.opacities.buffer(), layer.is_invisible())
+                else {
+                    continue;
+                };
+
+                let texture = ctx.texture_pool.get(
+                    device,
+                    target_descriptor(
+                        "layer",
+                        self.composite.format,
+                        ctx.viewport,
+                        TextureUsages::RENDER_ATTACHMENT
+                            | TextureUsages::TEXTURE_BINDING
+                            | TextureUsages::COPY_SRC,
+                    ),
+                );
+                self.render_layer(
+                    ctx,
+                    encoder,
+                    child,
+                    LayerTarget {
+                        view: &texture.default_view,
+                        texture: Some(&texture.texture),
+                    },
+                    LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                );
+                let texture = self.filters.apply(ctx, encoder, child, texture);
+                let mask = layer.mask.map(|mask| {
+                    let mask_texture = ctx.texture_pool.get(
+                        device,
+                        target_descriptor(
+                            "layer_mask",
+                            self.composite.format,
+                            ctx.viewport,
+                            TextureUsages::RENDER_ATTACHMENT
+                                | TextureUsages::TEXTURE_BINDING
+                                | TextureUsages::COPY_SRC,
+                        ),
+                    );
+                    self.render_layer(
+                        ctx,
+                        encoder,
+                        mask.layer,
+                        LayerTarget {
+                            view: &mask_texture.default_view,
+                            texture: Some(&mask_texture.texture),
+                        },
+                        LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    );
+                    let bind_group = device.create_bind_group(
+                        "layer_composite_mask_bind_group",
+                        &self.composite.mask_layout,
+                        &BindGroupEntries::single(&mask_texture.default_view),
+                    );
+                    (mask_texture, bind_group)
+                });
+
+                let [x, y, width, height] = scissor;
+                let (bind_group, backdrop) = match backdrop {
+                    Some(target_texture) => {
+                        // the shader reads the target below the layer from a copy
+                        let backdrop = ctx.texture_pool.get(
+                            device,
+                            target_descriptor(
+                                "layer_backdrop",
+                                self.composite.format,
+                                ctx.viewport,
+                                TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                            ),
+                        );
+                        if let Some(load) = load.take() {
+                            begin_pass(encoder, target.view, Some(load));
+                        }
+                        let origin = wgpu::Origin3d { x, y, z: 0 };
+                        encoder.copy_texture_to_texture(
+                            wgpu::ImageCopyTexture {
+                                origin,
+                                ..target_texture.as_image_copy()
+                            },
+                            wgpu::ImageCopyTexture {
+                                origin,
+                                ..backdrop.texture.as_image_copy()
+                            },
+                            wgpu::Extent3d {
+                                width,
+                                height,
+                                depth_or_array_layers: 1,
+                            },
+                        );
+                        let bind_group = device.create_bind_group(
+                            "layer_composite_backdrop_bind_group",
+                            &self.composite.backdrop_layout,
+                            &BindGroupEntries::sequential((
+                                &texture.default_view,
+                                &backdrop.default_view,
+                            )),
+                        );
+                        (bind_group, Some(backdrop))
+                    }
+                    None => {
+                        let bind_group = device.create_bind_group(
+                            "layer_composite_bind_group",
+                            &self.composite.layout,
+                            &BindGroupEntries::single(&texture.default_view),
+                        );
+                        (bind_group, None)
+                    }
+                };
+                {
+                    let mut pass = begin_pass(encoder, target.view, load.take());
+                    pass.set_scissor_rect(x, y, width, height);
+                    pass.set_pipeline(pipeline);
+                    pass.set_bind_group(0, &bind_group, &[]);
+                    if let Some((_, mask_bind_group)) = &mask {
+                        pass.set_bind_group(1, mask_bind_group, &[]);
+                    }
+                    pass.set_vertex_buffer(0, *opacities.slice(..));
+                    pass.draw(0..3, child as u32..child as u32 + 1);
+                }
+                // the composite is recorded, later layers may draw into the textures
+                ctx.texture_pool.release(&texture);
+                if let Some(backdrop) = backdrop {
+                    ctx.texture_pool.release(&backdrop);
+                }
+                if let Some((mask_texture, _)) = mask {
+                    ctx.texture_pool.release(&mask_texture);
+                }
+            } else if let LayerItem::Paths { range } = &items[i] {
+                i += 1;
+                self.render_paths(ctx, encoder, range.clone(), target.view, &mut load);
+            } else {
+                // draw the glyphs up to the next layer or paths in one pass
+                let mut pass = begin_pass(encoder, target.view, load.take());
+                while let Some(LayerItem::Glyphs { range, clip }) = items.get(i) {
+                    i += 1;
+                    let Some([x, y, width, height]) = scissor_rect(*clip, ctx.viewport) else {
+                        continue;
+                    };
+                    pass.set_scissor_rect(x, y, width, height);
+                    text_renderer.render(&mut pass, pipeline_cache, range.clone());
+                }
+            }
+        }
+
+        if let Some(load) = load {
+            // nothing was drawn, but the target is still cleared
+            begin_pass(encoder, target.view, Some(load));
+        }
+    }
+
+    /// Rasterizes a run of paths and composites it over `target`.
+    fn render_paths(
+        &self,
+        ctx: &mut LayerRenderContext,
+        encoder: &mut CommandEncoder,
+        paths: Range<u32>,
+        target: &TextureView,
+        load: &mut Option<LoadOp<wgpu::Color>>,
+    ) {
+        let pipeline = self
+            .pipeline_ids
+            .get(&PATHS_KEY)
+            .and_then(|id| ctx.pipeline_cache.get_render_pipeline(*id));
+        let (Some(rasterizer), Some(pipeline), Some(opacities)) =
+            (ctx.rasterizer, pipeline, self.opacities.buffer())
+        else {
+            return;
+        };
+        let Some((texture, [x, y])) = rasterizer.render(ctx, encoder, paths) else {
+            return;
+        };
+        let bind_group = ctx.device.create_bind_group(
+            "layer_composite_bind_group",
+            &self.composite.layout,
+            &BindGroupEntries::single(&texture.default_view),
+        );
+        {
+            let mut pass = begin_pass(encoder, target, load.take());
+            // the texture only covers the region the paths draw into
+            let size = texture.texture.size();
+            pass.set_viewport(
+                x as f32,
+                y as f32,
+                size.width as f32,
+                size.height as f32,
+                0.0,
+                1.0,
+            );
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.set_vertex_buffer(0, *opacities.slice(..));
+            // the root layer is always opaque
+            pass.draw(0..3, 0..1);
+        }
+        ctx.texture_pool.release(&texture);
+    }
+}
+
+/// Describes a texture for drawing layers into.
+pub(super) fn target_descriptor(
+    label: &'static str,
+    format: TextureFormat,
+    size: [u32; 2],
+    usage: TextureUsages,
+) -> wgpu::TextureDescriptor<'static> {
+    wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        view_formats: &[],
+    }
+}
+
+fn begin_pass<'a>(
+    encoder: &'a mut CommandEncoder,
+    target: &'a TextureView,
+    load: Option<LoadOp<wgpu::Color>>,
+) -> RenderPass<'a> {
+    encoder.begin_render_pass(&RenderPassDescriptor {
+        label: Some("layer_pass"),
+        color_attachments: &[Some(RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: Operations {
+                load: load.unwrap_or(LoadOp::Load),
+                store: StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: None,
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_resource::validate_shader;
+    use wgpu::Features;
+
+    const MODES: [BlendMode; 22] = [
+        BlendMode::Clear,
+        BlendMode::Copy,
+        BlendMode::SourceOver,
+        BlendMode::DestinationOver,
+        BlendMode::SourceIn,
+        BlendMode::DestinationIn,
+        BlendMode::SourceOut,
+        BlendMode::DestinationOut,
+        BlendMode::SourceAtop,
+        BlendMode::DestinationAtop,
+        BlendMode::Xor,
+        BlendMode::Plus,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Darken,
+        BlendMode::Lighten,
+        BlendMode::ColorDodge,
+        BlendMode::Hue,
+        BlendMode::Saturation,
+        BlendMode::Color,
+        BlendMode::Luminosity,
+    ];
+
+    #[test]
+    fn composite_shader_validates() {
+        validate_shader(&[], &composite_shader(), &[], Features::empty()).unwrap();
+        for mode in MODES.iter().filter_map(|mode| mode.shader_blend_mode()) {
+            let defs = [ShaderDefVal::UInt("BLEND_MODE".into(), mode)];
+            validate_shader(&[], &composite_shader(), &defs, Features::empty()).unwrap();
+        }
+        for mask in [MaskMode::Alpha, MaskMode::Luminance] {
+            let defs = [
+                ShaderDefVal::UInt("BLEND_MODE".into(), 1),
+                ShaderDefVal::UInt("MASK".into(), mask.shader_mask_mode()),
+            ];
+            validate_shader(&[], &composite_shader(), &defs, Features::empty()).unwrap();
+            validate_shader(&[], &composite_shader(), &defs[1..], Features::empty()).unwrap();
+        }
+        let defs = ["REGION".into()];
+        validate_shader(&[], &composite_shader(), &defs, Features::empty()).unwrap();
+    }
+
+    #[test]
+    fn blend_modes_have_one_implementation() {
+        for mode in MODES {
+            assert_ne!(
+                mode.blend_state().is_some(),
+                mode.shader_blend_mode().is_some(),
+                "{mode:?}"
+            );
+        }
+        assert_eq!(
+            BlendMode::SourceOver.blend_state(),
+            Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING)
+        );
+    }
+}
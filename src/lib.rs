@@ -3,19 +3,34 @@
 pub use ahash::{AHasher, RandomState};
 pub use hashbrown;
 use hashbrown::hash_map::RawEntryMut;
+pub use layer::{BlendMode, ColorMatrix, Filter, MaskMode};
+pub use raster::PathRendering;
+pub use render_resource::{
+    builtin_shaders, texture_size, Buffer, CachedTexture, PipelineCache, PipelineCacheStats,
+    RenderDevice, Sampler, Shader, ShaderDefVal, ShaderError, ShaderErrorSpan, ShaderImport,
+    ShaderLoader, ShaderLoaderError, Source, Texture, TexturePool, TexturePoolStats, TextureView,
+    TransientTextureRequest,
+};
 use std::{
     fmt::Debug,
     hash::{BuildHasher, BuildHasherDefault, Hash, Hasher},
     marker::PhantomData,
     ops::Deref,
 };
+pub use swash::text::Script;
+pub use text::{
+    FontFallback, LayoutCacheStats, PathTextOptions, PathTextOverflow, TextAntialiasing,
+    TextRendering,
+};
 
 use context::{WgpuImage, WgpuRenderContext};
 use text::{WgpuText, WgpuTextLayout, WgpuTextLayoutBuilder};
 
 mod context;
+mod layer;
 mod mesh;
-// mod render_graph;
+mod raster;
+pub mod render_graph;
 mod render_resource;
 pub mod renderer;
 mod text;
@@ -0,0 +1,335 @@
+use std::ops::Range;
+
+use piet::{
+    kurbo::{
+        self, Affine, BezPath, Cap, Join, Line, ParamCurve, PathEl, Point, Rect, Shape, Stroke,
+    },
+    LineCap, LineJoin, StrokeStyle,
+};
+
+mod render;
+
+pub(crate) use render::PathRasterizer;
+
+/// The width and height of the tiles paths are binned into, in pixels.
+pub(crate) const TILE_SIZE: u32 = 16;
+
+/// The largest distance between a curve and the lines it is flattened into,
+/// in pixels.
+pub(crate) const TOLERANCE: f64 = 0.25;
+
+/// How filled and stroked paths are drawn, see
+/// [`WgpuRenderer::set_path_rendering`](crate::renderer::WgpuRenderer::set_path_rendering).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PathRendering {
+    /// Paths are tessellated into triangles, which works on every device.
+    ///
+    /// The tessellator doesn't draw paths yet.
+    #[default]
+    Tessellation,
+    /// Paths are flattened into lines and binned into tiles on the CPU, then
+    /// compute shaders find the coverage of each pixel analytically. This
+    /// scales better to many complex paths, but needs compute shaders, which
+    /// WebGL2 lacks.
+    Compute,
+}
+
+/// Which areas enclosed by a path are inside of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub(crate) enum FillRule {
+    #[default]
+    NonZero,
+    EvenOdd,
+}
+
+/// Flattens `path`, transformed into device space, into lines appended to
+/// `lines`. Every subpath is closed, as they are filled.
+pub(crate) fn flatten(
+    path: impl IntoIterator<Item = PathEl>,
+    transform: Affine,
+    lines: &mut Vec<Line>,
+) {
+    let mut start = Point::ZERO;
+    let mut last = Point::ZERO;
+    let close = |lines: &mut Vec<Line>, start: Point, last: Point| {
+        if last != start {
+            lines.push(Line::new(last, start));
+        }
+    };
+    kurbo::flatten(
+        path.into_iter().map(|el| transform * el),
+        TOLERANCE,
+        |el| match el {
+            PathEl::MoveTo(p) => {
+                close(lines, start, last);
+                (start, last) = (p, p);
+            }
+            PathEl::LineTo(p) => {
+                lines.push(Line::new(last, p));
+                last = p;
+            }
+            PathEl::ClosePath => {
+                close(lines, start, last);
+                last = start;
+            }
+            // flattening only emits lines
+            PathEl::QuadTo(..) | PathEl::CurveTo(..) => {}
+        },
+    );
+    close(lines, start, last);
+}
+
+/// The outline of the stroke of `shape`, which is filled with the non-zero rule.
+/// `tolerance` is in the units of the shape.
+pub(crate) fn stroke_outline(
+    shape: impl Shape,
+    width: f64,
+    style: &StrokeStyle,
+    tolerance: f64,
+) -> BezPath {
+    let mut stroke = Stroke::new(width).with_caps(match style.line_cap {
+        LineCap::Butt => Cap::Butt,
+        LineCap::Round => Cap::Round,
+        LineCap::Square => Cap::Square,
+    });
+    stroke = match style.line_join {
+        LineJoin::Miter { limit } => stroke.with_join(Join::Miter).with_miter_limit(limit),
+        LineJoin::Round => stroke.with_join(Join::Round),
+        LineJoin::Bevel => stroke.with_join(Join::Bevel),
+    };
+    if !style.dash_pattern.is_empty() {
+        stroke = stroke.with_dashes(style.dash_offset, style.dash_pattern.iter().copied());
+    }
+    kurbo::stroke(
+        shape.path_elements(tolerance),
+        &stroke,
+        &Default::default(),
+        tolerance,
+    )
+}
+
+/// A tile covered by a path.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct PathTile {
+    /// The index of the tile, by rows.
+    pub(crate) tile: u32,
+    /// The parts of the lines of the path within the tile.
+    pub(crate) segments: Vec<Line>,
+    /// The coverage of each row of pixels of the tile by the lines to the left
+    /// of the tile, as a signed winding number.
+    pub(crate) backdrop: [f32; TILE_SIZE as usize],
+}
+
+/// Bins the `lines` of a path into the tiles of a grid of `tiles` columns and
+/// rows, within `bounds`. Tiles the path doesn't touch are left out.
+///
+/// Lines going down wind positively. The coverage of a pixel is the sum of
+/// the backdrop of its row and of the area right of the segments in the row,
+/// within the pixel.
+pub(crate) fn bin_path(lines: &[Line], bounds: Rect, tiles: [u32; 2]) -> Vec<PathTile> {
+    let size = TILE_SIZE as f64;
+    let Some(path_bounds) = lines
+        .iter()
+        .map(|line| line.bounding_box())
+        .reduce(|a, b| a.union(b))
+    else {
+        return Vec::new();
+    };
+    // the subpaths are closed, so their windings cancel out right of the path
+    let area = path_bounds.intersect(bounds).intersect(Rect::new(
+        0.0,
+        0.0,
+        tiles[0] as f64 * size,
+        tiles[1] as f64 * size,
+    ));
+    if area.width() <= 0.0 || area.height() <= 0.0 {
+        return Vec::new();
+    }
+    let columns = (area.x0 / size).floor() as u32..(area.x1 / size).ceil() as u32;
+    let rows = (area.y0 / size).floor() as u32..(area.y1 / size).ceil() as u32;
+    let first_x = columns.start as f64 * size;
+    // the tiles of `range` from `min` to `max`, inclusive
+    let tile_range = |min: f64, max: f64, range: &Range<u32>| {
+        let start = (min / size).floor().max(range.start as f64) as u32;
+        let end = ((max / size).floor() + 1.0).min(range.end as f64) as u32;
+        start..end.max(start)
+    };
+
+    // the segments of each tile by rows, and the coverage added by the pieces
+    // in each tile, for the tiles after it in its row, after the coverage of
+    // the pieces left of the area
+    let stride = columns.len() + 1;
+    let mut segments = vec![Vec::new(); rows.len() * columns.len()];
+    let mut cover = vec![[0.0f32; TILE_SIZE as usize]; rows.len() * stride];
+    // each line is only clipped against the tiles of its bounds
+    for line in lines {
+        let (top, bottom) = (line.p0.y.min(line.p1.y), line.p0.y.max(line.p1.y));
+        for row in tile_range(top, bottom, &rows) {
+            let (y0, y1) = (row as f64 * size, (row + 1) as f64 * size);
+            let Some(piece) = clip_y(*line, y0, y1) else {
+                continue;
+            };
+            let row = (row - rows.start) as usize;
+            let (left, right) = (piece.p0.x.min(piece.p1.x), piece.p0.x.max(piece.p1.x));
+            if left < first_x {
+                if let Some(left) = clip_x(piece, f64::NEG_INFINITY, first_x) {
+                    add_cover(&mut cover[row * stride], left, y0);
+                }
+            }
+            for column in tile_range(left, right, &columns) {
+                let x0 = column as f64 * size;
+                if let Some(segment) = clip_x(piece, x0, x0 + size) {
+                    let i = (column - columns.start) as usize;
+                    segments[row * columns.len() + i].push(segment);
+                    add_cover(&mut cover[row * stride + i + 1], segment, y0);
+                }
+            }
+        }
+    }
+
+    let mut path_tiles = Vec::new();
+    for (i, row) in rows.enumerate() {
+        let mut backdrop = [0.0f32; TILE_SIZE as usize];
+        for (j, column) in columns.clone().enumerate() {
+            for (backdrop, cover) in backdrop.iter_mut().zip(cover[i * stride + j]) {
+                *backdrop += cover;
+            }
+            let segments = std::mem::take(&mut segments[i * columns.len() + j]);
+            if segments.is_empty() && backdrop.iter().all(|cover| *cover == 0.0) {
+                continue;
+            }
+            path_tiles.push(PathTile {
+                tile: row * tiles[0] + column,
+                segments,
+                backdrop,
+            });
+        }
+    }
+    path_tiles
+}
+
+/// The part of `line` between the heights `y0` and `y1`, keeping its
+/// direction. Horizontal lines cover nothing and are left out.
+fn clip_y(line: Line, y0: f64, y1: f64) -> Option<Line> {
+    let dy = line.p1.y - line.p0.y;
+    if dy == 0.0 {
+        return None;
+    }
+    let t0 = ((y0 - line.p0.y) / dy).clamp(0.0, 1.0);
+    let t1 = ((y1 - line.p0.y) / dy).clamp(0.0, 1.0);
+    let (t0, t1) = (t0.min(t1), t0.max(t1));
+    (t0 < t1).then(|| Line::new(line.eval(t0), line.eval(t1)))
+}
+
+/// The part of `line` between `x0` and `x1`, keeping its direction.
+fn clip_x(line: Line, x0: f64, x1: f64) -> Option<Line> {
+    let dx = line.p1.x - line.p0.x;
+    if dx == 0.0 {
+        return (x0 <= line.p0.x && line.p0.x < x1).then_some(line);
+    }
+    let t0 = ((x0 - line.p0.x) / dx).clamp(0.0, 1.0);
+    let t1 = ((x1 - line.p0.x) / dx).clamp(0.0, 1.0);
+    let (t0, t1) = (t0.min(t1), t0.max(t1));
+    (t0 < t1).then(|| Line::new(line.eval(t0), line.eval(t1)))
+}
+
+/// Adds the signed height of `line` within each row of pixels below `y0`.
+fn add_cover(cover: &mut [f32; TILE_SIZE as usize], line: Line, y0: f64) {
+    let sign = if line.p1.y > line.p0.y { 1.0 } else { -1.0 };
+    let (top, bottom) = (line.p0.y.min(line.p1.y) - y0, line.p0.y.max(line.p1.y) - y0);
+    for (row, cover) in cover.iter_mut().enumerate() {
+        let row = row as f64;
+        let height = bottom.min(row + 1.0) - top.max(row);
+        if height > 0.0 {
+            *cover += (sign * height) as f32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattens_closed_subpaths() {
+        let mut lines = Vec::new();
+        let rect = Rect::new(0.0, 0.0, 2.0, 1.0);
+        flatten(rect.path_elements(0.1), Affine::scale(2.0), &mut lines);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], Line::new((0.0, 0.0), (4.0, 0.0)));
+        assert_eq!(lines.last().unwrap().p1, Point::ZERO);
+
+        // an open subpath is closed back to its start
+        lines.clear();
+        let open = [
+            PathEl::MoveTo((0.0, 0.0).into()),
+            PathEl::LineTo((1.0, 0.0).into()),
+            PathEl::LineTo((1.0, 1.0).into()),
+        ];
+        flatten(open, Affine::IDENTITY, &mut lines);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], Line::new((1.0, 1.0), (0.0, 0.0)));
+    }
+
+    #[test]
+    fn bins_into_tiles_with_backdrops() {
+        let mut lines = Vec::new();
+        let rect = Rect::new(4.0, 4.0, 40.0, 12.0);
+        flatten(rect.path_elements(0.1), Affine::IDENTITY, &mut lines);
+        let bounds = Rect::new(0.0, 0.0, 64.0, 64.0);
+        let tiles = bin_path(&lines, bounds, [4, 4]);
+
+        // the rectangle spans the first row, up to the third column
+        assert_eq!(
+            tiles.iter().map(|tile| tile.tile).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        // the left edge goes up, the right edge down
+        assert_eq!(tiles[0].segments, [Line::new((4.0, 12.0), (4.0, 4.0))]);
+        assert!(tiles[1].segments.is_empty());
+        assert_eq!(tiles[2].segments, [Line::new((40.0, 4.0), (40.0, 12.0))]);
+        // the tile inside the rectangle is covered from the left in rows 4..12
+        let mut inside = [0.0; 16];
+        inside[4..12].fill(-1.0);
+        assert_eq!(tiles[1].backdrop, inside);
+        assert_eq!(tiles[2].backdrop, inside);
+    }
+
+    #[test]
+    fn clips_to_bounds() {
+        let mut lines = Vec::new();
+        let rect = Rect::new(-20.0, 0.0, 8.0, 16.0);
+        flatten(rect.path_elements(0.1), Affine::IDENTITY, &mut lines);
+        let tiles = bin_path(&lines, Rect::new(0.0, 0.0, 16.0, 16.0), [1, 1]);
+        assert_eq!(tiles.len(), 1);
+        // the left edge is outside of the grid, so it's part of the backdrop
+        assert_eq!(tiles[0].backdrop, [-1.0; 16]);
+        assert_eq!(tiles[0].segments, [Line::new((8.0, 0.0), (8.0, 16.0))]);
+    }
+
+    #[test]
+    fn bins_lines_into_their_own_tiles() {
+        let mut lines = Vec::new();
+        let triangle = BezPath::from_vec(vec![
+            PathEl::MoveTo((3.0, 5.0).into()),
+            PathEl::LineTo((61.0, 27.0).into()),
+            PathEl::LineTo((17.0, 60.0).into()),
+            PathEl::ClosePath,
+        ]);
+        flatten(triangle, Affine::IDENTITY, &mut lines);
+        let tiles = bin_path(&lines, Rect::new(0.0, 0.0, 64.0, 64.0), [4, 4]);
+
+        let size = TILE_SIZE as f64;
+        let mut length = 0.0;
+        for tile in &tiles {
+            let (x, y) = ((tile.tile % 4) as f64 * size, (tile.tile / 4) as f64 * size);
+            let rect = Rect::new(x, y, x + size, y + size).inflate(1e-9, 1e-9);
+            for segment in &tile.segments {
+                assert!(rect.contains(segment.p0) && rect.contains(segment.p1));
+                length += segment.length();
+            }
+        }
+        let total: f64 = lines.iter().map(|line| line.length()).sum();
+        assert!((length - total).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,107 @@
+// Rasterizes paths binned into tiles, computing the coverage of each pixel
+// analytically.
+//
+// A workgroup draws one tile, and each invocation one of its pixels. The
+// coverage of a pixel by a path is the backdrop of its row, the winding of the
+// lines left of the tile, plus the area of the pixel right of each segment of
+// the tile, weighted by the height of the segment within the row. Lines going
+// down wind positively.
+
+const TILE_SIZE: u32 = 16u;
+const EVEN_ODD: u32 = 1u;
+
+struct Segment {
+    p0: vec2<f32>,
+    p1: vec2<f32>,
+}
+
+struct Command {
+    // premultiplied linear color
+    color: vec4<f32>,
+    // the clip rectangle, as (x0, y0, x1, y1)
+    clip: vec4<f32>,
+    segments: u32,
+    segment_count: u32,
+    backdrop: u32,
+    fill_rule: u32,
+}
+
+@group(0) @binding(0) var<storage> segments: array<Segment>;
+@group(0) @binding(1) var<storage> commands: array<Command>;
+@group(0) @binding(2) var<storage> backdrops: array<f32>;
+// the first command and the number of commands of each tile
+@group(0) @binding(3) var<storage> tiles: array<vec2<u32>>;
+
+@group(1) @binding(0) var output: texture_storage_2d<rgba16float, write>;
+
+@compute @workgroup_size(16, 16)
+fn rasterize(
+    @builtin(workgroup_id) tile_id: vec3<u32>,
+    @builtin(num_workgroups) tile_count: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let tile = tiles[tile_id.y * tile_count.x + tile_id.x];
+    let pixel = tile_id.xy * TILE_SIZE + local_id.xy;
+    let position = vec2<f32>(pixel);
+
+    var color = vec4<f32>(0.0);
+    for (var i = tile.x; i < tile.x + tile.y; i++) {
+        let command = commands[i];
+        var area = backdrops[command.backdrop + local_id.y];
+        for (var s = command.segments; s < command.segments + command.segment_count; s++) {
+            area += segment_area(segments[s], position);
+        }
+
+        var coverage: f32;
+        if command.fill_rule == EVEN_ODD {
+            coverage = abs(area - 2.0 * round(0.5 * area));
+        } else {
+            coverage = min(abs(area), 1.0);
+        }
+        coverage *= clip_area(command.clip, position);
+        color = command.color * coverage + color * (1.0 - command.color.a * coverage);
+    }
+
+    if all(pixel < textureDimensions(output)) {
+        textureStore(output, pixel, color);
+    }
+}
+
+// The area of the pixel at `position` right of the part of the segment within
+// the row of the pixel, times the signed height of that part.
+fn segment_area(segment: Segment, position: vec2<f32>) -> f32 {
+    let dy = segment.p1.y - segment.p0.y;
+    if dy == 0.0 {
+        return 0.0;
+    }
+    let t0 = clamp((position.y - segment.p0.y) / dy, 0.0, 1.0);
+    let t1 = clamp((position.y + 1.0 - segment.p0.y) / dy, 0.0, 1.0);
+    let a = mix(segment.p0, segment.p1, min(t0, t1));
+    let b = mix(segment.p0, segment.p1, max(t0, t1));
+
+    // split where the part enters and leaves the column of the pixel, so that
+    // each piece is left of, within or right of the pixel
+    let dx = b.x - a.x;
+    var u0 = 0.0;
+    var u1 = 1.0;
+    if dx != 0.0 {
+        let enter = clamp((position.x - a.x) / dx, 0.0, 1.0);
+        let leave = clamp((position.x + 1.0 - a.x) / dx, 0.0, 1.0);
+        u0 = min(enter, leave);
+        u1 = max(enter, leave);
+    }
+    return piece_area(a, b, 0.0, u0, position.x)
+        + piece_area(a, b, u0, u1, position.x)
+        + piece_area(a, b, u1, 1.0, position.x);
+}
+
+fn piece_area(a: vec2<f32>, b: vec2<f32>, u0: f32, u1: f32, x: f32) -> f32 {
+    // the pixel area right of a straight piece is linear in its position
+    let middle = mix(a.x, b.x, 0.5 * (u0 + u1));
+    return (b.y - a.y) * (u1 - u0) * clamp(x + 1.0 - middle, 0.0, 1.0);
+}
+
+fn clip_area(clip: vec4<f32>, position: vec2<f32>) -> f32 {
+    let size = max(min(clip.zw, position + 1.0) - max(clip.xy, position), vec2<f32>(0.0));
+    return size.x * size.y;
+}
@@ -0,0 +1,582 @@
+use bytemuck::{Pod, Zeroable};
+use piet::{
+    kurbo::{Affine, Line, PathEl, Rect, Shape, Vec2},
+    Color,
+};
+use std::ops::Range;
+use wgpu::{
+    BindGroupLayoutEntry, BindingType, BufferBindingType, BufferUsages, CommandEncoder,
+    ComputePassDescriptor, ShaderStages, StorageTextureAccess, TextureFormat, TextureUsages,
+    TextureViewDimension,
+};
+
+use super::{bin_path, flatten, FillRule, TILE_SIZE};
+use crate::{
+    layer::{Layer, LayerItem, LayerRenderContext},
+    render_resource::{
+        BindGroup, BindGroupEntries, BindGroupLayout, BufferVec, CachedComputePipelineId,
+        CachedTexture, ComputePipelineDescriptor, PipelineCache, RenderDevice, RenderQueue, Shader,
+        SpecializedComputePipeline, SpecializedComputePipelines,
+    },
+    text::linear_premultiplied,
+    HashMap,
+};
+
+/// The format paths are rasterized into, before they are composited.
+const OUTPUT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// A path recorded by [`PathRasterizer::push`].
+struct PathDraw {
+    /// The lines of the path, in device pixels.
+    lines: Range<usize>,
+    /// Premultiplied linear color.
+    color: [f32; 4],
+    fill_rule: FillRule,
+    /// The clip rectangle in device pixels.
+    clip: Option<Rect>,
+}
+
+/// A run of paths binned by [`PathRasterizer::prepare`].
+struct PathBatch {
+    bind_group: BindGroup,
+    /// The pixels the run is rasterized into, as `[x, y, width, height]` in
+    /// the viewport, starting at a tile.
+    region: [u32; 4],
+}
+
+/// The buffers a run of paths is binned into, kept for the run at the same
+/// position in the next frames.
+struct BatchBuffers {
+    segments: BufferVec<GpuSegment>,
+    commands: BufferVec<GpuCommand>,
+    backdrops: BufferVec<f32>,
+    tile_ranges: BufferVec<[u32; 2]>,
+    /// Created again when one of the buffers is.
+    bind_group: Option<BindGroup>,
+}
+
+impl BatchBuffers {
+    fn new() -> Self {
+        fn storage<T: Pod>(label: &str) -> BufferVec<T> {
+            let mut buffer = BufferVec::new(BufferUsages::STORAGE);
+            buffer.set_label(Some(label));
+            buffer
+        }
+        Self {
+            segments: storage("path_raster_segments"),
+            commands: storage("path_raster_commands"),
+            backdrops: storage("path_raster_backdrops"),
+            tile_ranges: storage("path_raster_tiles"),
+            bind_group: None,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.segments.clear();
+        self.commands.clear();
+        self.backdrops.clear();
+        self.tile_ranges.clear();
+    }
+
+    /// Uploads the buffers, and returns the bind group of the run.
+    fn write(
+        &mut self,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        layout: &BindGroupLayout,
+    ) -> BindGroup {
+        let capacities = self.capacities();
+        self.segments.write_buffer(device, queue);
+        self.commands.write_buffer(device, queue);
+        self.backdrops.write_buffer(device, queue);
+        self.tile_ranges.write_buffer(device, queue);
+        if self.capacities() != capacities {
+            self.bind_group = None;
+        }
+        self.bind_group
+            .get_or_insert_with(|| {
+                // the buffers were written, as they aren't empty
+                device.create_bind_group(
+                    "path_raster_bind_group",
+                    layout,
+                    &BindGroupEntries::sequential((
+                        self.segments.buffer().unwrap().as_entire_buffer_binding(),
+                        self.commands.buffer().unwrap().as_entire_buffer_binding(),
+                        self.backdrops.buffer().unwrap().as_entire_buffer_binding(),
+                        self.tile_ranges
+                            .buffer()
+                            .unwrap()
+                            .as_entire_buffer_binding(),
+                    )),
+                )
+            })
+            .clone()
+    }
+
+    fn capacities(&self) -> [usize; 4] {
+        [
+            self.segments.capacity(),
+            self.commands.capacity(),
+            self.backdrops.capacity(),
+            self.tile_ranges.capacity(),
+        ]
+    }
+}
+
+/// Returns the region of the viewport the `paths` draw into, as
+/// `[x, y, width, height]` with `x` and `y` at the start of a tile, or `None`
+/// if they are outside of it.
+fn path_region(lines: &[Line], paths: &[PathDraw], viewport: [u32; 2]) -> Option<[u32; 4]> {
+    let viewport_rect = Rect::new(0.0, 0.0, viewport[0] as f64, viewport[1] as f64);
+    let bounds = paths
+        .iter()
+        .filter_map(|path| {
+            let bounds = lines[path.lines.clone()]
+                .iter()
+                .map(|line| line.bounding_box())
+                .reduce(|a, b| a.union(b))?;
+            let bounds = bounds
+                .intersect(path.clip.unwrap_or(viewport_rect))
+                .intersect(viewport_rect);
+            (bounds.width() > 0.0 && bounds.height() > 0.0).then_some(bounds)
+        })
+        .reduce(|a, b| a.union(b))?;
+    let size = TILE_SIZE as f64;
+    let x = (bounds.x0 / size).floor() as u32 * TILE_SIZE;
+    let y = (bounds.y0 / size).floor() as u32 * TILE_SIZE;
+    let x1 = ((bounds.x1 / size).ceil() as u32 * TILE_SIZE).min(viewport[0]);
+    let y1 = ((bounds.y1 / size).ceil() as u32 * TILE_SIZE).min(viewport[1]);
+    Some([x, y, x1 - x, y1 - y])
+}
+
+/// A line within a tile, as read by `rasterize.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct GpuSegment {
+    p0: [f32; 2],
+    p1: [f32; 2],
+}
+
+impl From<&Line> for GpuSegment {
+    fn from(line: &Line) -> Self {
+        Self {
+            p0: [line.p0.x as f32, line.p0.y as f32],
+            p1: [line.p1.x as f32, line.p1.y as f32],
+        }
+    }
+}
+
+/// A path to draw into a tile, as read by `rasterize.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct GpuCommand {
+    color: [f32; 4],
+    clip: [f32; 4],
+    segments: u32,
+    segment_count: u32,
+    /// The index of the first of the backdrops of the rows of the tile.
+    backdrop: u32,
+    fill_rule: u32,
+}
+
+fn rasterize_shader() -> Shader {
+    Shader::from_wgsl(
+        include_str!("rasterize.wgsl"),
+        "piet_wgpu/raster/rasterize.wgsl",
+    )
+}
+
+struct RasterPipeline {
+    layout: BindGroupLayout,
+    output_layout: BindGroupLayout,
+    shader: Shader,
+}
+
+impl SpecializedComputePipeline for RasterPipeline {
+    type Key = ();
+
+    fn specialize(&self, _key: Self::Key) -> ComputePipelineDescriptor {
+        ComputePipelineDescriptor {
+            label: Some("path_raster_pipeline".into()),
+            layout: vec![self.layout.clone(), self.output_layout.clone()],
+            push_constant_ranges: Vec::new(),
+            shader: self.shader.clone(),
+            shader_defs: Vec::new(),
+            entry_point: "rasterize".into(),
+        }
+    }
+}
+
+/// Draws filled paths with compute shaders, for
+/// [`PathRendering::Compute`](super::PathRendering::Compute).
+///
+/// Paths are flattened when they are recorded. Each run of paths drawn into a
+/// layer is binned into the tiles of the region it covers by
+/// [`prepare`](Self::prepare), then rasterized into a texture of that region
+/// by [`render`](Self::render), which the layer composites.
+pub(crate) struct PathRasterizer {
+    pipeline: RasterPipeline,
+    pipelines: SpecializedComputePipelines<RasterPipeline>,
+    /// Queued again by [`prepare`](Self::prepare) if it was evicted.
+    pipeline_id: CachedComputePipelineId,
+    lines: Vec<Line>,
+    paths: Vec<PathDraw>,
+    /// The tiles of each run of paths, by the index of its first path.
+    batches: HashMap<u32, PathBatch>,
+    /// The buffers of the runs, in the order they are prepared.
+    buffers: Vec<BatchBuffers>,
+}
+
+impl PathRasterizer {
+    pub(crate) fn new(device: &RenderDevice, pipeline_cache: &mut PipelineCache) -> Self {
+        let storage_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("path_raster_bind_group_layout"),
+            entries: &[
+                storage_entry(0),
+                storage_entry(1),
+                storage_entry(2),
+                storage_entry(3),
+            ],
+        });
+        let output_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("path_raster_output_bind_group_layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::COMPUTE,
+                ty: BindingType::StorageTexture {
+                    access: StorageTextureAccess::WriteOnly,
+                    format: OUTPUT_FORMAT,
+                    view_dimension: TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+        });
+
+        let shader = rasterize_shader();
+        pipeline_cache.set_shader(shader.id, &shader);
+        let pipeline = RasterPipeline {
+            layout,
+            output_layout,
+            shader,
+        };
+        let mut pipelines = SpecializedComputePipelines::default();
+        let pipeline_id = pipelines.specialize(pipeline_cache, &pipeline, ());
+
+        Self {
+            pipeline,
+            pipelines,
+            pipeline_id,
+            lines: Vec::new(),
+            paths: Vec::new(),
+            batches: HashMap::default(),
+            buffers: Vec::new(),
+        }
+    }
+
+    /// Forgets the recorded paths.
+    pub(crate) fn clear(&mut self) {
+        self.lines.clear();
+        self.paths.clear();
+    }
+
+    /// Records a path transformed by `transform` into device space, and returns
+    /// its index.
+    pub(crate) fn push(
+        &mut self,
+        path: impl IntoIterator<Item = PathEl>,
+        transform: Affine,
+        color: &Color,
+        fill_rule: FillRule,
+        clip: Option<Rect>,
+    ) -> u32 {
+        let start = self.lines.len();
+        flatten(path, transform, &mut self.lines);
+        self.paths.push(PathDraw {
+            lines: start..self.lines.len(),
+            color: linear_premultiplied(color),
+            fill_rule,
+            clip,
+        });
+        self.paths.len() as u32 - 1
+    }
+
+    /// Bins the runs of paths drawn into `layers` into tiles of the viewport,
+    /// and uploads them.
+    pub(crate) fn prepare(
+        &mut self,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        pipeline_cache: &PipelineCache,
+        layers: &[Layer],
+        viewport: [u32; 2],
+    ) {
+        self.pipeline_id = self
+            .pipelines
+            .specialize(pipeline_cache, &self.pipeline, ());
+        self.batches.clear();
+        let mut buffers = std::mem::take(&mut self.buffers);
+        let mut used = 0;
+        for layer in layers {
+            for item in &layer.items {
+                if let LayerItem::Paths { range } = item {
+                    // paths recorded by a previous rasterizer are gone
+                    if range.end as usize > self.paths.len() {
+                        continue;
+                    }
+                    if used == buffers.len() {
+                        buffers.push(BatchBuffers::new());
+                    }
+                    let batch = self.prepare_batch(
+                        device,
+                        queue,
+                        &mut buffers[used],
+                        range.clone(),
+                        viewport,
+                    );
+                    if let Some(batch) = batch {
+                        self.batches.insert(range.start, batch);
+                        used += 1;
+                    }
+                }
+            }
+        }
+        self.buffers = buffers;
+    }
+
+    /// Bins a run of paths into the tiles of the region it covers, in which
+    /// the paths are moved to start at the origin. Returns `None` if the paths
+    /// are outside of the viewport.
+    fn prepare_batch(
+        &self,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        buffers: &mut BatchBuffers,
+        paths: Range<u32>,
+        viewport: [u32; 2],
+    ) -> Option<PathBatch> {
+        let paths = &self.paths[paths.start as usize..paths.end as usize];
+        let region = path_region(&self.lines, paths, viewport)?;
+        let [x, y, width, height] = region;
+        let origin = Vec2::new(x as f64, y as f64);
+        let tiles = [width, height].map(|length| length.div_ceil(TILE_SIZE));
+        let region_rect = Rect::new(0.0, 0.0, width as f64, height as f64);
+
+        // the commands of each tile, in drawing order
+        let mut tile_commands = vec![Vec::new(); (tiles[0] * tiles[1]) as usize];
+        buffers.clear();
+        let BatchBuffers {
+            segments,
+            commands,
+            backdrops,
+            tile_ranges,
+            ..
+        } = buffers;
+        let mut lines = Vec::new();
+        for path in paths {
+            let clip = path
+                .clip
+                .map_or(region_rect, |clip| (clip - origin).intersect(region_rect));
+            lines.clear();
+            lines.extend(
+                self.lines[path.lines.clone()]
+                    .iter()
+                    .map(|line| Line::new(line.p0 - origin, line.p1 - origin)),
+            );
+            for tile in bin_path(&lines, clip, tiles) {
+                tile_commands[tile.tile as usize].push(GpuCommand {
+                    color: path.color,
+                    clip: [clip.x0, clip.y0, clip.x1, clip.y1].map(|x| x as f32),
+                    segments: segments.len() as u32,
+                    segment_count: tile.segments.len() as u32,
+                    backdrop: backdrops.len() as u32,
+                    fill_rule: path.fill_rule as u32,
+                });
+                segments.extend(tile.segments.iter().map(GpuSegment::from));
+                backdrops.extend(tile.backdrop);
+            }
+        }
+
+        for tile in tile_commands {
+            tile_ranges.push([commands.len() as u32, tile.len() as u32]);
+            commands.extend(tile);
+        }
+
+        // bindings can't be empty
+        if segments.is_empty() {
+            segments.push(GpuSegment::zeroed());
+        }
+        if commands.is_empty() {
+            commands.push(GpuCommand::zeroed());
+        }
+        if backdrops.is_empty() {
+            backdrops.push(0.0);
+        }
+        let bind_group = buffers.write(device, queue, &self.pipeline.layout);
+        Some(PathBatch { bind_group, region })
+    }
+
+    /// Rasterizes a run of paths prepared by [`prepare`](Self::prepare) into a
+    /// texture of the region they cover, with premultiplied linear colors, and
+    /// returns it with the position of the region in the viewport. Returns
+    /// `None` until the pipeline is compiled, or if the paths are outside of
+    /// the viewport.
+    pub(crate) fn render(
+        &self,
+        ctx: &mut LayerRenderContext,
+        encoder: &mut CommandEncoder,
+        paths: Range<u32>,
+    ) -> Option<(CachedTexture, [u32; 2])> {
+        let pipeline = ctx.pipeline_cache.get_compute_pipeline(self.pipeline_id)?;
+        let PathBatch { bind_group, region } = self.batches.get(&paths.start)?;
+        let [x, y, width, height] = *region;
+
+        let output = ctx.texture_pool.get(
+            ctx.device,
+            wgpu::TextureDescriptor {
+                label: Some("path_raster"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: OUTPUT_FORMAT,
+                usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+        );
+        let output_bind_group = ctx.device.create_bind_group(
+            "path_raster_output_bind_group",
+            &self.pipeline.output_layout,
+            &BindGroupEntries::single(&output.default_view),
+        );
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: Some("path_raster_pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_bind_group(1, &output_bind_group, &[]);
+        pass.dispatch_workgroups(width.div_ceil(TILE_SIZE), height.div_ceil(TILE_SIZE), 1);
+        drop(pass);
+
+        Some((output, [x, y]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        render_resource::{test_device, validate_shader},
+        BlendMode,
+    };
+    use wgpu::Features;
+
+    fn region(rects: &[(Rect, Option<Rect>)], viewport: [u32; 2]) -> Option<[u32; 4]> {
+        let mut lines = Vec::new();
+        let mut paths = Vec::new();
+        for (rect, clip) in rects {
+            let start = lines.len();
+            flatten(rect.path_elements(0.1), Affine::IDENTITY, &mut lines);
+            paths.push(PathDraw {
+                lines: start..lines.len(),
+                color: [1.0; 4],
+                fill_rule: FillRule::NonZero,
+                clip: *clip,
+            });
+        }
+        path_region(&lines, &paths, viewport)
+    }
+
+    #[test]
+    fn rasterize_shader_validates() {
+        validate_shader(&[], &rasterize_shader(), &[], Features::empty()).unwrap();
+    }
+
+    #[test]
+    fn regions_cover_the_tiles_of_the_paths() {
+        let rect = Rect::new(20.0, 40.0, 30.0, 50.0);
+        assert_eq!(region(&[(rect, None)], [256, 256]), Some([16, 32, 16, 32]));
+        // the union of the paths of the run
+        let other = Rect::new(100.0, 0.0, 101.0, 1.0);
+        assert_eq!(
+            region(&[(rect, None), (other, None)], [256, 256]),
+            Some([16, 0, 96, 64])
+        );
+        // within the viewport
+        let edge = Rect::new(90.0, -10.0, 120.0, 10.0);
+        assert_eq!(region(&[(edge, None)], [100, 100]), Some([80, 0, 20, 16]));
+        // and the clip
+        let clip = Rect::new(0.0, 0.0, 25.0, 100.0);
+        assert_eq!(
+            region(&[(rect, Some(clip))], [256, 256]),
+            Some([16, 32, 16, 32])
+        );
+        let clip = Rect::new(0.0, 0.0, 10.0, 10.0);
+        assert_eq!(region(&[(rect, Some(clip))], [256, 256]), None);
+        assert_eq!(region(&[], [256, 256]), None);
+    }
+
+    #[test]
+    fn evicted_pipelines_are_queued_again() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let mut pipeline_cache = PipelineCache::new(device.clone());
+        let mut rasterizer = PathRasterizer::new(&device, &mut pipeline_cache);
+        pipeline_cache.block_on_queue();
+
+        pipeline_cache.set_eviction_age(Some(0));
+        pipeline_cache.process_queue();
+        pipeline_cache.process_queue();
+        assert_eq!(pipeline_cache.stats().evicted, 1);
+        pipeline_cache.set_eviction_age(None);
+
+        let rect = Rect::new(20.0, 40.0, 30.0, 50.0);
+        let path = rasterizer.push(
+            rect.path_elements(0.1),
+            Affine::IDENTITY,
+            &Color::WHITE,
+            FillRule::NonZero,
+            None,
+        );
+        let layer = Layer {
+            opacity: 1.0,
+            blend_mode: BlendMode::SourceOver,
+            clip: None,
+            items: vec![LayerItem::Paths {
+                range: path..path + 1,
+            }],
+            filters: Vec::new(),
+            mask: None,
+            state_depth: 1,
+        };
+        let layers = [layer];
+        rasterizer.prepare(&device, &queue, &pipeline_cache, &layers, [256, 256]);
+        pipeline_cache.block_on_queue();
+        assert!(pipeline_cache
+            .get_compute_pipeline(rasterizer.pipeline_id)
+            .is_some());
+        assert_eq!(rasterizer.batches[&path].region, [16, 32, 16, 32]);
+
+        // the next frames reuse the buffers of the run
+        let bind_group = rasterizer.batches[&path].bind_group.id();
+        rasterizer.prepare(&device, &queue, &pipeline_cache, &layers, [256, 256]);
+        assert_eq!(rasterizer.buffers.len(), 1);
+        assert_eq!(rasterizer.batches[&path].bind_group.id(), bind_group);
+    }
+}
@@ -4,14 +4,26 @@ use crate::renderer::WgpuRenderer;
 
 use super::{Node, RenderGraph};
 
-/// Adds common [`RenderGraph`] operations to [`App`].
+/// Creates a render graph [`Node`] from the state of the [`WgpuRenderer`],
+/// e.g. to create its pipelines and buffers up front.
+pub trait FromRenderer {
+    fn from_renderer(renderer: &WgpuRenderer) -> Self;
+}
+
+impl<T: Default> FromRenderer for T {
+    fn from_renderer(_renderer: &WgpuRenderer) -> Self {
+        T::default()
+    }
+}
+
+/// Adds common [`RenderGraph`] operations to the [`WgpuRenderer`].
 pub trait RenderGraphApp {
     // Add a sub graph to the [`RenderGraph`]
     fn add_render_sub_graph(&mut self, sub_graph_name: &'static str) -> &mut Self;
     /// Add a [`Node`] to the [`RenderGraph`]:
-    /// * Create the [`Node`] using the [`FromWorld`] implementation
+    /// * Create the [`Node`] using the [`FromRenderer`] implementation
     /// * Add it to the graph
-    fn add_render_graph_node<T: Node + FromWorld>(
+    fn add_render_graph_node<T: Node + FromRenderer>(
         &mut self,
         sub_graph_name: &'static str,
         node_name: &'static str,
@@ -31,17 +43,14 @@ pub trait RenderGraphApp {
     ) -> &mut Self;
 }
 
-impl RenderGraphApp for WgpuRenderer {
-    fn add_render_graph_node<T: Node + FromWorld>(
+impl RenderGraphApp for WgpuRenderer<'_> {
+    fn add_render_graph_node<T: Node + FromRenderer>(
         &mut self,
         sub_graph_name: &'static str,
         node_name: &'static str,
     ) -> &mut Self {
-        let node = T::from_world(&mut self.world);
-        let mut render_graph = self.world.get_resource_mut::<RenderGraph>().expect(
-            "RenderGraph not found. Make sure you are using add_render_graph_node on the RenderApp",
-        );
-        if let Some(graph) = render_graph.get_sub_graph_mut(sub_graph_name) {
+        let node = T::from_renderer(self);
+        if let Some(graph) = self.render_graph_mut().get_sub_graph_mut(sub_graph_name) {
             graph.add_node(node_name, node);
         } else {
             warn!("Tried adding a render graph node to {sub_graph_name} but the sub graph doesn't exist");
@@ -54,10 +63,7 @@ impl RenderGraphApp for WgpuRenderer {
         sub_graph_name: &'static str,
         edges: &[&'static str],
     ) -> &mut Self {
-        let mut render_graph = self.world.get_resource_mut::<RenderGraph>().expect(
-            "RenderGraph not found. Make sure you are using add_render_graph_edges on the RenderApp",
-        );
-        if let Some(graph) = render_graph.get_sub_graph_mut(sub_graph_name) {
+        if let Some(graph) = self.render_graph_mut().get_sub_graph_mut(sub_graph_name) {
             graph.add_node_edges(edges);
         } else {
             warn!("Tried adding render graph edges to {sub_graph_name} but the sub graph doesn't exist");
@@ -71,10 +77,7 @@ impl RenderGraphApp for WgpuRenderer {
         output_edge: &'static str,
         input_edge: &'static str,
     ) -> &mut Self {
-        let mut render_graph = self.world.get_resource_mut::<RenderGraph>().expect(
-            "RenderGraph not found. Make sure you are using add_render_graph_edge on the RenderApp",
-        );
-        if let Some(graph) = render_graph.get_sub_graph_mut(sub_graph_name) {
+        if let Some(graph) = self.render_graph_mut().get_sub_graph_mut(sub_graph_name) {
             graph.add_node_edge(output_edge, input_edge);
         } else {
             warn!("Tried adding a render graph edge to {sub_graph_name} but the sub graph doesn't exist");
@@ -83,10 +86,8 @@ impl RenderGraphApp for WgpuRenderer {
     }
 
     fn add_render_sub_graph(&mut self, sub_graph_name: &'static str) -> &mut Self {
-        let mut render_graph = self.world.get_resource_mut::<RenderGraph>().expect(
-            "RenderGraph not found. Make sure you are using add_render_sub_graph on the RenderApp",
-        );
-        render_graph.add_sub_graph(sub_graph_name, RenderGraph::default());
+        self.render_graph_mut()
+            .add_sub_graph(sub_graph_name, RenderGraph::default());
         self
     }
 }
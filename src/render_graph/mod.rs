@@ -4,6 +4,7 @@ mod edge;
 mod graph;
 mod node;
 mod node_slot;
+mod runner;
 
 pub use app::*;
 pub use context::*;
@@ -11,6 +12,7 @@ pub use edge::*;
 pub use graph::*;
 pub use node::*;
 pub use node_slot::*;
+pub use runner::*;
 
 use thiserror::Error;
 
@@ -39,6 +41,8 @@ pub enum RenderGraphError {
     UnconnectedNodeInputSlot { node: NodeId, input_slot: usize },
     #[error("node has an unconnected output slot")]
     UnconnectedNodeOutputSlot { node: NodeId, output_slot: usize },
+    #[error("render graph contains a cycle")]
+    CycleDetected { nodes: Vec<NodeId> },
     #[error("node input slot already occupied")]
     NodeInputSlotAlreadyOccupied {
         node: NodeId,
@@ -4,11 +4,7 @@ use crate::{
         Edge, InputSlotError, OutputSlotError, RenderGraphContext, RenderGraphError,
         RunSubGraphError, SlotInfo, SlotInfos,
     },
-    renderer::RenderContext,
-};
-use bevy_ecs::{
-    query::{QueryItem, QueryState, ReadOnlyWorldQuery},
-    world::{FromWorld, World},
+    renderer::{RenderContext, WgpuRenderer},
 };
 use downcast_rs::{impl_downcast, Downcast};
 use std::{borrow::Cow, fmt::Debug};
@@ -41,8 +37,17 @@ pub trait Node: Downcast + Send + Sync + 'static {
         Vec::new()
     }
 
-    /// Updates internal node state using the current render [`World`] prior to the run method.
-    fn update(&mut self, _world: &mut World) {}
+    /// Specifies the transient textures this node renders to or reads from.
+    /// Nodes declaring the same name share the texture, which lives from the first
+    /// to the last of them in execution order; textures whose lifetimes don't
+    /// overlap may share their allocation. They are available during the run
+    /// method inside the [`RenderGraphContext`].
+    fn transient_textures(&self) -> Vec<TransientTextureInfo> {
+        Vec::new()
+    }
+
+    /// Updates internal node state using the [`WgpuRenderer`] prior to the run method.
+    fn update(&mut self, _renderer: &WgpuRenderer) {}
 
     /// Runs the graph node logic, issues draw calls, updates the output slots and
     /// optionally queues up subgraphs for execution. The graph data, input and output values are
@@ -51,7 +56,7 @@ pub trait Node: Downcast + Send + Sync + 'static {
         &self,
         graph: &mut RenderGraphContext,
         render_context: &mut RenderContext,
-        world: &World,
+        renderer: &WgpuRenderer,
     ) -> Result<(), NodeRunError>;
 }
 
@@ -67,6 +72,26 @@ pub enum NodeRunError {
     RunSubGraphError(#[from] RunSubGraphError),
 }
 
+/// A texture used by a [`Node`] for the duration of a graph run, which is handed
+/// out by the [`TexturePool`](crate::TexturePool).
+#[derive(Clone, Debug)]
+pub struct TransientTextureInfo {
+    pub name: Cow<'static, str>,
+    pub descriptor: wgpu::TextureDescriptor<'static>,
+}
+
+impl TransientTextureInfo {
+    pub fn new(
+        name: impl Into<Cow<'static, str>>,
+        descriptor: wgpu::TextureDescriptor<'static>,
+    ) -> Self {
+        TransientTextureInfo {
+            name: name.into(),
+            descriptor,
+        }
+    }
+}
+
 /// A collection of input and output [`Edges`](Edge) for a [`Node`].
 #[derive(Debug)]
 pub struct Edges {
@@ -304,7 +329,7 @@ impl Node for EmptyNode {
         &self,
         _graph: &mut RenderGraphContext,
         _render_context: &mut RenderContext,
-        _world: &World,
+        _renderer: &WgpuRenderer,
     ) -> Result<(), NodeRunError> {
         Ok(())
     }
@@ -312,11 +337,11 @@ impl Node for EmptyNode {
 
 /// A [`RenderGraph`](super::RenderGraph) [`Node`] that runs the configured graph name once.
 /// This makes it easier to insert sub-graph runs into a graph.
-pub struct RunGraphOnViewNode {
+pub struct RunSubGraphNode {
     graph_name: Cow<'static, str>,
 }
 
-impl RunGraphOnViewNode {
+impl RunSubGraphNode {
     pub fn new<T: Into<Cow<'static, str>>>(graph_name: T) -> Self {
         Self {
             graph_name: graph_name.into(),
@@ -324,85 +349,14 @@ impl RunGraphOnViewNode {
     }
 }
 
-impl Node for RunGraphOnViewNode {
+impl Node for RunSubGraphNode {
     fn run(
         &self,
         graph: &mut RenderGraphContext,
         _render_context: &mut RenderContext,
-        _world: &World,
-    ) -> Result<(), NodeRunError> {
-        graph.run_sub_graph(self.graph_name.clone(), vec![], Some(graph.view_entity()))?;
-        Ok(())
-    }
-}
-
-/// This trait should be used instead of the [`Node`] trait when making a render node that runs on a view.
-///
-/// It is intended to be used with [`ViewNodeRunner`]
-pub trait ViewNode {
-    /// The query that will be used on the view entity.
-    /// It is guaranteed to run on the view entity, so there's no need for a filter
-    type ViewQuery: ReadOnlyWorldQuery;
-
-    /// Updates internal node state using the current render [`World`] prior to the run method.
-    fn update(&mut self, _world: &mut World) {}
-
-    /// Runs the graph node logic, issues draw calls, updates the output slots and
-    /// optionally queues up subgraphs for execution. The graph data, input and output values are
-    /// passed via the [`RenderGraphContext`].
-    fn run(
-        &self,
-        graph: &mut RenderGraphContext,
-        render_context: &mut RenderContext,
-        view_query: QueryItem<Self::ViewQuery>,
-        world: &World,
-    ) -> Result<(), NodeRunError>;
-}
-
-/// This [`Node`] can be used to run any [`ViewNode`].
-/// It will take care of updating the view query in `update()` and running the query in `run()`.
-///
-/// This [`Node`] exists to help reduce boilerplate when making a render node that runs on a view.
-pub struct ViewNodeRunner<N: ViewNode> {
-    view_query: QueryState<N::ViewQuery>,
-    node: N,
-}
-
-impl<N: ViewNode> ViewNodeRunner<N> {
-    pub fn new(node: N, world: &mut World) -> Self {
-        Self {
-            view_query: world.query_filtered(),
-            node,
-        }
-    }
-}
-
-impl<N: ViewNode + FromWorld> FromWorld for ViewNodeRunner<N> {
-    fn from_world(world: &mut World) -> Self {
-        Self::new(N::from_world(world), world)
-    }
-}
-
-impl<T> Node for ViewNodeRunner<T>
-where
-    T: ViewNode + Send + Sync + 'static,
-{
-    fn update(&mut self, world: &mut World) {
-        self.view_query.update_archetypes(world);
-        self.node.update(world);
-    }
-
-    fn run(
-        &self,
-        graph: &mut RenderGraphContext,
-        render_context: &mut RenderContext,
-        world: &World,
+        _renderer: &WgpuRenderer,
     ) -> Result<(), NodeRunError> {
-        let Ok(view) = self.view_query.get_manual(world, graph.view_entity()) else {
-            return Ok(());
-        };
-
-        ViewNode::run(&self.node, graph, render_context, view, world)?;
+        graph.run_sub_graph(self.graph_name.clone(), vec![])?;
         Ok(())
     }
 }
@@ -0,0 +1,290 @@
+use std::{borrow::Cow, collections::VecDeque};
+use thiserror::Error;
+
+use crate::{
+    render_graph::{
+        Edge, NodeId, NodeRunError, NodeState, RenderGraph, RenderGraphContext, RenderGraphError,
+        SlotLabel, SlotType, SlotValue,
+    },
+    render_resource::{CachedTexture, TransientTextureRequest},
+    renderer::{RenderContext, WgpuRenderer},
+    HashMap,
+};
+
+/// Runs a [`RenderGraph`] and its sub graphs in dependency order.
+pub struct RenderGraphRunner;
+
+#[derive(Error, Debug)]
+pub enum RenderGraphRunnerError {
+    #[error(transparent)]
+    InvalidGraph(#[from] RenderGraphError),
+    #[error(transparent)]
+    NodeRunError(#[from] NodeRunError),
+    #[error("node output slot not set (index {slot_index}, name {slot_name})")]
+    EmptyNodeOutputSlot {
+        type_name: &'static str,
+        slot_index: usize,
+        slot_name: Cow<'static, str>,
+    },
+    #[error("graph '{sub_graph:?}' could not be run because slot '{slot_name}' at index {slot_index} has no value")]
+    MissingInput {
+        slot_index: usize,
+        slot_name: Cow<'static, str>,
+        sub_graph: Option<Cow<'static, str>>,
+    },
+    #[error("attempted to use the wrong type for input slot")]
+    MismatchedInputSlotType {
+        slot_index: usize,
+        label: SlotLabel,
+        expected: SlotType,
+        actual: SlotType,
+    },
+    #[error(
+        "node (name: '{node_name:?}') has {slot_count} input slots, but was provided {value_count} values"
+    )]
+    MismatchedInputCount {
+        node_name: Option<Cow<'static, str>>,
+        slot_count: usize,
+        value_count: usize,
+    },
+    #[error("transient texture '{name}' is declared with different descriptors (by node '{node_name:?}')")]
+    MismatchedTransientTexture {
+        name: Cow<'static, str>,
+        node_name: Option<Cow<'static, str>>,
+    },
+}
+
+impl RenderGraphRunner {
+    /// Runs `graph` with the values of its input node set to `inputs`, returning
+    /// the command buffers recorded by its nodes in execution order.
+    ///
+    /// The graph is [validated](RenderGraph::validate) first, as nodes that can
+    /// never be scheduled would otherwise be skipped silently.
+    pub fn run(
+        graph: &RenderGraph,
+        renderer: &WgpuRenderer,
+        inputs: &[SlotValue],
+    ) -> Result<Vec<wgpu::CommandBuffer>, RenderGraphRunnerError> {
+        graph.validate()?;
+
+        let mut render_context = RenderContext::new(renderer.device.clone());
+        Self::run_graph(graph, None, &mut render_context, renderer, inputs)?;
+        Ok(render_context.finish())
+    }
+
+    fn run_graph(
+        graph: &RenderGraph,
+        sub_graph: Option<Cow<'static, str>>,
+        render_context: &mut RenderContext,
+        renderer: &WgpuRenderer,
+        inputs: &[SlotValue],
+    ) -> Result<(), RenderGraphRunnerError> {
+        let mut node_outputs: HashMap<NodeId, Vec<SlotValue>> = HashMap::default();
+
+        // pass inputs into the graph
+        if let Some(input_node) = graph.get_input_node() {
+            let mut input_values: Vec<SlotValue> = Vec::new();
+            for (i, input_slot) in input_node.input_slots.iter().enumerate() {
+                if let Some(input_value) = inputs.get(i) {
+                    if input_slot.slot_type != input_value.slot_type() {
+                        return Err(RenderGraphRunnerError::MismatchedInputSlotType {
+                            slot_index: i,
+                            actual: input_value.slot_type(),
+                            expected: input_slot.slot_type,
+                            label: input_slot.name.clone().into(),
+                        });
+                    }
+                    input_values.push(input_value.clone());
+                } else {
+                    return Err(RenderGraphRunnerError::MissingInput {
+                        slot_index: i,
+                        slot_name: input_slot.name.clone(),
+                        sub_graph,
+                    });
+                }
+            }
+
+            node_outputs.insert(input_node.id, input_values);
+        }
+
+        let schedule = Self::schedule(graph);
+        let transient_textures = Self::get_transient_textures(&schedule, renderer)?;
+        let result = Self::run_nodes(
+            graph,
+            &schedule,
+            node_outputs,
+            &transient_textures,
+            render_context,
+            renderer,
+        );
+
+        // the commands using the textures are recorded, so later passes may reuse them
+        let mut texture_pool = renderer.texture_pool();
+        for texture in transient_textures.values() {
+            texture_pool.release(texture);
+        }
+
+        result
+    }
+
+    /// Orders the nodes of the validated `graph`, such that every node comes
+    /// after the nodes it depends on.
+    fn schedule(graph: &RenderGraph) -> Vec<&NodeState> {
+        let mut in_degrees: HashMap<NodeId, usize> = graph
+            .iter_nodes()
+            .map(|node| (node.id, node.edges.input_edges().len()))
+            .collect();
+        let mut ready: VecDeque<&NodeState> = graph
+            .iter_nodes()
+            .filter(|node| node.edges.input_edges().is_empty())
+            .collect();
+
+        let mut schedule = Vec::with_capacity(in_degrees.len());
+        while let Some(node_state) = ready.pop_front() {
+            schedule.push(node_state);
+            for (_, output_node) in graph.iter_node_outputs(node_state.id).expect("node exists") {
+                let in_degree = in_degrees.get_mut(&output_node.id).unwrap();
+                *in_degree -= 1;
+                if *in_degree == 0 {
+                    ready.push_back(output_node);
+                }
+            }
+        }
+
+        schedule
+    }
+
+    /// Gets the transient textures declared by the scheduled nodes from the
+    /// [`TexturePool`](crate::TexturePool), using each node's position in the
+    /// schedule as its pass.
+    fn get_transient_textures(
+        schedule: &[&NodeState],
+        renderer: &WgpuRenderer,
+    ) -> Result<HashMap<Cow<'static, str>, CachedTexture>, RenderGraphRunnerError> {
+        let mut names: Vec<Cow<'static, str>> = Vec::new();
+        let mut requests: Vec<TransientTextureRequest> = Vec::new();
+        for (pass, node_state) in schedule.iter().enumerate() {
+            for info in node_state.node.transient_textures() {
+                match names.iter().position(|name| *name == info.name) {
+                    Some(index) if requests[index].descriptor != info.descriptor => {
+                        return Err(RenderGraphRunnerError::MismatchedTransientTexture {
+                            name: info.name,
+                            node_name: node_state.name.clone(),
+                        });
+                    }
+                    Some(index) => requests[index].last_pass = pass,
+                    None => {
+                        names.push(info.name);
+                        requests.push(TransientTextureRequest {
+                            descriptor: info.descriptor,
+                            first_pass: pass,
+                            last_pass: pass,
+                        });
+                    }
+                }
+            }
+        }
+
+        if requests.is_empty() {
+            return Ok(HashMap::default());
+        }
+        let textures = renderer
+            .texture_pool()
+            .get_aliased(&renderer.device, &requests);
+        Ok(names.into_iter().zip(textures).collect())
+    }
+
+    fn run_nodes(
+        graph: &RenderGraph,
+        schedule: &[&NodeState],
+        mut node_outputs: HashMap<NodeId, Vec<SlotValue>>,
+        transient_textures: &HashMap<Cow<'static, str>, CachedTexture>,
+        render_context: &mut RenderContext,
+        renderer: &WgpuRenderer,
+    ) -> Result<(), RenderGraphRunnerError> {
+        for node_state in schedule {
+            // the outputs of the input node are the graph's inputs
+            if node_outputs.contains_key(&node_state.id) {
+                continue;
+            }
+
+            // the schedule guarantees that all dependencies have finished running
+            let mut slot_indices_and_inputs: Vec<(usize, SlotValue)> = Vec::new();
+            for (edge, input_node) in graph
+                .iter_node_inputs(node_state.id)
+                .expect("node is in graph")
+            {
+                if let Edge::SlotEdge {
+                    output_index,
+                    input_index,
+                    ..
+                } = edge
+                {
+                    let outputs = node_outputs
+                        .get(&input_node.id)
+                        .expect("dependencies are scheduled first");
+                    slot_indices_and_inputs.push((*input_index, outputs[*output_index].clone()));
+                }
+            }
+
+            // construct final sorted input list
+            slot_indices_and_inputs.sort_by_key(|(index, _)| *index);
+            let inputs: Vec<SlotValue> = slot_indices_and_inputs
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect();
+
+            if inputs.len() != node_state.input_slots.len() {
+                return Err(RenderGraphRunnerError::MismatchedInputCount {
+                    node_name: node_state.name.clone(),
+                    slot_count: node_state.input_slots.len(),
+                    value_count: inputs.len(),
+                });
+            }
+
+            let mut outputs: Vec<Option<SlotValue>> = vec![None; node_state.output_slots.len()];
+            {
+                let mut context = RenderGraphContext::new(
+                    graph,
+                    node_state,
+                    &inputs,
+                    &mut outputs,
+                    transient_textures,
+                );
+                node_state
+                    .node
+                    .run(&mut context, render_context, renderer)?;
+
+                for run_sub_graph in context.finish() {
+                    let sub_graph = graph
+                        .get_sub_graph(&run_sub_graph.name)
+                        .expect("sub graph exists because it was validated when queued.");
+                    Self::run_graph(
+                        sub_graph,
+                        Some(run_sub_graph.name),
+                        render_context,
+                        renderer,
+                        &run_sub_graph.inputs,
+                    )?;
+                }
+            }
+
+            let mut values: Vec<SlotValue> = Vec::new();
+            for (i, output) in outputs.into_iter().enumerate() {
+                if let Some(value) = output {
+                    values.push(value);
+                } else {
+                    let empty_slot = node_state.output_slots.get_slot(i).unwrap();
+                    return Err(RenderGraphRunnerError::EmptyNodeOutputSlot {
+                        type_name: node_state.type_name,
+                        slot_index: i,
+                        slot_name: empty_slot.name.clone(),
+                    });
+                }
+            }
+            node_outputs.insert(node_state.id, values);
+        }
+
+        Ok(())
+    }
+}
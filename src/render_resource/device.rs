@@ -219,3 +219,30 @@ impl RenderDevice {
         }
     }
 }
+
+/// Creates a device on the first adapter found, which may be a software one,
+/// for the tests that need a GPU. Returns `None` without an adapter, in which
+/// case the tests pass without checking anything.
+#[cfg(test)]
+pub(crate) fn test_device() -> Option<(RenderDevice, RenderQueue)> {
+    let instance = wgpu::Instance::default();
+    let adapter = instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .into_iter()
+        .next()?;
+    let (device, queue) = now_or_never(adapter.request_device(&Default::default(), None))?.ok()?;
+    Some((
+        RenderDevice::from(device),
+        RenderQueue(std::sync::Arc::new(queue)),
+    ))
+}
+
+/// Polls a future once, returning its output if it is ready.
+#[cfg(test)]
+fn now_or_never<F: std::future::Future>(future: F) -> Option<F::Output> {
+    let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+    match std::pin::pin!(future).poll(&mut context) {
+        std::task::Poll::Ready(output) => Some(output),
+        std::task::Poll::Pending => None,
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    fs,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Identifies the files written by [`ShaderDiskCache`].
+const MAGIC: &[u8; 4] = b"PWSM";
+/// Bumped whenever the entry layout or the composed output changes, such as on
+/// a naga upgrade, so that entries from other versions are not read.
+const FORMAT_VERSION: u32 = 2;
+/// Magic, format version, source hash and payload checksum.
+const HEADER_LEN: usize = 4 + 4 + 8 + 8;
+
+/// A 64-bit FNV-1a hasher.
+///
+/// Unlike aHash, its output is specified and does not depend on the target or
+/// on the crate version, so it is used for everything written to disk. Integers
+/// are hashed as little endian, and `usize`s as `u64`s.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64);
+    }
+}
+
+/// Hashes `value` with a [`StableHasher`].
+pub(crate) fn stable_hash(value: impl Hash) -> u64 {
+    let mut hasher = StableHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Persists the shader modules composed by the [`PipelineCache`](super::PipelineCache)
+/// across runs, so that the `#import`s and shader defs of a shader are only
+/// resolved the first time it is used.
+///
+/// Each entry is keyed by the shader path and its shader defs, and records a
+/// hash of the sources it was composed from. Entries whose sources changed, or
+/// which cannot be read back or validated, are deleted and composed again.
+///
+/// wgpu 0.19 does not expose the driver pipeline cache, so pipelines themselves
+/// are still compiled by the driver on every run.
+#[derive(Clone, Debug)]
+pub struct ShaderDiskCache {
+    directory: PathBuf,
+}
+
+#[derive(Error, Debug)]
+enum DiskCacheError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("the entry is corrupt")]
+    Corrupt,
+    #[error("the entry was composed from other sources")]
+    Stale,
+}
+
+impl ShaderDiskCache {
+    /// Creates a cache storing its entries in `directory`, which is created
+    /// when the first entry is written.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// Deletes every entry.
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.directory) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry_path(&self, key: u64) -> PathBuf {
+        self.directory.join(format!("{key:016x}.wgsl.bin"))
+    }
+
+    /// Returns the composed WGSL stored for `key`, if it was composed from
+    /// sources hashing to `source_hash`.
+    pub(crate) fn load_module(&self, key: u64, source_hash: u64) -> Option<String> {
+        let path = self.entry_path(key);
+        match Self::read_entry(&path, source_hash) {
+            Ok(module) => Some(module),
+            Err(DiskCacheError::Io(err)) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                match err {
+                    DiskCacheError::Stale => debug!("invalidating shader cache entry {:?}", path),
+                    _ => warn!("invalidating shader cache entry {:?}: {}", path, err),
+                }
+                let _ = fs::remove_file(&path);
+                None
+            }
+        }
+    }
+
+    fn read_entry(path: &Path, source_hash: u64) -> Result<String, DiskCacheError> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            return Err(DiskCacheError::Corrupt);
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let stored_source_hash = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let checksum = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+        let payload = &bytes[HEADER_LEN..];
+        if version != FORMAT_VERSION || checksum != stable_hash(payload) {
+            return Err(DiskCacheError::Corrupt);
+        }
+        if stored_source_hash != source_hash {
+            return Err(DiskCacheError::Stale);
+        }
+        String::from_utf8(payload.to_vec()).map_err(|_| DiskCacheError::Corrupt)
+    }
+
+    /// Deletes the entry of `key`, such as when its module no longer validates.
+    pub(crate) fn remove_module(&self, key: u64) {
+        let path = self.entry_path(key);
+        warn!(
+            "invalidating shader cache entry {:?}: the module is invalid",
+            path
+        );
+        let _ = fs::remove_file(path);
+    }
+
+    /// Stores the composed WGSL of `key`. Failures are logged, as the cache is
+    /// only an optimization.
+    pub(crate) fn store_module(&self, key: u64, source_hash: u64, module: &str) {
+        if let Err(err) = self.write_entry(key, source_hash, module) {
+            warn!("could not write shader cache entry: {}", err);
+        }
+    }
+
+    fn write_entry(&self, key: u64, source_hash: u64, module: &str) -> io::Result<()> {
+        let payload = module.as_bytes();
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&source_hash.to_le_bytes());
+        bytes.extend_from_slice(&stable_hash(payload).to_le_bytes());
+        bytes.extend_from_slice(payload);
+
+        fs::create_dir_all(&self.directory)?;
+        // write then rename, so that readers never see a partial entry
+        let path = self.entry_path(key);
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, bytes)?;
+        fs::rename(&temporary, &path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(name: &str) -> ShaderDiskCache {
+        let cache = ShaderDiskCache::new(std::env::temp_dir().join(format!(
+            "piet-wgpu-shader-cache-{name}-{}",
+            std::process::id()
+        )));
+        cache.clear().unwrap();
+        cache
+    }
+
+    #[test]
+    fn stable_hashes() {
+        // the FNV-1a test vectors
+        let fnv = |bytes: &[u8]| {
+            let mut hasher = StableHasher::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(fnv(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv(b"foobar"), 0x8594_4171_f739_67e8);
+
+        assert_eq!(stable_hash(1usize), stable_hash(1u64));
+        assert_eq!(stable_hash(0x0102u16), fnv(&[2, 1]));
+    }
+
+    #[test]
+    fn round_trip() {
+        let cache = cache("round-trip");
+        assert_eq!(cache.load_module(1, 7), None);
+        cache.store_module(1, 7, "fn main() {}");
+        assert_eq!(cache.load_module(1, 7).as_deref(), Some("fn main() {}"));
+        cache.clear().unwrap();
+    }
+
+    #[test]
+    fn stale_and_corrupt_entries_are_removed() {
+        let cache = cache("invalidation");
+        cache.store_module(1, 7, "fn main() {}");
+        assert_eq!(cache.load_module(1, 8), None);
+        assert!(!cache.entry_path(1).exists());
+
+        cache.store_module(2, 7, "fn main() {}");
+        let path = cache.entry_path(2);
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, bytes).unwrap();
+        assert_eq!(cache.load_module(2, 7), None);
+        assert!(!path.exists());
+        cache.clear().unwrap();
+    }
+}
@@ -8,14 +8,21 @@ mod bind_group_layout;
 mod buffer;
 mod buffer_vec;
 mod device;
+mod disk_cache;
 mod gpu_array_buffer;
 mod pipeline;
 mod pipeline_cache;
 mod pipeline_specializer;
 pub mod resource_macros;
 mod shader;
+mod shader_error;
+mod shader_library;
+#[cfg(feature = "hot-reload")]
+mod shader_watcher;
 mod storage_buffer;
+mod task_pool;
 mod texture;
+mod texture_pool;
 mod uniform_buffer;
 
 pub use bind_group::*;
@@ -24,14 +31,21 @@ pub use bind_group_layout::*;
 pub use buffer::*;
 pub use buffer_vec::*;
 pub use device::*;
+pub use disk_cache::*;
 pub use gpu_array_buffer::*;
 
 pub use pipeline::*;
 pub use pipeline_cache::*;
 pub use pipeline_specializer::*;
 pub use shader::*;
+pub use shader_error::*;
+pub use shader_library::*;
+#[cfg(feature = "hot-reload")]
+pub use shader_watcher::*;
 pub use storage_buffer::*;
+pub use task_pool::*;
 pub use texture::*;
+pub use texture_pool::*;
 pub use uniform_buffer::*;
 use wgpu::Queue;
 
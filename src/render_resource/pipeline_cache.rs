@@ -1,23 +1,34 @@
 use crate::{
     default,
     render_resource::{
-        BindGroupLayout, BindGroupLayoutId, ComputePipeline, ComputePipelineDescriptor,
-        RenderPipeline, RenderPipelineDescriptor, Shader, ShaderImport,
+        builtin_shaders, stable_hash, BindGroupLayout, BindGroupLayoutId, ComputePipeline,
+        ComputePipelineDescriptor, RenderPipeline, RenderPipelineDescriptor, Shader,
+        ShaderDiskCache, ShaderError, ShaderImport, ShaderLoader, ShaderLoaderError, Source,
+        StableHasher, Task, TaskPanicked, TaskPool,
     },
-    Entry, HashMap, HashSet,
+    HashMap, HashSet,
 };
+use instant::Instant;
 use std::{
     borrow::Cow,
-    hash::Hash,
+    cell::Cell,
+    hash::{Hash, Hasher},
     mem,
     ops::Deref,
-    sync::{Mutex, PoisonError},
+    path::Path,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    time::Duration,
 };
 use thiserror::Error;
 use tracing::{debug, error};
 use uuid::Uuid;
 use wgpu::{
-    naga::valid::Capabilities, ComputePipelineDescriptor as RawComputePipelineDescriptor, Features,
+    naga::{
+        back::wgsl::WriterFlags,
+        valid::{Capabilities, ValidationFlags, Validator},
+        FastHashMap, Module, ShaderStage,
+    },
+    ComputePipelineDescriptor as RawComputePipelineDescriptor, Features,
     FragmentState as RawFragmentState, PipelineLayoutDescriptor, PushConstantRange,
     RenderPipelineDescriptor as RawRenderPipelineDescriptor, ShaderModuleDescriptor,
     VertexBufferLayout as RawVertexBufferLayout, VertexState as RawVertexState,
@@ -26,6 +37,8 @@ use wgpu::{
 use crate::render_resource::resource_macros::*;
 
 use super::RenderDevice;
+#[cfg(feature = "hot-reload")]
+use super::ShaderWatcher;
 
 render_resource_wrapper!(ErasedShaderModule, wgpu::ShaderModule);
 render_resource_wrapper!(ErasedPipelineLayout, wgpu::PipelineLayout);
@@ -51,30 +64,79 @@ pub enum Pipeline {
 type CachedPipelineId = usize;
 
 /// Index of a cached render pipeline in a [`PipelineCache`].
+///
+/// The index of an evicted pipeline is reused, and the generation tells the
+/// pipelines using the same index apart.
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
-pub struct CachedRenderPipelineId(CachedPipelineId);
+pub struct CachedRenderPipelineId {
+    index: CachedPipelineId,
+    generation: u32,
+}
 
 impl CachedRenderPipelineId {
     /// An invalid cached render pipeline index, often used to initialize a variable.
-    pub const INVALID: Self = CachedRenderPipelineId(usize::MAX);
+    pub const INVALID: Self = CachedRenderPipelineId {
+        index: usize::MAX,
+        generation: 0,
+    };
 
     #[inline]
     pub fn id(&self) -> usize {
-        self.0
+        self.index
     }
 }
 
 /// Index of a cached compute pipeline in a [`PipelineCache`].
+///
+/// The index of an evicted pipeline is reused, and the generation tells the
+/// pipelines using the same index apart.
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
-pub struct CachedComputePipelineId(CachedPipelineId);
+pub struct CachedComputePipelineId {
+    index: CachedPipelineId,
+    generation: u32,
+}
 
 impl CachedComputePipelineId {
     /// An invalid cached compute pipeline index, often used to initialize a variable.
-    pub const INVALID: Self = CachedComputePipelineId(usize::MAX);
+    pub const INVALID: Self = CachedComputePipelineId {
+        index: usize::MAX,
+        generation: 0,
+    };
 
     #[inline]
     pub fn id(&self) -> usize {
-        self.0
+        self.index
+    }
+}
+
+/// The id of a render or compute pipeline, see [`ShaderError::pipeline`].
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum PipelineId {
+    Render(CachedRenderPipelineId),
+    Compute(CachedComputePipelineId),
+}
+
+impl From<CachedRenderPipelineId> for PipelineId {
+    fn from(id: CachedRenderPipelineId) -> Self {
+        PipelineId::Render(id)
+    }
+}
+
+impl From<CachedComputePipelineId> for PipelineId {
+    fn from(id: CachedComputePipelineId) -> Self {
+        PipelineId::Compute(id)
+    }
+}
+
+impl PartialEq<CachedRenderPipelineId> for PipelineId {
+    fn eq(&self, other: &CachedRenderPipelineId) -> bool {
+        *self == PipelineId::Render(*other)
+    }
+}
+
+impl PartialEq<CachedComputePipelineId> for PipelineId {
+    fn eq(&self, other: &CachedComputePipelineId) -> bool {
+        *self == PipelineId::Compute(*other)
     }
 }
 
@@ -83,11 +145,94 @@ pub struct CachedPipeline {
     pub state: CachedPipelineState,
 }
 
+/// A slot of [`PipelineCache::pipelines`], which is free once its pipeline was
+/// evicted.
+struct PipelineSlot {
+    generation: u32,
+    pipeline: Option<CachedPipeline>,
+    /// The last frame the pipeline was looked up in, see [`PipelineCache::set_eviction_age`].
+    last_used_frame: Cell<u64>,
+}
+
+/// The pipelines queued since the last [`PipelineCache::process_queue`], with
+/// the index of the slot they go into.
+#[derive(Default)]
+struct NewPipelines {
+    pipelines: Vec<(CachedPipelineId, CachedPipeline)>,
+    /// The slots of the evicted pipelines, which new pipelines go into first.
+    free_slots: Vec<CachedPipelineId>,
+    /// The number of new pipelines going into slots past the end of
+    /// [`PipelineCache::pipelines`].
+    appended: usize,
+}
+
+impl PipelineSlot {
+    /// Returns the pipeline of the slot if it has the generation `generation`,
+    /// that is if it was not evicted.
+    fn get(&self, generation: u32) -> Option<&CachedPipeline> {
+        if self.generation != generation {
+            return None;
+        }
+        self.pipeline.as_ref()
+    }
+}
+
+impl NewPipelines {
+    /// Adds `pipeline`, in a free slot of `slots` if there is one, returning
+    /// its index and generation.
+    fn push(
+        &mut self,
+        slots: &[PipelineSlot],
+        pipeline: CachedPipeline,
+    ) -> (CachedPipelineId, u32) {
+        let index = self.free_slots.pop().unwrap_or_else(|| {
+            self.appended += 1;
+            slots.len() + self.appended - 1
+        });
+        self.pipelines.push((index, pipeline));
+        let generation = slots.get(index).map_or(0, |slot| slot.generation);
+        (index, generation)
+    }
+
+    /// Moves the new pipelines into their slots, as used in `frame`, and
+    /// returns their indices.
+    fn insert_into(&mut self, slots: &mut Vec<PipelineSlot>, frame: u64) -> Vec<CachedPipelineId> {
+        self.appended = 0;
+        self.pipelines
+            .drain(..)
+            .map(|(index, pipeline)| {
+                if index == slots.len() {
+                    slots.push(PipelineSlot {
+                        generation: 0,
+                        pipeline: None,
+                        last_used_frame: Cell::new(0),
+                    });
+                }
+                let slot = &mut slots[index];
+                slot.pipeline = Some(pipeline);
+                slot.last_used_frame.set(frame);
+                index
+            })
+            .collect()
+    }
+
+    /// Evicts the pipeline of `slots[index]`, invalidating its ids, and frees
+    /// the slot for the next new pipeline.
+    fn evict(&mut self, slots: &mut [PipelineSlot], index: CachedPipelineId) {
+        let slot = &mut slots[index];
+        slot.pipeline = None;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(index);
+    }
+}
+
 /// State of a cached pipeline inserted into a [`PipelineCache`].
 #[derive(Debug)]
 pub enum CachedPipelineState {
     /// The pipeline GPU object is queued for creation.
     Queued,
+    /// The pipeline GPU object is being created on a worker thread.
+    Creating(Task<Result<Pipeline, PipelineCacheError>>),
     /// The pipeline GPU object was created successfully and is available (allocated on the GPU).
     Ok(Pipeline),
     /// An error occurred while trying to create the pipeline GPU object.
@@ -111,15 +256,34 @@ impl CachedPipelineState {
             CachedPipelineState::Queued => {
                 panic!("Pipeline has not been compiled yet. It is still in the 'Queued' state.")
             }
+            CachedPipelineState::Creating(_) => {
+                panic!("Pipeline has not been compiled yet. It is still in the 'Creating' state.")
+            }
             CachedPipelineState::Err(err) => panic!("{}", err),
         }
     }
+
+    /// Returns `true` while the pipeline is queued or being created, in which
+    /// case renderers may want to draw with a fallback pipeline.
+    pub fn is_pending(&self) -> bool {
+        matches!(
+            self,
+            CachedPipelineState::Queued | CachedPipelineState::Creating(_)
+        )
+    }
+}
+
+/// A module created from a shader with some shader defs, and the pipelines
+/// using it. The module is released with the last of its pipelines.
+struct ProcessedShader {
+    module: ErasedShaderModule,
+    pipelines: HashSet<CachedPipelineId>,
 }
 
 #[derive(Default)]
 struct ShaderData {
     pipelines: HashSet<CachedPipelineId>,
-    processed_shaders: HashMap<Vec<ShaderDefVal>, ErasedShaderModule>,
+    processed_shaders: HashMap<Vec<ShaderDefVal>, ProcessedShader>,
     resolved_imports: HashMap<ShaderImport, Uuid>,
     dependents: HashSet<Uuid>,
 }
@@ -129,7 +293,66 @@ struct ShaderCache {
     shaders: HashMap<Uuid, Shader>,
     import_path_shaders: HashMap<ShaderImport, Uuid>,
     waiting_on_import: HashMap<ShaderImport, Vec<Uuid>>,
-    composer: naga_oil::compose::Composer,
+    /// Locked apart from the cache, as modules are composed on worker threads.
+    composer: Arc<Mutex<naga_oil::compose::Composer>>,
+    /// The modules of the shaders which changed, removed from the composer
+    /// before it is used again.
+    stale_modules: Arc<Mutex<Vec<String>>>,
+    /// Bumped whenever shaders change, so that modules created from the old
+    /// shaders are not cached.
+    epoch: u64,
+    /// The shaders and shader defs whose modules are being created, which
+    /// other pipelines wait for instead of creating them again.
+    creating_modules: HashSet<(Uuid, Vec<ShaderDefVal>)>,
+    /// Notified when a module of `creating_modules` is done.
+    module_created: Arc<Condvar>,
+    capabilities: Capabilities,
+    disk_cache: Option<ShaderDiskCache>,
+    /// How many times a pipeline used a module from `processed_shaders`.
+    module_hits: usize,
+    /// How many modules were created.
+    modules_created: usize,
+}
+
+/// What creating a shader module needs, copied out of the [`ShaderCache`] so
+/// that the module is composed without holding its lock.
+struct ShaderJob {
+    id: Uuid,
+    shader_defs: Vec<ShaderDefVal>,
+    /// The shader and the shaders it imports, directly or not.
+    shaders: HashMap<Uuid, Shader>,
+    import_path_shaders: HashMap<ShaderImport, Uuid>,
+    composer: Arc<Mutex<naga_oil::compose::Composer>>,
+    stale_modules: Arc<Mutex<Vec<String>>>,
+    capabilities: Capabilities,
+    disk_cache: Option<ShaderDiskCache>,
+    /// The [`ShaderCache::epoch`] the job was created in.
+    epoch: u64,
+}
+
+enum ModuleLookup {
+    Cached(ErasedShaderModule),
+    /// Another thread is creating the module.
+    Creating,
+    Missing(ShaderJob),
+}
+
+/// Removes a module from [`ShaderCache::creating_modules`] when dropped, also
+/// when creating it failed or panicked.
+struct CreatingModule<'a> {
+    shader_cache: &'a Mutex<ShaderCache>,
+    key: (Uuid, Vec<ShaderDefVal>),
+}
+
+impl Drop for CreatingModule<'_> {
+    fn drop(&mut self) {
+        let mut shader_cache = self
+            .shader_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        shader_cache.creating_modules.remove(&self.key);
+        shader_cache.module_created.notify_all();
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
@@ -162,10 +385,14 @@ impl ShaderDefVal {
 }
 
 impl ShaderCache {
-    fn new(render_device: &RenderDevice) -> Self {
+    fn new(features: Features) -> Self {
         const CAPABILITIES: &[(Features, Capabilities)] = &[
             (Features::PUSH_CONSTANTS, Capabilities::PUSH_CONSTANT),
             (Features::SHADER_F64, Capabilities::FLOAT64),
+            (
+                Features::DUAL_SOURCE_BLENDING,
+                Capabilities::DUAL_SOURCE_BLENDING,
+            ),
             (
                 Features::SHADER_PRIMITIVE_INDEX,
                 Capabilities::PRIMITIVE_INDEX,
@@ -183,7 +410,6 @@ impl ShaderCache {
                 Capabilities::UNIFORM_BUFFER_AND_STORAGE_TEXTURE_ARRAY_NON_UNIFORM_INDEXING,
             ),
         ];
-        let features = render_device.features();
         let mut capabilities = Capabilities::empty();
         for (feature, capability) in CAPABILITIES {
             if features.contains(*feature) {
@@ -191,20 +417,29 @@ impl ShaderCache {
             }
         }
 
-        #[cfg(debug_assertions)]
-        let composer = naga_oil::compose::Composer::default();
-        #[cfg(not(debug_assertions))]
-        let composer = naga_oil::compose::Composer::non_validating();
-
-        let composer = composer.with_capabilities(capabilities);
-
-        Self {
-            composer,
+        // validating the composed modules reports errors with spans in the
+        // shader sources, which wgpu cannot do
+        let composer = naga_oil::compose::Composer::default().with_capabilities(capabilities);
+
+        let mut shader_cache = Self {
+            composer: Arc::new(Mutex::new(composer)),
+            stale_modules: default(),
+            epoch: 0,
+            creating_modules: default(),
+            module_created: default(),
+            capabilities,
+            disk_cache: None,
+            module_hits: 0,
+            modules_created: 0,
             data: Default::default(),
             shaders: Default::default(),
             import_path_shaders: Default::default(),
             waiting_on_import: Default::default(),
+        };
+        for shader in builtin_shaders() {
+            shader_cache.set_shader(shader.id, shader);
         }
+        shader_cache
     }
 
     fn add_import_to_composer(
@@ -225,7 +460,7 @@ impl ShaderCache {
                         )?;
                     }
 
-                    composer.add_composable_module(shader.into())?;
+                    composer.add_composable_module(shader.try_into()?)?;
                 }
             }
             // if we fail to add a module the composer will tell us what is missing
@@ -234,19 +469,130 @@ impl ShaderCache {
         Ok(())
     }
 
-    #[allow(clippy::result_large_err)]
+    /// Hashes the sources a shader is composed from, that is its own source and
+    /// those of its transitive imports, to detect stale disk cache entries.
+    fn source_hash(
+        import_path_shaders: &HashMap<ShaderImport, Uuid>,
+        shaders: &HashMap<Uuid, Shader>,
+        shader: &Shader,
+    ) -> u64 {
+        let mut hasher = StableHasher::default();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        let mut visited = HashSet::default();
+        let mut pending = vec![shader];
+        while let Some(shader) = pending.pop() {
+            if !visited.insert(shader.import_path()) {
+                continue;
+            }
+            shader.source.hash(&mut hasher);
+            shader.shader_defs.hash(&mut hasher);
+            pending.extend(
+                shader
+                    .imports()
+                    .filter_map(|import| shaders.get(import_path_shaders.get(import)?)),
+            );
+        }
+        hasher.finish()
+    }
+
+    /// The disk cache key of `shader` composed with `shader_defs`. The shader ids
+    /// are not stable across runs, so entries are keyed by path instead.
+    fn disk_key(shader: &Shader, shader_defs: &[ShaderDefVal]) -> u64 {
+        stable_hash((
+            &shader.path,
+            shader.import_path(),
+            shader_defs,
+            &shader.shader_defs,
+        ))
+    }
+
+    /// Parses and validates WGSL read from the disk cache, which may have been
+    /// written by another version of naga.
+    fn read_wgsl(capabilities: Capabilities, wgsl: &str) -> Option<Module> {
+        let module = wgpu::naga::front::wgsl::parse_str(wgsl).ok()?;
+        Validator::new(ValidationFlags::all(), capabilities)
+            .validate(&module)
+            .ok()?;
+        Some(module)
+    }
+
+    /// Writes a composed module back to WGSL, so that it can be stored in the
+    /// disk cache.
+    fn write_wgsl(capabilities: Capabilities, module: &Module) -> Option<String> {
+        let info = Validator::new(ValidationFlags::all(), capabilities)
+            .validate(module)
+            .ok()?;
+        wgpu::naga::back::wgsl::write_string(module, &info, WriterFlags::empty()).ok()
+    }
+
+    /// Parses a SPIR-V shader with the options wgpu uses.
+    #[cfg(feature = "spirv")]
+    fn parse_spirv(data: &[u8]) -> Result<Module, PipelineCacheError> {
+        let options = wgpu::naga::front::spv::Options {
+            adjust_coordinate_space: false,
+            strict_capabilities: true,
+            block_ctx_dump_prefix: None,
+        };
+        wgpu::naga::front::spv::parse_u8_slice(data, &options)
+            .map_err(|err| PipelineCacheError::CreateShaderModule(err.to_string()))
+    }
+
+    #[cfg(not(feature = "spirv"))]
+    fn parse_spirv(_: &[u8]) -> Result<Module, PipelineCacheError> {
+        Err(PipelineCacheError::CreateShaderModule(
+            "SPIR-V shaders need the \"spirv\" feature".to_owned(),
+        ))
+    }
+
+    /// Parses a GLSL compute shader, with the shader defs as `#define`s.
+    fn parse_glsl_compute(
+        source: &str,
+        defines: FastHashMap<String, String>,
+    ) -> Result<Module, PipelineCacheError> {
+        let options = wgpu::naga::front::glsl::Options {
+            stage: ShaderStage::Compute,
+            defines,
+        };
+        wgpu::naga::front::glsl::Frontend::default()
+            .parse(&options, source)
+            .map_err(|errors| {
+                let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+                PipelineCacheError::CreateShaderModule(errors.join("\n"))
+            })
+    }
+
+    /// Validates a module which was not composed, as the composer validates
+    /// the others. `source` is the text of the shader, if it has one.
+    fn validate_module(
+        capabilities: Capabilities,
+        module: Module,
+        source: Option<&str>,
+    ) -> Result<Module, PipelineCacheError> {
+        match Validator::new(ValidationFlags::all(), capabilities).validate(&module) {
+            Ok(_) => Ok(module),
+            Err(err) => Err(PipelineCacheError::CreateShaderModule(match source {
+                Some(source) => err.emit_to_string(source),
+                None => err.to_string(),
+            })),
+        }
+    }
+
+    /// Returns the module of the shader `id` with `shader_defs` for `pipeline`
+    /// if it was already created, or the job creating it.
     fn get(
         &mut self,
         render_device: &RenderDevice,
         pipeline: CachedPipelineId,
         id: Uuid,
         shader_defs: &[ShaderDefVal],
-    ) -> Result<ErasedShaderModule, PipelineCacheError> {
+    ) -> Result<ModuleLookup, PipelineCacheError> {
+        // the pipeline is re-queued when the shader or its imports are set
+        let data = self.data.entry(id).or_default();
+        data.pipelines.insert(pipeline);
         let shader = self
             .shaders
             .get(&id)
             .ok_or(PipelineCacheError::ShaderNotLoaded(id))?;
-        let data = self.data.entry(id).or_default();
         let n_asset_imports = shader
             .imports()
             .filter(|import| matches!(import, ShaderImport::AssetPath(_)))
@@ -260,82 +606,120 @@ impl ShaderCache {
             return Err(PipelineCacheError::ShaderImportNotYetAvailable);
         }
 
-        data.pipelines.insert(pipeline);
-
-        // PERF: this shader_defs clone isn't great. use raw_entry_mut when it stabilizes
-        let module = match data.processed_shaders.entry(shader_defs.to_vec()) {
-            Entry::Occupied(entry) => entry.into_mut(),
-            Entry::Vacant(entry) => {
-                let mut shader_defs = shader_defs.to_vec();
-                #[cfg(all(feature = "webgl", target_arch = "wasm32"))]
-                {
-                    shader_defs.push("NO_ARRAY_TEXTURES_SUPPORT".into());
-                    shader_defs.push("SIXTEEN_BYTE_ALIGNMENT".into());
-                }
-
-                shader_defs.push(ShaderDefVal::UInt(
-                    String::from("AVAILABLE_STORAGE_BUFFER_BINDINGS"),
-                    render_device.limits().max_storage_buffers_per_shader_stage,
-                ));
-
-                debug!(
-                    "processing shader {:?}, with shader defs {:?}",
-                    id, shader_defs
-                );
-                let shader_source = match &shader.source {
-                    _ => {
-                        for import in shader.imports() {
-                            Self::add_import_to_composer(
-                                &mut self.composer,
-                                &self.import_path_shaders,
-                                &self.shaders,
-                                import,
-                            )?;
-                        }
+        if let Some(processed) = data.processed_shaders.get_mut(shader_defs) {
+            processed.pipelines.insert(pipeline);
+            self.module_hits += 1;
+            return Ok(ModuleLookup::Cached(processed.module.clone()));
+        }
+        if self.creating_modules.contains(&(id, shader_defs.to_vec())) {
+            return Ok(ModuleLookup::Creating);
+        }
 
-                        let shader_defs = shader_defs
-                            .into_iter()
-                            .chain(shader.shader_defs.iter().cloned())
-                            .map(|def| match def {
-                                ShaderDefVal::Bool(k, v) => {
-                                    (k, naga_oil::compose::ShaderDefValue::Bool(v))
-                                }
-                                ShaderDefVal::Int(k, v) => {
-                                    (k, naga_oil::compose::ShaderDefValue::Int(v))
-                                }
-                                ShaderDefVal::UInt(k, v) => {
-                                    (k, naga_oil::compose::ShaderDefValue::UInt(v))
-                                }
-                            })
-                            .collect::<std::collections::HashMap<_, _>>();
+        let mut processed_defs = shader_defs.to_vec();
+        #[cfg(all(feature = "webgl", target_arch = "wasm32"))]
+        {
+            processed_defs.push("NO_ARRAY_TEXTURES_SUPPORT".into());
+            processed_defs.push("SIXTEEN_BYTE_ALIGNMENT".into());
+        }
 
-                        let naga = self.composer.make_naga_module(
-                            naga_oil::compose::NagaModuleDescriptor {
-                                shader_defs,
-                                ..shader.into()
-                            },
-                        )?;
+        processed_defs.push(ShaderDefVal::UInt(
+            String::from("AVAILABLE_STORAGE_BUFFER_BINDINGS"),
+            render_device.limits().max_storage_buffers_per_shader_stage,
+        ));
 
-                        wgpu::ShaderSource::Naga(Cow::Owned(naga))
-                    }
-                };
+        self.job(id, processed_defs).map(ModuleLookup::Missing)
+    }
 
-                let module_descriptor = ShaderModuleDescriptor {
-                    label: None,
-                    source: shader_source,
-                };
+    /// Copies what composing the shader `id` with `shader_defs` needs out of
+    /// the cache.
+    fn job(
+        &self,
+        id: Uuid,
+        shader_defs: Vec<ShaderDefVal>,
+    ) -> Result<ShaderJob, PipelineCacheError> {
+        let mut shaders = HashMap::default();
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            if shaders.contains_key(&id) {
+                continue;
+            }
+            if let Some(shader) = self.shaders.get(&id) {
+                pending.extend(
+                    shader
+                        .imports()
+                        .filter_map(|import| self.import_path_shaders.get(import)),
+                );
+                shaders.insert(id, shader.clone());
+            }
+        }
+        if !shaders.contains_key(&id) {
+            return Err(PipelineCacheError::ShaderNotLoaded(id));
+        }
 
-                render_device
-                    .wgpu_device()
-                    .push_error_scope(wgpu::ErrorFilter::Validation);
-                let shader_module = render_device.create_shader_module(module_descriptor);
-                let error = render_device.wgpu_device().pop_error_scope();
+        Ok(ShaderJob {
+            id,
+            shader_defs,
+            shaders,
+            import_path_shaders: self.import_path_shaders.clone(),
+            composer: self.composer.clone(),
+            stale_modules: self.stale_modules.clone(),
+            capabilities: self.capabilities,
+            disk_cache: self.disk_cache.clone(),
+            epoch: self.epoch,
+        })
+    }
 
-                entry.insert(ErasedShaderModule::new(shader_module))
+    /// Returns the module of the shader `id` with `shader_defs` for `pipeline`,
+    /// creating it without holding the lock of `shader_cache`, or waiting for
+    /// the thread already creating it.
+    fn get_module(
+        shader_cache: &Mutex<Self>,
+        render_device: &RenderDevice,
+        pipeline: CachedPipelineId,
+        id: Uuid,
+        shader_defs: &[ShaderDefVal],
+    ) -> Result<ErasedShaderModule, PipelineCacheError> {
+        let lock = || shader_cache.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut cache = lock();
+        let job = loop {
+            match cache.get(render_device, pipeline, id, shader_defs)? {
+                ModuleLookup::Cached(module) => return Ok(module),
+                ModuleLookup::Creating => {
+                    let module_created = cache.module_created.clone();
+                    cache = module_created
+                        .wait(cache)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                ModuleLookup::Missing(job) => break job,
             }
         };
-
-        Ok(module.clone())
+        let key = (id, shader_defs.to_vec());
+        cache.creating_modules.insert(key.clone());
+        drop(cache);
+        let creating = CreatingModule { shader_cache, key };
+        let module = job.create_module(render_device)?;
+
+        let mut cache = lock();
+        cache.modules_created += 1;
+        // the shader changed while the module was created from the old one
+        if job.epoch != cache.epoch {
+            return Ok(module);
+        }
+        let processed = cache
+            .data
+            .entry(id)
+            .or_default()
+            .processed_shaders
+            .entry(shader_defs.to_vec())
+            .or_insert_with(|| ProcessedShader {
+                module,
+                pipelines: HashSet::default(),
+            });
+        processed.pipelines.insert(pipeline);
+        let module = processed.module.clone();
+        drop(cache);
+        drop(creating);
+        Ok(module)
     }
 
     fn clear(&mut self, id: Uuid) -> Vec<CachedPipelineId> {
@@ -348,28 +732,52 @@ impl ShaderCache {
                 shaders_to_clear.extend(data.dependents.iter().copied());
 
                 if let Some(Shader { import_path, .. }) = self.shaders.get(&handle) {
-                    self.composer
-                        .remove_composable_module(&import_path.module_name());
+                    self.stale_modules
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .push(import_path.module_name().into_owned());
                 }
             }
         }
+        self.epoch += 1;
 
         pipelines_to_queue
     }
 
+    /// Forgets the evicted `pipeline`, releasing the modules no other pipeline uses.
+    fn release_pipeline(&mut self, pipeline: CachedPipelineId) {
+        for data in self.data.values_mut() {
+            if data.pipelines.remove(&pipeline) {
+                data.processed_shaders.retain(|_, processed| {
+                    processed.pipelines.remove(&pipeline);
+                    !processed.pipelines.is_empty()
+                });
+            }
+        }
+    }
+
     fn set_shader(&mut self, id: Uuid, shader: Shader) -> Vec<CachedPipelineId> {
-        let pipelines_to_queue = self.clear(id);
+        let mut pipelines_to_queue = self.clear(id);
         let path = shader.import_path();
-        self.import_path_shaders.insert(path.clone(), id);
-        if let Some(waiting_shaders) = self.waiting_on_import.get_mut(path) {
-            for waiting_shader in waiting_shaders.drain(..) {
-                // resolve waiting shader import
-                let data = self.data.entry(waiting_shader).or_default();
-                data.resolved_imports.insert(path.clone(), id);
-                // add waiting shader as dependent of this shader
-                let data = self.data.entry(id).or_default();
-                data.dependents.insert(waiting_shader);
+        // SPIR-V is not composed, so its path does not resolve imports
+        let waiting_shaders = if matches!(shader.source, Source::SpirV(_)) {
+            if self.import_path_shaders.get(path) == Some(&id) {
+                self.import_path_shaders.remove(path);
             }
+            None
+        } else {
+            self.import_path_shaders.insert(path.clone(), id);
+            self.waiting_on_import.remove(path)
+        };
+        for waiting_shader in waiting_shaders.into_iter().flatten() {
+            // resolve waiting shader import
+            let data = self.data.entry(waiting_shader).or_default();
+            data.resolved_imports.insert(path.clone(), id);
+            // add waiting shader as dependent of this shader
+            let data = self.data.entry(id).or_default();
+            data.dependents.insert(waiting_shader);
+            // the pipelines of the waiting shader can be created now
+            pipelines_to_queue.extend(self.clear(waiting_shader));
         }
 
         for import in shader.imports() {
@@ -400,6 +808,172 @@ impl ShaderCache {
     }
 }
 
+impl ShaderJob {
+    fn create_module(
+        &self,
+        render_device: &RenderDevice,
+    ) -> Result<ErasedShaderModule, PipelineCacheError> {
+        debug!(
+            "processing shader {:?}, with shader defs {:?}",
+            self.id, self.shader_defs
+        );
+        let module_descriptor = ShaderModuleDescriptor {
+            label: None,
+            source: self.process_shader()?,
+        };
+
+        // The module was validated by naga, so wgpu accepts it. Error scopes
+        // can't tell it apart from the errors of other threads, as they are
+        // shared by the whole device.
+        let shader_module = render_device.create_shader_module(module_descriptor);
+        Ok(ErasedShaderModule::new(shader_module))
+    }
+
+    /// Locks the composer, after removing the modules of the shaders which
+    /// changed since it was last used.
+    fn composer(&self) -> MutexGuard<'_, naga_oil::compose::Composer> {
+        let mut composer = self.composer.lock().unwrap_or_else(PoisonError::into_inner);
+        for module_name in self
+            .stale_modules
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .drain(..)
+        {
+            composer.remove_composable_module(&module_name);
+        }
+        composer
+    }
+
+    /// Composes the shader with the shader defs into a module wgpu can
+    /// create, validating it with the capabilities of the device.
+    ///
+    /// GLSL compute and SPIR-V shaders are not composed, but parsed and
+    /// validated on their own.
+    fn process_shader(&self) -> Result<wgpu::ShaderSource<'_>, PipelineCacheError> {
+        let shader = &self.shaders[&self.id];
+        let shader_defs = &self.shader_defs;
+        let shader_source = match &shader.source {
+            Source::SpirV(data) => {
+                let module = ShaderCache::parse_spirv(data)?;
+                let module = ShaderCache::validate_module(self.capabilities, module, None)?;
+                wgpu::ShaderSource::Naga(Cow::Owned(module))
+            }
+            // naga_oil only composes vertex and fragment GLSL
+            Source::Glsl(source, ShaderStage::Compute) => {
+                let defines = shader_defs
+                    .iter()
+                    .chain(&shader.shader_defs)
+                    .filter(|def| !matches!(def, ShaderDefVal::Bool(_, false)))
+                    .map(|def| match def {
+                        ShaderDefVal::Bool(k, _)
+                        | ShaderDefVal::Int(k, _)
+                        | ShaderDefVal::UInt(k, _) => (k.clone(), def.value_as_string()),
+                    })
+                    .collect();
+                let module = ShaderCache::parse_glsl_compute(source, defines)?;
+                let module = ShaderCache::validate_module(self.capabilities, module, Some(source))?;
+                wgpu::ShaderSource::Naga(Cow::Owned(module))
+            }
+            Source::Wgsl(_) | Source::Glsl(..) => {
+                let disk_entry = self.disk_cache.as_ref().map(|_| {
+                    let key = ShaderCache::disk_key(shader, shader_defs);
+                    let source_hash =
+                        ShaderCache::source_hash(&self.import_path_shaders, &self.shaders, shader);
+                    (key, source_hash)
+                });
+                let cached = self.disk_cache.as_ref().zip(disk_entry).and_then(
+                    |(disk_cache, (key, source_hash))| {
+                        let wgsl = disk_cache.load_module(key, source_hash)?;
+                        let module = ShaderCache::read_wgsl(self.capabilities, &wgsl);
+                        if module.is_none() {
+                            disk_cache.remove_module(key);
+                        }
+                        module
+                    },
+                );
+
+                match cached {
+                    Some(module) => {
+                        debug!("loaded shader {:?} from the disk cache", self.id);
+                        wgpu::ShaderSource::Naga(Cow::Owned(module))
+                    }
+                    None => {
+                        let mut composer = self.composer();
+                        for import in shader.imports() {
+                            ShaderCache::add_import_to_composer(
+                                &mut composer,
+                                &self.import_path_shaders,
+                                &self.shaders,
+                                import,
+                            )?;
+                        }
+
+                        let shader_defs = shader_defs
+                            .iter()
+                            .cloned()
+                            .chain(shader.shader_defs.iter().cloned())
+                            .map(|def| match def {
+                                ShaderDefVal::Bool(k, v) => {
+                                    (k, naga_oil::compose::ShaderDefValue::Bool(v))
+                                }
+                                ShaderDefVal::Int(k, v) => {
+                                    (k, naga_oil::compose::ShaderDefValue::Int(v))
+                                }
+                                ShaderDefVal::UInt(k, v) => {
+                                    (k, naga_oil::compose::ShaderDefValue::UInt(v))
+                                }
+                            })
+                            .collect::<std::collections::HashMap<_, _>>();
+
+                        let naga =
+                            composer.make_naga_module(naga_oil::compose::NagaModuleDescriptor {
+                                shader_defs,
+                                ..shader.try_into()?
+                            })?;
+
+                        if let Some((disk_cache, (key, source_hash))) =
+                            self.disk_cache.as_ref().zip(disk_entry)
+                        {
+                            if let Some(wgsl) = ShaderCache::write_wgsl(self.capabilities, &naga) {
+                                disk_cache.store_module(key, source_hash, &wgsl);
+                            }
+                        }
+
+                        wgpu::ShaderSource::Naga(Cow::Owned(naga))
+                    }
+                }
+            }
+        };
+
+        Ok(shader_source)
+    }
+}
+
+/// Composes and validates `shader` with `shader_defs` as the [`PipelineCache`] would on a
+/// device supporting `features`, without creating a device. `imports` are the shaders it imports.
+#[cfg(test)]
+pub(crate) fn validate_shader(
+    imports: &[Shader],
+    shader: &Shader,
+    shader_defs: &[ShaderDefVal],
+    features: Features,
+) -> Result<(), PipelineCacheError> {
+    let mut shader_cache = ShaderCache::new(features);
+    for import in imports {
+        shader_cache.set_shader(import.id, import.clone());
+    }
+    shader_cache.set_shader(shader.id, shader.clone());
+    let mut shader_defs = shader_defs.to_vec();
+    shader_defs.push(ShaderDefVal::UInt(
+        String::from("AVAILABLE_STORAGE_BUFFER_BINDINGS"),
+        wgpu::Limits::downlevel_webgl2_defaults().max_storage_buffers_per_shader_stage,
+    ));
+    shader_cache
+        .job(shader.id, shader_defs)?
+        .process_shader()
+        .map(|_| ())
+}
+
 type LayoutCacheKey = (Vec<BindGroupLayoutId>, Vec<PushConstantRange>);
 #[derive(Default)]
 struct LayoutCache {
@@ -412,7 +986,7 @@ impl LayoutCache {
         render_device: &RenderDevice,
         bind_group_layouts: &[BindGroupLayout],
         push_constant_ranges: Vec<PushConstantRange>,
-    ) -> &wgpu::PipelineLayout {
+    ) -> ErasedPipelineLayout {
         let bind_group_ids = bind_group_layouts.iter().map(|l| l.id()).collect();
         self.layouts
             .entry((bind_group_ids, push_constant_ranges))
@@ -429,6 +1003,66 @@ impl LayoutCache {
                     },
                 ))
             })
+            .clone()
+    }
+}
+
+/// Called with the errors of the pipelines which failed because of their shaders.
+pub type ShaderErrorCallback = Box<dyn FnMut(&ShaderError)>;
+
+/// A snapshot of the pipelines and shader modules of a [`PipelineCache`], see
+/// [`PipelineCache::stats`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PipelineCacheStats {
+    /// The pipelines waiting to be created, including the pipelines queued since
+    /// the last [`PipelineCache::process_queue`].
+    pub queued: usize,
+    /// The pipelines being created on worker threads.
+    pub creating: usize,
+    pub ok: usize,
+    pub err: usize,
+    /// How many pipelines were created, including pipelines created again after
+    /// one of their shaders changed.
+    pub compiled: usize,
+    /// The time spent creating pipelines, from composing their shaders to
+    /// creating the pipeline objects.
+    pub total_compile_time: Duration,
+    pub max_compile_time: Duration,
+    /// The shader modules in the cache, one for each shader and set of shader
+    /// defs used by a pipeline.
+    pub shader_modules: usize,
+    /// How many shader modules were created, including modules discarded after
+    /// their shader changed.
+    pub shader_modules_created: usize,
+    /// How many times a pipeline used a shader module created for another
+    /// pipeline.
+    pub shader_module_reuses: usize,
+    /// How many pipelines were evicted, see [`PipelineCache::set_eviction_age`].
+    pub evicted: usize,
+}
+
+impl PipelineCacheStats {
+    pub fn mean_compile_time(&self) -> Option<Duration> {
+        let compiled = u32::try_from(self.compiled)
+            .ok()
+            .filter(|count| *count > 0)?;
+        Some(self.total_compile_time / compiled)
+    }
+}
+
+/// The time spent creating pipelines on the worker threads.
+#[derive(Clone, Copy, Default)]
+struct CompileTimes {
+    count: usize,
+    total: Duration,
+    max: Duration,
+}
+
+impl CompileTimes {
+    fn record(&mut self, time: Duration) {
+        self.count += 1;
+        self.total += time;
+        self.max = self.max.max(time);
     }
 }
 
@@ -440,50 +1074,139 @@ impl LayoutCache {
 /// pipeline object is deferred to the [`RenderSet::Render`] step, just before the render
 /// graph starts being processed, as this requires access to the GPU.
 ///
+/// Pipelines are created on worker threads, so a pipeline may stay in the
+/// [`CachedPipelineState::Creating`] state for a few frames after it was queued.
+///
 /// Note that the cache do not perform automatic deduplication of identical pipelines. It is
 /// up to the user not to insert the same pipeline twice to avoid wasting GPU resources.
 ///
+/// Pipelines are kept until they are evicted, which only happens after
+/// [`set_eviction_age`](Self::set_eviction_age) was called.
+///
 /// [`RenderSet::Render`]: crate::RenderSet::Render
 pub struct PipelineCache {
-    layout_cache: LayoutCache,
-    shader_cache: ShaderCache,
+    layout_cache: Arc<Mutex<LayoutCache>>,
+    shader_cache: Arc<Mutex<ShaderCache>>,
+    task_pool: TaskPool,
+    compile_times: Arc<Mutex<CompileTimes>>,
+    #[cfg(feature = "hot-reload")]
+    shader_watcher: Option<ShaderWatcher>,
     device: RenderDevice,
-    pipelines: Vec<CachedPipeline>,
+    pipelines: Vec<PipelineSlot>,
     waiting_pipelines: HashSet<CachedPipelineId>,
-    new_pipelines: Mutex<Vec<CachedPipeline>>,
+    new_pipelines: Mutex<NewPipelines>,
+    /// The number of times [`process_queue`](Self::process_queue) was called.
+    frame: u64,
+    eviction_age: Option<u64>,
+    evicted: usize,
+    shader_errors: HashMap<CachedPipelineId, ShaderError>,
+    shader_error_callback: Option<ShaderErrorCallback>,
 }
 
 impl PipelineCache {
     pub fn pipelines(&self) -> impl Iterator<Item = &CachedPipeline> {
-        self.pipelines.iter()
+        self.pipelines
+            .iter()
+            .filter_map(|slot| slot.pipeline.as_ref())
     }
 
     /// Create a new pipeline cache associated with the given render device.
     pub fn new(device: RenderDevice) -> Self {
         Self {
-            shader_cache: ShaderCache::new(&device),
+            shader_cache: Arc::new(Mutex::new(ShaderCache::new(device.features()))),
             device,
             layout_cache: default(),
+            task_pool: TaskPool::new(),
+            compile_times: default(),
+            #[cfg(feature = "hot-reload")]
+            shader_watcher: None,
             waiting_pipelines: default(),
             new_pipelines: default(),
             pipelines: default(),
+            frame: 0,
+            eviction_age: None,
+            evicted: 0,
+            shader_errors: default(),
+            shader_error_callback: None,
         }
     }
 
+    /// Returns the pipeline in the slot `index` if it has the generation
+    /// `generation`, that is if it was not evicted.
+    fn cached_pipeline(&self, index: CachedPipelineId, generation: u32) -> Option<&CachedPipeline> {
+        self.pipelines.get(index)?.get(generation)
+    }
+
+    /// Like [`cached_pipeline`](Self::cached_pipeline), also keeping the
+    /// pipeline from being evicted.
+    fn use_cached_pipeline(
+        &self,
+        index: CachedPipelineId,
+        generation: u32,
+    ) -> Option<&CachedPipeline> {
+        let pipeline = self.cached_pipeline(index, generation)?;
+        self.pipelines[index].last_used_frame.set(self.frame);
+        Some(pipeline)
+    }
+
+    /// Returns `true` if the pipeline `index` was queued and not evicted since.
+    fn contains_pipeline(&self, index: CachedPipelineId, generation: u32) -> bool {
+        if self.cached_pipeline(index, generation).is_some() {
+            return true;
+        }
+        // new pipelines take the generation of their slot
+        self.pipelines.get(index).map_or(0, |slot| slot.generation) == generation
+            && self
+                .new_pipelines
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .pipelines
+                .iter()
+                .any(|(new_index, _)| *new_index == index)
+    }
+
+    /// Returns `false` if the render pipeline was evicted, in which case it has
+    /// to be queued again.
+    pub fn contains_render_pipeline(&self, id: CachedRenderPipelineId) -> bool {
+        self.contains_pipeline(id.index, id.generation)
+    }
+
+    /// Returns `false` if the compute pipeline was evicted, in which case it has
+    /// to be queued again.
+    pub fn contains_compute_pipeline(&self, id: CachedComputePipelineId) -> bool {
+        self.contains_pipeline(id.index, id.generation)
+    }
+
     /// Get the state of a cached render pipeline.
     ///
     /// See [`PipelineCache::queue_render_pipeline()`].
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the pipeline was evicted, or was queued after the
+    /// last [`process_queue`](Self::process_queue).
     #[inline]
     pub fn get_render_pipeline_state(&self, id: CachedRenderPipelineId) -> &CachedPipelineState {
-        &self.pipelines[id.0].state
+        &self
+            .use_cached_pipeline(id.index, id.generation)
+            .expect("the render pipeline was evicted")
+            .state
     }
 
     /// Get the state of a cached compute pipeline.
     ///
     /// See [`PipelineCache::queue_compute_pipeline()`].
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the pipeline was evicted, or was queued after the
+    /// last [`process_queue`](Self::process_queue).
     #[inline]
     pub fn get_compute_pipeline_state(&self, id: CachedComputePipelineId) -> &CachedPipelineState {
-        &self.pipelines[id.0].state
+        &self
+            .use_cached_pipeline(id.index, id.generation)
+            .expect("the compute pipeline was evicted")
+            .state
     }
 
     /// Get the render pipeline descriptor a cached render pipeline was inserted from.
@@ -494,7 +1217,10 @@ impl PipelineCache {
         &self,
         id: CachedRenderPipelineId,
     ) -> &RenderPipelineDescriptor {
-        match &self.pipelines[id.0].descriptor {
+        let pipeline = self
+            .cached_pipeline(id.index, id.generation)
+            .expect("the render pipeline was evicted");
+        match &pipeline.descriptor {
             PipelineDescriptor::RenderPipelineDescriptor(descriptor) => descriptor,
             PipelineDescriptor::ComputePipelineDescriptor(_) => unreachable!(),
         }
@@ -508,7 +1234,10 @@ impl PipelineCache {
         &self,
         id: CachedComputePipelineId,
     ) -> &ComputePipelineDescriptor {
-        match &self.pipelines[id.0].descriptor {
+        let pipeline = self
+            .cached_pipeline(id.index, id.generation)
+            .expect("the compute pipeline was evicted");
+        match &pipeline.descriptor {
             PipelineDescriptor::RenderPipelineDescriptor(_) => unreachable!(),
             PipelineDescriptor::ComputePipelineDescriptor(descriptor) => descriptor,
         }
@@ -524,7 +1253,7 @@ impl PipelineCache {
     #[inline]
     pub fn get_render_pipeline(&self, id: CachedRenderPipelineId) -> Option<&RenderPipeline> {
         if let CachedPipelineState::Ok(Pipeline::RenderPipeline(pipeline)) =
-            &self.pipelines[id.0].state
+            &self.use_cached_pipeline(id.index, id.generation)?.state
         {
             Some(pipeline)
         } else {
@@ -542,7 +1271,7 @@ impl PipelineCache {
     #[inline]
     pub fn get_compute_pipeline(&self, id: CachedComputePipelineId) -> Option<&ComputePipeline> {
         if let CachedPipelineState::Ok(Pipeline::ComputePipeline(pipeline)) =
-            &self.pipelines[id.0].state
+            &self.use_cached_pipeline(id.index, id.generation)?.state
         {
             Some(pipeline)
         } else {
@@ -561,22 +1290,24 @@ impl PipelineCache {
     /// the caching state with [`get_render_pipeline_state()`] and to retrieve the created GPU pipeline once
     /// it's ready with [`get_render_pipeline()`].
     ///
+    /// Once eviction is enabled with [`set_eviction_age`](Self::set_eviction_age),
+    /// the id becomes invalid when the pipeline is not used for a while. Callers
+    /// keeping the id must queue the pipeline again when
+    /// [`contains_render_pipeline()`] returns `false`, as
+    /// `SpecializedRenderPipelines` does.
+    ///
     /// [`get_render_pipeline_state()`]: PipelineCache::get_render_pipeline_state
     /// [`get_render_pipeline()`]: PipelineCache::get_render_pipeline
+    /// [`contains_render_pipeline()`]: PipelineCache::contains_render_pipeline
     pub fn queue_render_pipeline(
         &self,
         descriptor: RenderPipelineDescriptor,
     ) -> CachedRenderPipelineId {
-        let mut new_pipelines = self
-            .new_pipelines
-            .lock()
-            .unwrap_or_else(PoisonError::into_inner);
-        let id = CachedRenderPipelineId(self.pipelines.len() + new_pipelines.len());
-        new_pipelines.push(CachedPipeline {
+        let (index, generation) = self.queue_pipeline(CachedPipeline {
             descriptor: PipelineDescriptor::RenderPipelineDescriptor(Box::new(descriptor)),
             state: CachedPipelineState::Queued,
         });
-        id
+        CachedRenderPipelineId { index, generation }
     }
 
     /// Insert a compute pipeline into the cache, and queue its creation.
@@ -590,223 +1321,534 @@ impl PipelineCache {
     /// the caching state with [`get_compute_pipeline_state()`] and to retrieve the created GPU pipeline once
     /// it's ready with [`get_compute_pipeline()`].
     ///
+    /// Once eviction is enabled with [`set_eviction_age`](Self::set_eviction_age),
+    /// the id becomes invalid when the pipeline is not used for a while. Callers
+    /// keeping the id must queue the pipeline again when
+    /// [`contains_compute_pipeline()`] returns `false`, as
+    /// `SpecializedComputePipelines` does.
+    ///
     /// [`get_compute_pipeline_state()`]: PipelineCache::get_compute_pipeline_state
     /// [`get_compute_pipeline()`]: PipelineCache::get_compute_pipeline
+    /// [`contains_compute_pipeline()`]: PipelineCache::contains_compute_pipeline
     pub fn queue_compute_pipeline(
         &self,
         descriptor: ComputePipelineDescriptor,
     ) -> CachedComputePipelineId {
-        let mut new_pipelines = self
-            .new_pipelines
-            .lock()
-            .unwrap_or_else(PoisonError::into_inner);
-        let id = CachedComputePipelineId(self.pipelines.len() + new_pipelines.len());
-        new_pipelines.push(CachedPipeline {
+        let (index, generation) = self.queue_pipeline(CachedPipeline {
             descriptor: PipelineDescriptor::ComputePipelineDescriptor(Box::new(descriptor)),
             state: CachedPipelineState::Queued,
         });
-        id
+        CachedComputePipelineId { index, generation }
     }
 
-    fn set_shader(&mut self, id: Uuid, shader: &Shader) {
-        let pipelines_to_queue = self.shader_cache.set_shader(id, shader.clone());
-        for cached_pipeline in pipelines_to_queue {
-            self.pipelines[cached_pipeline].state = CachedPipelineState::Queued;
-            self.waiting_pipelines.insert(cached_pipeline);
+    /// Adds `pipeline` to the new pipelines, in a free slot if there is one,
+    /// returning its index and generation.
+    fn queue_pipeline(&self, pipeline: CachedPipeline) -> (CachedPipelineId, u32) {
+        self.new_pipelines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(&self.pipelines, pipeline)
+    }
+
+    /// Evicts the pipelines which were not used in the last `frames` calls to
+    /// [`process_queue`](Self::process_queue), or disables eviction with `None`,
+    /// which is the default.
+    ///
+    /// A pipeline is used when it or its state is looked up. Evicted pipelines
+    /// release their GPU objects and the shader modules no other pipeline
+    /// uses, and their ids become invalid, see
+    /// [`contains_render_pipeline`](Self::contains_render_pipeline).
+    pub fn set_eviction_age(&mut self, frames: Option<u64>) {
+        self.eviction_age = frames;
+    }
+
+    fn evict_unused_pipelines(&mut self, age: u64) {
+        let mut shader_cache = self
+            .shader_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut new_pipelines = self
+            .new_pipelines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for index in 0..self.pipelines.len() {
+            let slot = &self.pipelines[index];
+            // pending pipelines are not looked up until they are created
+            if slot.pipeline.is_none()
+                || self.waiting_pipelines.contains(&index)
+                || self.frame - slot.last_used_frame.get() <= age
+            {
+                continue;
+            }
+            new_pipelines.evict(&mut self.pipelines, index);
+            self.shader_errors.remove(&index);
+            shader_cache.release_pipeline(index);
+            self.evicted += 1;
         }
     }
 
-    fn remove_shader(&mut self, shader: Uuid) {
-        let pipelines_to_queue = self.shader_cache.remove(shader);
-        for cached_pipeline in pipelines_to_queue {
-            self.pipelines[cached_pipeline].state = CachedPipelineState::Queued;
-            self.waiting_pipelines.insert(cached_pipeline);
+    /// Re-queues the pipelines using a shader which changed.
+    fn requeue_pipelines(&mut self, pipelines: Vec<CachedPipelineId>) {
+        for index in pipelines {
+            if let Some(pipeline) = &mut self.pipelines[index].pipeline {
+                pipeline.state = CachedPipelineState::Queued;
+                self.waiting_pipelines.insert(index);
+            }
         }
     }
 
-    fn process_render_pipeline(
-        &mut self,
-        id: CachedPipelineId,
-        descriptor: &RenderPipelineDescriptor,
-    ) -> CachedPipelineState {
-        let vertex_module = match self.shader_cache.get(
-            &self.device,
-            id,
-            descriptor.vertex.shader.id,
-            &descriptor.vertex.shader_defs,
-        ) {
-            Ok(module) => module,
-            Err(err) => {
-                return CachedPipelineState::Err(err);
+    /// Returns why the pipelines which failed because of their shaders could not
+    /// be created. An error is removed once its pipeline is created, such as
+    /// after the shader was fixed and replaced.
+    pub fn shader_errors(&self) -> impl Iterator<Item = &ShaderError> {
+        self.shader_errors.values()
+    }
+
+    /// Sets a function called by [`process_queue`](Self::process_queue) with
+    /// every new shader error, such as to show it in an overlay.
+    pub fn set_shader_error_callback(&mut self, callback: Option<ShaderErrorCallback>) {
+        self.shader_error_callback = callback;
+    }
+
+    fn shader_cache(&self) -> MutexGuard<'_, ShaderCache> {
+        self.shader_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The composer of the shader cache, to lock without keeping the cache locked.
+    fn composer(&self) -> Arc<Mutex<naga_oil::compose::Composer>> {
+        self.shader_cache().composer.clone()
+    }
+
+    /// Adds a shader to the cache, or replaces the shader `id`, such as one
+    /// created with [`Shader::from_wgsl`] from an embedded string.
+    ///
+    /// Shaders can be added in any order, the pipelines using a shader whose
+    /// imports are missing are created once the imports are added. Replacing a
+    /// shader re-queues the pipelines using it or any shader importing it.
+    ///
+    /// SPIR-V shaders cannot be imported, so their import path is not registered.
+    pub fn set_shader(&mut self, id: Uuid, shader: &Shader) {
+        let pipelines_to_queue = self.shader_cache().set_shader(id, shader.clone());
+        self.requeue_pipelines(pipelines_to_queue);
+    }
+
+    /// Returns the number of pipelines in each state, how long they took to
+    /// create, and how often shader modules were shared between pipelines.
+    pub fn stats(&self) -> PipelineCacheStats {
+        let mut stats = PipelineCacheStats {
+            queued: self
+                .new_pipelines
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .pipelines
+                .len(),
+            evicted: self.evicted,
+            ..default()
+        };
+        for pipeline in self.pipelines() {
+            match pipeline.state {
+                CachedPipelineState::Queued => stats.queued += 1,
+                CachedPipelineState::Creating(_) => stats.creating += 1,
+                CachedPipelineState::Ok(_) => stats.ok += 1,
+                CachedPipelineState::Err(_) => stats.err += 1,
             }
+        }
+
+        let compile_times = *self
+            .compile_times
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        stats.compiled = compile_times.count;
+        stats.total_compile_time = compile_times.total;
+        stats.max_compile_time = compile_times.max;
+
+        let shader_cache = self.shader_cache();
+        stats.shader_modules = shader_cache
+            .data
+            .values()
+            .map(|data| data.processed_shaders.len())
+            .sum();
+        stats.shader_modules_created = shader_cache.modules_created;
+        stats.shader_module_reuses = shader_cache.module_hits;
+        stats
+    }
+
+    /// Sets the cache used to persist composed shader modules across runs, or
+    /// disables it with `None`.
+    ///
+    /// Shaders which were already composed are not written to the cache.
+    pub fn set_disk_cache(&mut self, disk_cache: Option<ShaderDiskCache>) {
+        self.shader_cache().disk_cache = disk_cache;
+    }
+
+    pub fn disk_cache(&self) -> Option<ShaderDiskCache> {
+        self.shader_cache().disk_cache.clone()
+    }
+
+    /// Removes the shader `id`. The pipelines using it are re-queued, and wait
+    /// until a shader with the same id is added again.
+    pub fn remove_shader(&mut self, id: Uuid) {
+        #[cfg(feature = "hot-reload")]
+        if let Some(shader_watcher) = &mut self.shader_watcher {
+            shader_watcher.unwatch(id);
+        }
+        let pipelines_to_queue = self.shader_cache().remove(id);
+        self.requeue_pipelines(pipelines_to_queue);
+    }
+
+    /// Loads a WGSL shader from a file and adds it to the cache, returning its id.
+    ///
+    /// With the `hot-reload` feature and [`watch_shaders`](Self::watch_shaders), the
+    /// shader is reloaded when the file changes.
+    pub fn load_shader(&mut self, path: impl AsRef<Path>) -> Result<Uuid, ShaderLoaderError> {
+        let path = path.as_ref();
+        let shader = ShaderLoader.load(path)?;
+        #[cfg(feature = "hot-reload")]
+        if let Some(shader_watcher) = &mut self.shader_watcher {
+            shader_watcher.watch(path, shader.id)?;
+        }
+        self.set_shader(shader.id, &shader);
+        Ok(shader.id)
+    }
+
+    /// Starts watching the files of the shaders loaded with
+    /// [`load_shader`](Self::load_shader) from now on. Changed shaders are
+    /// reloaded by [`process_queue`](Self::process_queue).
+    #[cfg(feature = "hot-reload")]
+    pub fn watch_shaders(&mut self) -> Result<(), ShaderLoaderError> {
+        if self.shader_watcher.is_none() {
+            self.shader_watcher = Some(ShaderWatcher::new()?);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn reload_changed_shaders(&mut self) {
+        let Some(shader_watcher) = &self.shader_watcher else {
+            return;
         };
+        for (id, path) in shader_watcher.changed() {
+            match ShaderLoader.load(&path) {
+                Ok(shader) => {
+                    debug!("reloading shader {:?}", path);
+                    self.set_shader(id, &Shader { id, ..shader });
+                }
+                Err(err) => error!("failed to reload shader {:?}: {}", path, err),
+            }
+        }
+    }
 
-        let fragment_data = if let Some(fragment) = &descriptor.fragment {
-            let fragment_module = match self.shader_cache.get(
-                &self.device,
+    fn start_create_render_pipeline(
+        &self,
+        id: CachedPipelineId,
+        descriptor: RenderPipelineDescriptor,
+    ) -> CachedPipelineState {
+        let device = self.device.clone();
+        let shader_cache = self.shader_cache.clone();
+        let layout_cache = self.layout_cache.clone();
+        let compile_times = self.compile_times.clone();
+        CachedPipelineState::Creating(self.task_pool.spawn(move || {
+            let start = Instant::now();
+            let vertex_module = ShaderCache::get_module(
+                &shader_cache,
+                &device,
                 id,
-                fragment.shader.id,
-                &fragment.shader_defs,
-            ) {
-                Ok(module) => module,
-                Err(err) => {
-                    return CachedPipelineState::Err(err);
-                }
+                descriptor.vertex.shader.id,
+                &descriptor.vertex.shader_defs,
+            )?;
+            let fragment_module = match &descriptor.fragment {
+                Some(fragment) => Some(ShaderCache::get_module(
+                    &shader_cache,
+                    &device,
+                    id,
+                    fragment.shader.id,
+                    &fragment.shader_defs,
+                )?),
+                None => None,
             };
-            Some((
-                fragment_module,
-                fragment.entry_point.deref(),
-                fragment.targets.as_slice(),
-            ))
-        } else {
-            None
-        };
-
-        let vertex_buffer_layouts = descriptor
-            .vertex
-            .buffers
-            .iter()
-            .map(|layout| RawVertexBufferLayout {
-                array_stride: layout.array_stride,
-                attributes: &layout.attributes,
-                step_mode: layout.step_mode,
-            })
-            .collect::<Vec<_>>();
 
-        let layout = if descriptor.layout.is_empty() && descriptor.push_constant_ranges.is_empty() {
-            None
-        } else {
-            Some(self.layout_cache.get(
-                &self.device,
-                &descriptor.layout,
-                descriptor.push_constant_ranges.to_vec(),
-            ))
-        };
+            let layout =
+                if descriptor.layout.is_empty() && descriptor.push_constant_ranges.is_empty() {
+                    None
+                } else {
+                    Some(
+                        layout_cache
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .get(
+                                &device,
+                                &descriptor.layout,
+                                descriptor.push_constant_ranges.to_vec(),
+                            ),
+                    )
+                };
 
-        let descriptor = RawRenderPipelineDescriptor {
-            multiview: None,
-            depth_stencil: descriptor.depth_stencil.clone(),
-            label: descriptor.label.as_deref(),
-            layout,
-            multisample: descriptor.multisample,
-            primitive: descriptor.primitive,
-            vertex: RawVertexState {
-                buffers: &vertex_buffer_layouts,
-                entry_point: descriptor.vertex.entry_point.deref(),
-                module: &vertex_module,
-            },
-            fragment: fragment_data
-                .as_ref()
-                .map(|(module, entry_point, targets)| RawFragmentState {
-                    entry_point,
+            let vertex_buffer_layouts = descriptor
+                .vertex
+                .buffers
+                .iter()
+                .map(|layout| RawVertexBufferLayout {
+                    array_stride: layout.array_stride,
+                    attributes: &layout.attributes,
+                    step_mode: layout.step_mode,
+                })
+                .collect::<Vec<_>>();
+
+            let fragment_data = descriptor.fragment.as_ref().zip(fragment_module.as_ref());
+
+            let raw_descriptor = RawRenderPipelineDescriptor {
+                multiview: None,
+                depth_stencil: descriptor.depth_stencil.clone(),
+                label: descriptor.label.as_deref(),
+                layout: layout.as_deref(),
+                multisample: descriptor.multisample,
+                primitive: descriptor.primitive,
+                vertex: RawVertexState {
+                    buffers: &vertex_buffer_layouts,
+                    entry_point: descriptor.vertex.entry_point.deref(),
+                    module: &vertex_module,
+                },
+                fragment: fragment_data.map(|(fragment, module)| RawFragmentState {
+                    entry_point: fragment.entry_point.deref(),
                     module,
-                    targets,
+                    targets: &fragment.targets,
                 }),
-        };
+            };
 
-        let pipeline = self.device.create_render_pipeline(&descriptor);
+            let pipeline = device.create_render_pipeline(&raw_descriptor);
+            compile_times
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .record(start.elapsed());
 
-        CachedPipelineState::Ok(Pipeline::RenderPipeline(pipeline))
+            Ok(Pipeline::RenderPipeline(pipeline))
+        }))
     }
 
-    fn process_compute_pipeline(
-        &mut self,
+    fn start_create_compute_pipeline(
+        &self,
         id: CachedPipelineId,
-        descriptor: &ComputePipelineDescriptor,
+        descriptor: ComputePipelineDescriptor,
     ) -> CachedPipelineState {
-        let compute_module = match self.shader_cache.get(
-            &self.device,
-            id,
-            descriptor.shader.id,
-            &descriptor.shader_defs,
-        ) {
-            Ok(module) => module,
-            Err(err) => {
-                return CachedPipelineState::Err(err);
-            }
-        };
-
-        let layout = if descriptor.layout.is_empty() && descriptor.push_constant_ranges.is_empty() {
-            None
-        } else {
-            Some(self.layout_cache.get(
-                &self.device,
-                &descriptor.layout,
-                descriptor.push_constant_ranges.to_vec(),
-            ))
-        };
+        let device = self.device.clone();
+        let shader_cache = self.shader_cache.clone();
+        let layout_cache = self.layout_cache.clone();
+        let compile_times = self.compile_times.clone();
+        CachedPipelineState::Creating(self.task_pool.spawn(move || {
+            let start = Instant::now();
+            let compute_module = ShaderCache::get_module(
+                &shader_cache,
+                &device,
+                id,
+                descriptor.shader.id,
+                &descriptor.shader_defs,
+            )?;
+
+            let layout =
+                if descriptor.layout.is_empty() && descriptor.push_constant_ranges.is_empty() {
+                    None
+                } else {
+                    Some(
+                        layout_cache
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .get(
+                                &device,
+                                &descriptor.layout,
+                                descriptor.push_constant_ranges.to_vec(),
+                            ),
+                    )
+                };
 
-        let descriptor = RawComputePipelineDescriptor {
-            label: descriptor.label.as_deref(),
-            layout,
-            module: &compute_module,
-            entry_point: descriptor.entry_point.as_ref(),
-        };
+            let raw_descriptor = RawComputePipelineDescriptor {
+                label: descriptor.label.as_deref(),
+                layout: layout.as_deref(),
+                module: &compute_module,
+                entry_point: descriptor.entry_point.as_ref(),
+            };
 
-        let pipeline = self.device.create_compute_pipeline(&descriptor);
+            let pipeline = device.create_compute_pipeline(&raw_descriptor);
+            compile_times
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .record(start.elapsed());
 
-        CachedPipelineState::Ok(Pipeline::ComputePipeline(pipeline))
+            Ok(Pipeline::ComputePipeline(pipeline))
+        }))
     }
 
-    /// Process the pipeline queue and create all pending pipelines if possible.
+    /// Process the pipeline queue, starting the creation of the pending pipelines and
+    /// collecting the pipelines whose creation completed.
     ///
     /// This is generally called automatically during the [`RenderSet::Render`] step, but can
-    /// be called manually to force creation at a different time.
+    /// be called manually to force creation at a different time. Call it again in later
+    /// frames until [`CachedPipelineState::is_pending`] is `false` for a pipeline, or use
+    /// [`block_on_queue`](Self::block_on_queue) to wait for all of them.
+    ///
+    /// This is also when unused pipelines are evicted, see
+    /// [`set_eviction_age`](Self::set_eviction_age).
     ///
     /// [`RenderSet::Render`]: crate::RenderSet::Render
     pub fn process_queue(&mut self) {
+        #[cfg(feature = "hot-reload")]
+        self.reload_changed_shaders();
+
+        self.frame += 1;
         let mut waiting_pipelines = mem::take(&mut self.waiting_pipelines);
         let mut pipelines = mem::take(&mut self.pipelines);
 
-        {
-            let mut new_pipelines = self
-                .new_pipelines
+        waiting_pipelines.extend(
+            self.new_pipelines
                 .lock()
-                .unwrap_or_else(PoisonError::into_inner);
-            for new_pipeline in new_pipelines.drain(..) {
-                let id = pipelines.len();
-                pipelines.push(new_pipeline);
-                waiting_pipelines.insert(id);
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert_into(&mut pipelines, self.frame),
+        );
+
+        for id in waiting_pipelines {
+            let slot = &mut pipelines[id];
+            if let Some(pipeline) = &mut slot.pipeline {
+                self.process_pipeline(pipeline, id, slot.generation);
             }
         }
 
-        for id in waiting_pipelines {
-            let pipeline = &mut pipelines[id];
-            if matches!(pipeline.state, CachedPipelineState::Ok(_)) {
-                continue;
+        self.pipelines = pipelines;
+
+        if let Some(age) = self.eviction_age {
+            self.evict_unused_pipelines(age);
+        }
+    }
+
+    /// Processes the pipeline queue, waiting for every pipeline being created
+    /// to complete, such as to avoid drawing a first frame with missing pipelines.
+    ///
+    /// Pipelines whose shaders are not loaded yet stay queued.
+    pub fn block_on_queue(&mut self) {
+        self.process_queue();
+        for id in &self.waiting_pipelines {
+            if let Some(CachedPipeline {
+                state: CachedPipelineState::Creating(task),
+                ..
+            }) = &mut self.pipelines[*id].pipeline
+            {
+                task.wait();
             }
+        }
+        self.process_queue();
+    }
+
+    fn process_pipeline(
+        &mut self,
+        cached_pipeline: &mut CachedPipeline,
+        id: CachedPipelineId,
+        generation: u32,
+    ) {
+        match &mut cached_pipeline.state {
+            CachedPipelineState::Queued | CachedPipelineState::Err(_) => {
+                cached_pipeline.state = match &cached_pipeline.descriptor {
+                    PipelineDescriptor::RenderPipelineDescriptor(descriptor) => {
+                        self.start_create_render_pipeline(id, *descriptor.clone())
+                    }
+                    PipelineDescriptor::ComputePipelineDescriptor(descriptor) => {
+                        self.start_create_compute_pipeline(id, *descriptor.clone())
+                    }
+                };
+            }
+            CachedPipelineState::Creating(task) => match task.poll() {
+                Some(Ok(Ok(pipeline))) => {
+                    cached_pipeline.state = CachedPipelineState::Ok(pipeline);
+                    self.shader_errors.remove(&id);
+                    return;
+                }
+                Some(Ok(Err(err))) => cached_pipeline.state = CachedPipelineState::Err(err),
+                Some(Err(err)) => cached_pipeline.state = CachedPipelineState::Err(err.into()),
+                None => {}
+            },
+            CachedPipelineState::Ok(_) => return,
+        }
 
-            pipeline.state = match &pipeline.descriptor {
-                PipelineDescriptor::RenderPipelineDescriptor(descriptor) => {
-                    self.process_render_pipeline(id, descriptor)
+        if let CachedPipelineState::Err(err) = &cached_pipeline.state {
+            let (pipeline, label) = match &cached_pipeline.descriptor {
+                PipelineDescriptor::RenderPipelineDescriptor(descriptor) => (
+                    PipelineId::Render(CachedRenderPipelineId {
+                        index: id,
+                        generation,
+                    }),
+                    &descriptor.label,
+                ),
+                PipelineDescriptor::ComputePipelineDescriptor(descriptor) => (
+                    PipelineId::Compute(CachedComputePipelineId {
+                        index: id,
+                        generation,
+                    }),
+                    &descriptor.label,
+                ),
+            };
+            let label = label.as_deref().map(str::to_owned);
+            let shader_error = match err {
+                // re-queued by `set_shader`, once the shader and its imports are set
+                PipelineCacheError::ShaderNotLoaded(_)
+                | PipelineCacheError::ShaderImportNotYetAvailable => {
+                    cached_pipeline.state = CachedPipelineState::Queued;
+                    return;
+                }
+                // shader could not be processed ... retrying won't help
+                PipelineCacheError::Validation(err) => {
+                    let shader_error = ShaderError::from_composer_error(
+                        pipeline,
+                        label,
+                        err,
+                        &self
+                            .composer()
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner),
+                    );
+                    error!("shader validation failed:\n{}", shader_error.report);
+                    Some(shader_error)
+                }
+                PipelineCacheError::ProcessShaderError(err) => {
+                    let shader_error = ShaderError::from_composer_error(
+                        pipeline,
+                        label,
+                        err,
+                        &self
+                            .composer()
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner),
+                    );
+                    error!("failed to process shader:\n{}", shader_error.report);
+                    Some(shader_error)
+                }
+                PipelineCacheError::CreateShaderModule(description) => {
+                    error!("failed to create shader module: {}", description);
+                    Some(ShaderError::from_description(pipeline, label, description))
+                }
+                PipelineCacheError::NotComposable(path) => {
+                    let description = format!("shader {path:?} cannot be composed");
+                    error!("{}", description);
+                    Some(ShaderError::from_description(pipeline, label, &description))
                 }
-                PipelineDescriptor::ComputePipelineDescriptor(descriptor) => {
-                    self.process_compute_pipeline(id, descriptor)
+                PipelineCacheError::CreationPanicked(err) => {
+                    error!("failed to create pipeline: {}", err);
+                    Some(ShaderError::from_description(
+                        pipeline,
+                        label,
+                        &err.to_string(),
+                    ))
                 }
             };
-
-            if let CachedPipelineState::Err(err) = &pipeline.state {
-                match err {
-                    PipelineCacheError::ShaderNotLoaded(_)
-                    | PipelineCacheError::ShaderImportNotYetAvailable => {
-                        // retry
-                        self.waiting_pipelines.insert(id);
-                    }
-                    // shader could not be processed ... retrying won't help
-                    PipelineCacheError::ProcessShaderError(err) => {
-                        let error_detail = err.emit_to_string(&self.shader_cache.composer);
-                        error!("failed to process shader:\n{}", error_detail);
-                        continue;
-                    }
-                    PipelineCacheError::CreateShaderModule(description) => {
-                        error!("failed to create shader module: {}", description);
-                        continue;
-                    }
+            if let Some(shader_error) = shader_error {
+                if let Some(callback) = &mut self.shader_error_callback {
+                    callback(&shader_error);
                 }
+                self.shader_errors.insert(id, shader_error);
+                return;
             }
         }
 
-        self.pipelines = pipelines;
+        // retry, or poll again in the next frame
+        self.waiting_pipelines.insert(id);
     }
 }
 
@@ -818,9 +1860,292 @@ pub enum PipelineCacheError {
     )]
     ShaderNotLoaded(Uuid),
     #[error(transparent)]
-    ProcessShaderError(#[from] naga_oil::compose::ComposerError),
+    ProcessShaderError(Box<naga_oil::compose::ComposerError>),
+    /// The composed module is not valid for the capabilities of the device.
+    #[error("Shader validation failed: {0}")]
+    Validation(Box<naga_oil::compose::ComposerError>),
     #[error("Shader import not yet available.")]
     ShaderImportNotYetAvailable,
     #[error("Could not create shader module: {0}")]
     CreateShaderModule(String),
+    /// The shader at the path is SPIR-V, which is neither composed nor imported,
+    /// or GLSL compute, which is not composed.
+    #[error("Shader {0:?} cannot be composed")]
+    NotComposable(String),
+    /// Creating the pipeline panicked on a worker thread, such as on a wgpu
+    /// validation error.
+    #[error("Pipeline creation panicked: {}", .0.message)]
+    CreationPanicked(#[from] TaskPanicked),
+}
+
+impl From<naga_oil::compose::ComposerError> for PipelineCacheError {
+    fn from(err: naga_oil::compose::ComposerError) -> Self {
+        match err.inner {
+            naga_oil::compose::ComposerErrorInner::ShaderValidationError(_) => {
+                PipelineCacheError::Validation(Box::new(err))
+            }
+            _ => PipelineCacheError::ProcessShaderError(Box::new(err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_resource::test_device;
+
+    fn compute_pipeline() -> CachedPipeline {
+        CachedPipeline {
+            descriptor: PipelineDescriptor::ComputePipelineDescriptor(Box::new(
+                ComputePipelineDescriptor {
+                    label: None,
+                    layout: Vec::new(),
+                    push_constant_ranges: Vec::new(),
+                    shader: Shader::from_wgsl("", "test/main.wgsl"),
+                    shader_defs: Vec::new(),
+                    entry_point: "main".into(),
+                },
+            )),
+            state: CachedPipelineState::Queued,
+        }
+    }
+
+    #[test]
+    fn free_slots_are_recycled() {
+        let mut slots = Vec::new();
+        let mut new_pipelines = NewPipelines::default();
+        assert_eq!(new_pipelines.push(&slots, compute_pipeline()), (0, 0));
+        assert_eq!(new_pipelines.push(&slots, compute_pipeline()), (1, 0));
+        assert_eq!(new_pipelines.insert_into(&mut slots, 1), [0, 1]);
+
+        new_pipelines.evict(&mut slots, 1);
+        new_pipelines.evict(&mut slots, 0);
+        assert_eq!(new_pipelines.push(&slots, compute_pipeline()), (0, 1));
+        assert_eq!(new_pipelines.push(&slots, compute_pipeline()), (1, 1));
+        // appended once the free slots are used up
+        assert_eq!(new_pipelines.push(&slots, compute_pipeline()), (2, 0));
+        assert_eq!(new_pipelines.insert_into(&mut slots, 2), [0, 1, 2]);
+        assert_eq!(slots.len(), 3);
+        assert!(new_pipelines.free_slots.is_empty());
+    }
+
+    #[test]
+    fn stale_ids_miss_after_their_slot_is_reused() {
+        let mut slots = Vec::new();
+        let mut new_pipelines = NewPipelines::default();
+        let (index, generation) = new_pipelines.push(&slots, compute_pipeline());
+        new_pipelines.insert_into(&mut slots, 1);
+        assert!(slots[index].get(generation).is_some());
+
+        new_pipelines.evict(&mut slots, index);
+        assert!(slots[index].get(generation).is_none());
+        let (new_index, new_generation) = new_pipelines.push(&slots, compute_pipeline());
+        new_pipelines.insert_into(&mut slots, 2);
+        assert_eq!(new_index, index);
+        assert!(slots[index].get(generation).is_none());
+        assert!(slots[index].get(new_generation).is_some());
+    }
+
+    #[test]
+    fn pipelines_waiting_on_an_import_are_requeued_once_it_is_set() {
+        let mut shader_cache = ShaderCache::new(Features::empty());
+        let shader = Shader::from_wgsl(
+            "#import test::lib\n@compute @workgroup_size(1) fn main() { lib::f(); }",
+            "test/main.wgsl",
+        );
+        shader_cache.set_shader(shader.id, shader.clone());
+        // as `get` does for a pipeline which failed on the missing import
+        shader_cache
+            .data
+            .entry(shader.id)
+            .or_default()
+            .pipelines
+            .insert(7);
+
+        let lib = Shader::from_wgsl("#define_import_path test::lib\nfn f() {}", "test/lib.wgsl");
+        assert_eq!(shader_cache.set_shader(lib.id, lib), vec![7]);
+        assert!(shader_cache
+            .job(shader.id, Vec::new())
+            .unwrap()
+            .process_shader()
+            .is_ok());
+    }
+
+    #[test]
+    fn only_the_dependents_of_a_changed_shader_are_requeued() {
+        let mut shader_cache = ShaderCache::new(Features::empty());
+        let lib = Shader::from_wgsl("#define_import_path test::lib\nfn f() {}", "test/lib.wgsl");
+        shader_cache.set_shader(lib.id, lib.clone());
+        let importing = Shader::from_wgsl(
+            "#import test::lib\n@compute @workgroup_size(1) fn main() { lib::f(); }",
+            "test/importing.wgsl",
+        );
+        let standalone = Shader::from_wgsl(
+            "@compute @workgroup_size(1) fn main() {}",
+            "test/standalone.wgsl",
+        );
+        shader_cache.set_shader(importing.id, importing.clone());
+        shader_cache.set_shader(standalone.id, standalone.clone());
+        assert_eq!(
+            shader_cache.data[&lib.id].dependents,
+            HashSet::from_iter([importing.id])
+        );
+        // as `get` does for the pipelines using each shader
+        for (shader, pipeline) in [(&importing, 1), (&standalone, 2)] {
+            shader_cache
+                .data
+                .entry(shader.id)
+                .or_default()
+                .pipelines
+                .insert(pipeline);
+        }
+
+        let changed = Shader::from_wgsl(
+            "#define_import_path test::lib\nfn f() { let x = 1; }",
+            "test/lib.wgsl",
+        );
+        assert_eq!(shader_cache.set_shader(lib.id, changed), vec![1]);
+        assert_eq!(shader_cache.remove(lib.id), vec![1]);
+        assert_eq!(shader_cache.set_shader(standalone.id, standalone), vec![2]);
+    }
+
+    #[test]
+    fn spirv_shaders_cannot_be_imported() {
+        let mut shader_cache = ShaderCache::new(Features::empty());
+        let spirv = Shader::from_spirv(&[0x03, 0x02, 0x23, 0x07][..], "test/lib.spv");
+        shader_cache.set_shader(spirv.id, spirv.clone());
+        assert!(!shader_cache
+            .import_path_shaders
+            .contains_key(spirv.import_path()));
+
+        let shader = Shader {
+            imports: vec![spirv.import_path().clone()],
+            ..Shader::from_wgsl("@compute @workgroup_size(1) fn main() {}", "test/main.wgsl")
+        };
+        shader_cache.set_shader(shader.id, shader.clone());
+        assert_eq!(
+            shader_cache.waiting_on_import[spirv.import_path()],
+            [shader.id]
+        );
+    }
+
+    #[test]
+    fn invalid_disk_cache_entries_are_composed_again() {
+        let disk_cache = ShaderDiskCache::new(
+            std::env::temp_dir().join(format!("piet-wgpu-pipeline-cache-{}", std::process::id())),
+        );
+        disk_cache.clear().unwrap();
+        let mut shader_cache = ShaderCache::new(Features::empty());
+        shader_cache.disk_cache = Some(disk_cache.clone());
+        let shader =
+            Shader::from_wgsl("@compute @workgroup_size(1) fn main() {}", "test/main.wgsl");
+        shader_cache.set_shader(shader.id, shader.clone());
+
+        // parses, but does not validate
+        let key = ShaderCache::disk_key(&shader, &[]);
+        let source_hash = ShaderCache::source_hash(
+            &shader_cache.import_path_shaders,
+            &shader_cache.shaders,
+            &shader,
+        );
+        disk_cache.store_module(key, source_hash, "fn main() { let x: i32 = 1.0; }");
+
+        let job = shader_cache.job(shader.id, Vec::new()).unwrap();
+        let source = job.process_shader().unwrap();
+        assert!(matches!(source, wgpu::ShaderSource::Naga(_)));
+        let wgsl = disk_cache.load_module(key, source_hash).unwrap();
+        assert!(ShaderCache::read_wgsl(Capabilities::empty(), &wgsl).is_some());
+        disk_cache.clear().unwrap();
+    }
+
+    #[test]
+    fn glsl_compute_shaders_are_validated_before_creation() {
+        let source = "#version 450
+            layout(local_size_x = 1) in;
+            layout(set = 0, binding = 0) buffer Data { float data[]; };
+            void main() { data[0] = VALUE; }";
+        let shader = Shader::from_glsl(source, ShaderStage::Compute, "test/main.comp");
+        let defs = [ShaderDefVal::Int("VALUE".into(), 1)];
+        validate_shader(&[], &shader, &defs, Features::empty()).unwrap();
+        // `VALUE` is undefined
+        assert!(matches!(
+            validate_shader(&[], &shader, &[], Features::empty()),
+            Err(PipelineCacheError::CreateShaderModule(_))
+        ));
+    }
+
+    #[test]
+    fn modules_are_created_once_for_concurrent_pipelines() {
+        let Some((device, _)) = test_device() else {
+            return;
+        };
+        let shader =
+            Shader::from_wgsl("@compute @workgroup_size(1) fn main() {}", "test/main.wgsl");
+        let shader_cache = Mutex::new(ShaderCache::new(device.features()));
+        shader_cache
+            .lock()
+            .unwrap()
+            .set_shader(shader.id, shader.clone());
+
+        let pipelines = 0..4;
+        let barrier = std::sync::Barrier::new(pipelines.len());
+        std::thread::scope(|scope| {
+            for pipeline in pipelines.clone() {
+                let (device, shader_cache, barrier) = (&device, &shader_cache, &barrier);
+                scope.spawn(move || {
+                    barrier.wait();
+                    ShaderCache::get_module(shader_cache, device, pipeline, shader.id, &[])
+                        .unwrap();
+                });
+            }
+        });
+
+        let shader_cache = shader_cache.into_inner().unwrap();
+        assert_eq!(shader_cache.modules_created, 1);
+        assert!(shader_cache.creating_modules.is_empty());
+        let processed = &shader_cache.data[&shader.id].processed_shaders[&Vec::new()];
+        assert_eq!(processed.pipelines, HashSet::from_iter(pipelines));
+    }
+
+    #[test]
+    fn shader_errors_tell_pipelines_in_reused_slots_apart() {
+        let Some((device, _)) = test_device() else {
+            return;
+        };
+        let mut pipeline_cache = PipelineCache::new(device);
+        let shader = Shader::from_wgsl(
+            "@compute @workgroup_size(1) fn main() { let x: i32 = 1.0; }",
+            "test/invalid.wgsl",
+        );
+        pipeline_cache.set_shader(shader.id, &shader);
+        let descriptor = ComputePipelineDescriptor {
+            label: None,
+            layout: Vec::new(),
+            push_constant_ranges: Vec::new(),
+            shader,
+            shader_defs: Vec::new(),
+            entry_point: "main".into(),
+        };
+        let errors = |pipeline_cache: &PipelineCache| {
+            pipeline_cache
+                .shader_errors()
+                .map(|err| err.pipeline)
+                .collect::<Vec<_>>()
+        };
+
+        let id = pipeline_cache.queue_compute_pipeline(descriptor.clone());
+        pipeline_cache.block_on_queue();
+        assert_eq!(errors(&pipeline_cache), [PipelineId::from(id)]);
+
+        pipeline_cache.set_eviction_age(Some(0));
+        pipeline_cache.process_queue();
+        pipeline_cache.process_queue();
+        pipeline_cache.set_eviction_age(None);
+        let new_id = pipeline_cache.queue_compute_pipeline(descriptor);
+        assert_eq!(new_id.id(), id.id());
+        pipeline_cache.block_on_queue();
+        let errors = errors(&pipeline_cache);
+        assert_eq!(errors, [PipelineId::from(new_id)]);
+        assert!(errors[0] != id);
+    }
 }
@@ -34,10 +34,31 @@ impl<S: SpecializedRenderPipeline> SpecializedRenderPipelines<S> {
         specialize_pipeline: &S,
         key: S::Key,
     ) -> CachedRenderPipelineId {
-        *self.cache.entry(key.clone()).or_insert_with(|| {
-            let descriptor = specialize_pipeline.specialize(key);
-            cache.queue_render_pipeline(descriptor)
-        })
+        match self.cache.entry(key.clone()) {
+            Entry::Occupied(entry) if cache.contains_render_pipeline(*entry.get()) => *entry.get(),
+            // queue the pipeline again if it was evicted
+            entry => {
+                let descriptor = specialize_pipeline.specialize(key);
+                *entry.insert(cache.queue_render_pipeline(descriptor)).get()
+            }
+        }
+    }
+
+    /// Queues the pipelines of `keys` ahead of their first use, such as every
+    /// combination of brush, blend mode and sample count, so that they are
+    /// created on worker threads rather than when first drawn.
+    ///
+    /// Use [`PipelineCache::block_on_queue`] to wait for them, or
+    /// [`PipelineCache::stats`] to follow their progress.
+    pub fn warm_up(
+        &mut self,
+        cache: &PipelineCache,
+        specialize_pipeline: &S,
+        keys: impl IntoIterator<Item = S::Key>,
+    ) -> Vec<CachedRenderPipelineId> {
+        keys.into_iter()
+            .map(|key| self.specialize(cache, specialize_pipeline, key))
+            .collect()
     }
 }
 
@@ -63,10 +84,14 @@ impl<S: SpecializedComputePipeline> SpecializedComputePipelines<S> {
         specialize_pipeline: &S,
         key: S::Key,
     ) -> CachedComputePipelineId {
-        *self.cache.entry(key.clone()).or_insert_with(|| {
-            let descriptor = specialize_pipeline.specialize(key);
-            cache.queue_compute_pipeline(descriptor)
-        })
+        match self.cache.entry(key.clone()) {
+            Entry::Occupied(entry) if cache.contains_compute_pipeline(*entry.get()) => *entry.get(),
+            // queue the pipeline again if it was evicted
+            entry => {
+                let descriptor = specialize_pipeline.specialize(key);
+                *entry.insert(cache.queue_compute_pipeline(descriptor)).get()
+            }
+        }
     }
 }
 
@@ -107,8 +132,10 @@ impl<S: SpecializedMeshPipeline> SpecializedMeshPipelines<S> {
             .mesh_layout_cache
             .get_or_insert_with(layout, Default::default);
         match map.entry(key.clone()) {
-            Entry::Occupied(entry) => Ok(*entry.into_mut()),
-            Entry::Vacant(entry) => {
+            Entry::Occupied(entry) if cache.contains_render_pipeline(*entry.get()) => {
+                Ok(*entry.into_mut())
+            }
+            entry => {
                 let descriptor = specialize_pipeline
                     .specialize(key.clone(), layout)
                     .map_err(|mut err| {
@@ -133,8 +160,8 @@ impl<S: SpecializedMeshPipeline> SpecializedMeshPipelines<S> {
                             .1
                     }
                 };
-                Ok(*entry.insert(match layout_map.entry(key) {
-                    Entry::Occupied(entry) => {
+                let id = match layout_map.entry(key) {
+                    Entry::Occupied(entry) if cache.contains_render_pipeline(*entry.get()) => {
                         if cfg!(debug_assertions) {
                             let stored_descriptor =
                                 cache.get_render_pipeline_descriptor(*entry.get());
@@ -152,8 +179,9 @@ impl<S: SpecializedMeshPipeline> SpecializedMeshPipelines<S> {
                         }
                         *entry.into_mut()
                     }
-                    Entry::Vacant(entry) => *entry.insert(cache.queue_render_pipeline(descriptor)),
-                }))
+                    entry => *entry.insert(cache.queue_render_pipeline(descriptor)).get(),
+                };
+                Ok(*entry.insert(id).get())
             }
         }
     }
@@ -164,3 +192,94 @@ pub enum SpecializedMeshPipelineError {
     #[error(transparent)]
     MissingVertexAttribute(#[from] MissingVertexAttributeError),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_resource::{test_device, Shader, ShaderDefVal, VertexState};
+
+    struct TestPipeline {
+        shader: Shader,
+    }
+
+    impl SpecializedRenderPipeline for TestPipeline {
+        type Key = u32;
+
+        fn specialize(&self, key: Self::Key) -> RenderPipelineDescriptor {
+            RenderPipelineDescriptor {
+                label: Some(format!("test_{key}_pipeline").into()),
+                layout: Vec::new(),
+                push_constant_ranges: Vec::new(),
+                vertex: VertexState {
+                    shader: self.shader.clone(),
+                    shader_defs: vec![ShaderDefVal::UInt("KEY".into(), key)],
+                    entry_point: "vertex".into(),
+                    buffers: Vec::new(),
+                },
+                primitive: Default::default(),
+                depth_stencil: None,
+                multisample: Default::default(),
+                fragment: None,
+            }
+        }
+    }
+
+    fn test_pipeline(cache: &mut PipelineCache) -> TestPipeline {
+        let shader = Shader::from_wgsl(
+            "@vertex fn vertex() -> @builtin(position) vec4<f32> { return vec4<f32>(0.0); }",
+            "test/vertex.wgsl",
+        );
+        cache.set_shader(shader.id, &shader);
+        TestPipeline { shader }
+    }
+
+    #[test]
+    fn warm_up_queues_each_key_once() {
+        let Some((device, _)) = test_device() else {
+            return;
+        };
+        let mut cache = PipelineCache::new(device);
+        let pipeline = test_pipeline(&mut cache);
+        let mut pipelines = SpecializedRenderPipelines::default();
+
+        let ids = pipelines.warm_up(&cache, &pipeline, [0, 1, 0, 2, 1]);
+        assert_eq!(ids[2], ids[0]);
+        assert_eq!(ids[4], ids[1]);
+        assert!(ids[0] != ids[1] && ids[1] != ids[3] && ids[0] != ids[3]);
+        assert_eq!(cache.stats().queued, 3);
+        // and warmed up keys aren't queued again when they are first used
+        assert_eq!(pipelines.specialize(&cache, &pipeline, 2), ids[3]);
+        assert_eq!(pipelines.warm_up(&cache, &pipeline, [1]), [ids[1]]);
+        assert_eq!(cache.stats().queued, 3);
+    }
+
+    #[test]
+    fn stats_count_created_pending_and_evicted_pipelines() {
+        let Some((device, _)) = test_device() else {
+            return;
+        };
+        let mut cache = PipelineCache::new(device);
+        let pipeline = test_pipeline(&mut cache);
+        let mut pipelines = SpecializedRenderPipelines::default();
+
+        pipelines.warm_up(&cache, &pipeline, [0, 1]);
+        cache.block_on_queue();
+        pipelines.specialize(&cache, &pipeline, 2);
+        let stats = cache.stats();
+        assert_eq!((stats.ok, stats.queued, stats.evicted), (2, 1, 0));
+        assert_eq!(stats.compiled, 2);
+
+        cache.block_on_queue();
+        cache.set_eviction_age(Some(0));
+        cache.process_queue();
+        cache.process_queue();
+        let stats = cache.stats();
+        assert_eq!((stats.ok, stats.queued, stats.evicted), (0, 0, 3));
+        assert_eq!(stats.compiled, 3);
+
+        // an evicted key is queued again
+        cache.set_eviction_age(None);
+        pipelines.specialize(&cache, &pipeline, 0);
+        assert_eq!(cache.stats().queued, 1);
+    }
+}
@@ -1,8 +1,9 @@
-use super::ShaderDefVal;
+use super::{PipelineCacheError, ShaderDefVal};
 use crate::define_atomic_id;
-use std::{borrow::Cow, marker::Copy};
+use std::{borrow::Cow, fs, marker::Copy, path::Path};
 use thiserror::Error;
 use uuid::Uuid;
+use wgpu::naga::ShaderStage;
 
 define_atomic_id!(ShaderId);
 
@@ -69,6 +70,42 @@ impl Shader {
         }
     }
 
+    pub fn from_glsl(
+        source: impl Into<Cow<'static, str>>,
+        stage: ShaderStage,
+        path: impl Into<String>,
+    ) -> Shader {
+        let source = source.into();
+        let path = path.into();
+        let (import_path, imports) = Shader::preprocess(&source, &path);
+        Shader {
+            id: Uuid::new_v4(),
+            path,
+            imports,
+            import_path,
+            source: Source::Glsl(source, stage),
+            additional_imports: Default::default(),
+            shader_defs: Default::default(),
+            file_dependencies: Default::default(),
+        }
+    }
+
+    /// Creates a shader from SPIR-V, which is passed to wgpu as is. It can
+    /// neither import nor be imported, and ignores shader defs.
+    pub fn from_spirv(source: impl Into<Cow<'static, [u8]>>, path: impl Into<String>) -> Shader {
+        let path = path.into();
+        Shader {
+            id: Uuid::new_v4(),
+            import_path: ShaderImport::AssetPath(path.clone()),
+            path,
+            imports: Vec::new(),
+            source: Source::SpirV(source.into()),
+            additional_imports: Default::default(),
+            shader_defs: Default::default(),
+            file_dependencies: Default::default(),
+        }
+    }
+
     pub fn from_wgsl_with_defs(
         source: impl Into<Cow<'static, str>>,
         path: impl Into<String>,
@@ -100,8 +137,14 @@ impl Shader {
     }
 }
 
-impl<'a> From<&'a Shader> for naga_oil::compose::ComposableModuleDescriptor<'a> {
-    fn from(shader: &'a Shader) -> Self {
+impl<'a> TryFrom<&'a Shader> for naga_oil::compose::ComposableModuleDescriptor<'a> {
+    type Error = PipelineCacheError;
+
+    fn try_from(shader: &'a Shader) -> Result<Self, Self::Error> {
+        let (Some(source), Some(language)) = (shader.source.as_str(), shader.source.language())
+        else {
+            return Err(PipelineCacheError::NotComposable(shader.path.clone()));
+        };
         let shader_defs = shader
             .shader_defs
             .iter()
@@ -123,53 +166,76 @@ impl<'a> From<&'a Shader> for naga_oil::compose::ComposableModuleDescriptor<'a>
             ShaderImport::Custom(_) => None,
         };
 
-        naga_oil::compose::ComposableModuleDescriptor {
-            source: shader.source.as_str(),
+        Ok(naga_oil::compose::ComposableModuleDescriptor {
+            source,
             file_path: &shader.path,
-            language: (&shader.source).into(),
+            language,
             additional_imports: &shader.additional_imports,
             shader_defs,
             as_name,
-        }
+        })
     }
 }
 
-impl<'a> From<&'a Shader> for naga_oil::compose::NagaModuleDescriptor<'a> {
-    fn from(shader: &'a Shader) -> Self {
-        naga_oil::compose::NagaModuleDescriptor {
-            source: shader.source.as_str(),
+impl<'a> TryFrom<&'a Shader> for naga_oil::compose::NagaModuleDescriptor<'a> {
+    type Error = PipelineCacheError;
+
+    fn try_from(shader: &'a Shader) -> Result<Self, Self::Error> {
+        let (Some(source), Some(shader_type)) =
+            (shader.source.as_str(), shader.source.shader_type())
+        else {
+            return Err(PipelineCacheError::NotComposable(shader.path.clone()));
+        };
+        Ok(naga_oil::compose::NagaModuleDescriptor {
+            source,
             file_path: &shader.path,
-            shader_type: (&shader.source).into(),
+            shader_type,
             ..Default::default()
-        }
+        })
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Source {
     Wgsl(Cow<'static, str>),
+    /// GLSL 440 or 450. Vertex and fragment shaders are composed like WGSL
+    /// shaders, compute shaders are compiled on their own, with the shader defs
+    /// as `#define`s.
+    Glsl(Cow<'static, str>, ShaderStage),
+    /// SPIR-V, which needs the `spirv` feature.
+    SpirV(Cow<'static, [u8]>),
 }
 
 impl Source {
-    pub fn as_str(&self) -> &str {
+    /// Returns the text of the shader, or `None` for SPIR-V shaders, which are
+    /// binary.
+    pub fn as_str(&self) -> Option<&str> {
         match self {
-            Source::Wgsl(s) => s,
+            Source::Wgsl(s) | Source::Glsl(s, _) => Some(s),
+            Source::SpirV(_) => None,
         }
     }
-}
 
-impl From<&Source> for naga_oil::compose::ShaderLanguage {
-    fn from(value: &Source) -> Self {
-        match value {
-            Source::Wgsl(_) => naga_oil::compose::ShaderLanguage::Wgsl,
+    /// Returns the language naga_oil composes the shader from when it is
+    /// imported, or `None` for SPIR-V shaders, which cannot be imported.
+    pub fn language(&self) -> Option<naga_oil::compose::ShaderLanguage> {
+        match self {
+            Source::Wgsl(_) => Some(naga_oil::compose::ShaderLanguage::Wgsl),
+            Source::Glsl(..) => Some(naga_oil::compose::ShaderLanguage::Glsl),
+            Source::SpirV(_) => None,
         }
     }
-}
 
-impl From<&Source> for naga_oil::compose::ShaderType {
-    fn from(value: &Source) -> Self {
-        match value {
-            Source::Wgsl(_) => naga_oil::compose::ShaderType::Wgsl,
+    /// Returns the type naga_oil composes the shader as, or `None` for SPIR-V
+    /// and GLSL compute shaders, which are not composed.
+    pub fn shader_type(&self) -> Option<naga_oil::compose::ShaderType> {
+        match self {
+            Source::Wgsl(_) => Some(naga_oil::compose::ShaderType::Wgsl),
+            Source::Glsl(_, ShaderStage::Vertex) => Some(naga_oil::compose::ShaderType::GlslVertex),
+            Source::Glsl(_, ShaderStage::Fragment) => {
+                Some(naga_oil::compose::ShaderType::GlslFragment)
+            }
+            Source::Glsl(_, ShaderStage::Compute) | Source::SpirV(_) => None,
         }
     }
 }
@@ -177,6 +243,31 @@ impl From<&Source> for naga_oil::compose::ShaderType {
 #[derive(Default)]
 pub struct ShaderLoader;
 
+impl ShaderLoader {
+    /// Reads a shader from a file. The path of the file is the path of the
+    /// shader, which other shaders can `#import` it by.
+    ///
+    /// The language is picked by extension: `.vert`, `.frag` and `.comp` are
+    /// GLSL, `.spv` is SPIR-V, and anything else is WGSL.
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<Shader, ShaderLoaderError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)?;
+        let name = path.to_string_lossy();
+        let stage = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("spv") => return Ok(Shader::from_spirv(bytes, name)),
+            Some("vert") => Some(ShaderStage::Vertex),
+            Some("frag") => Some(ShaderStage::Fragment),
+            Some("comp") => Some(ShaderStage::Compute),
+            _ => None,
+        };
+        let source = String::from_utf8(bytes)?;
+        Ok(match stage {
+            Some(stage) => Shader::from_glsl(source, stage, name),
+            None => Shader::from_wgsl(source, name),
+        })
+    }
+}
+
 #[non_exhaustive]
 #[derive(Debug, Error)]
 pub enum ShaderLoaderError {
@@ -184,6 +275,9 @@ pub enum ShaderLoaderError {
     Io(#[from] std::io::Error),
     #[error("Could not parse shader: {0}")]
     Parse(#[from] std::string::FromUtf8Error),
+    #[cfg(feature = "hot-reload")]
+    #[error("Could not watch shader: {0}")]
+    Watch(#[from] notify_debouncer_mini::notify::Error),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
@@ -206,3 +300,55 @@ pub enum ShaderRef {
     /// Use the "default" shader for the current context.
     Default,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use naga_oil::compose::{
+        ComposableModuleDescriptor, Composer, NagaModuleDescriptor, ShaderDefValue,
+    };
+
+    #[test]
+    fn glsl_is_composed_with_shader_defs() {
+        let shader = Shader::from_glsl(
+            "#version 450\n\
+             layout(location = 0) out vec4 color;\n\
+             void main() {\n\
+             #ifdef RED\n\
+                 color = vec4(1.0, 0.0, 0.0, 1.0);\n\
+             #else\n\
+                 color = vec4(undefined);\n\
+             #endif\n\
+             }\n",
+            ShaderStage::Fragment,
+            "red.frag",
+        );
+        let mut composer = Composer::default();
+        let module = composer.make_naga_module(NagaModuleDescriptor {
+            shader_defs: [("RED".to_owned(), ShaderDefValue::Bool(true))].into(),
+            ..(&shader).try_into().unwrap()
+        });
+        assert!(module.is_ok());
+        let module = composer.make_naga_module((&shader).try_into().unwrap());
+        assert!(module.is_err());
+    }
+
+    #[test]
+    fn spirv_and_glsl_compute_are_not_composed() {
+        let spirv = Shader::from_spirv(&[0x03, 0x02, 0x23, 0x07][..], "main.spv");
+        assert_eq!(spirv.source.as_str(), None);
+        assert!(ComposableModuleDescriptor::try_from(&spirv).is_err());
+        assert!(NagaModuleDescriptor::try_from(&spirv).is_err());
+
+        let compute = Shader::from_glsl(
+            "#version 450\nvoid main() {}",
+            ShaderStage::Compute,
+            "main.comp",
+        );
+        assert!(ComposableModuleDescriptor::try_from(&compute).is_ok());
+        assert!(matches!(
+            NagaModuleDescriptor::try_from(&compute),
+            Err(PipelineCacheError::NotComposable(path)) if path == "main.comp"
+        ));
+    }
+}
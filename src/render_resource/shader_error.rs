@@ -0,0 +1,225 @@
+use naga_oil::compose::{ComposerError, ComposerErrorInner};
+use std::{error::Error, ops::Range};
+
+use super::PipelineId;
+
+/// The number of low bits of a span naga_oil keeps as the offset, the high
+/// bits identify the module the span is in. Mirrors a private naga_oil constant.
+const SPAN_SHIFT: usize = 21;
+
+/// A labelled location in the source of a [`ShaderError`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderErrorSpan {
+    /// The byte range in [`ShaderError::source`].
+    pub range: Range<usize>,
+    /// The 1-based line of the start of the range.
+    pub line: usize,
+    /// The 1-based column, in characters, of the start of the range.
+    pub column: usize,
+    pub message: String,
+}
+
+/// Why a pipeline could not be created, as reported by
+/// [`PipelineCache::shader_errors`](super::PipelineCache::shader_errors).
+#[derive(Clone, Debug)]
+pub struct ShaderError {
+    /// The pipeline, which can be compared with its
+    /// [`CachedRenderPipelineId`](super::CachedRenderPipelineId) or
+    /// [`CachedComputePipelineId`](super::CachedComputePipelineId).
+    pub pipeline: PipelineId,
+    /// The label of the pipeline.
+    pub label: Option<String>,
+    /// The path of the shader the error is in, if known.
+    pub path: Option<String>,
+    /// The source the spans refer to, that is the shader after its shader
+    /// defs were applied, or the composed module for validation errors.
+    pub source: String,
+    pub message: String,
+    pub spans: Vec<ShaderErrorSpan>,
+    /// Further messages, such as the causes of a naga validation error.
+    pub notes: Vec<String>,
+    /// The error formatted with its source, as it is logged.
+    pub report: String,
+}
+
+impl ShaderError {
+    pub(crate) fn from_composer_error(
+        pipeline: PipelineId,
+        label: Option<String>,
+        err: &ComposerError,
+        composer: &naga_oil::compose::Composer,
+    ) -> Self {
+        let source = err.source.source(composer).into_owned();
+        let offset = err.source.offset();
+        let map_span = |range: Range<usize>| {
+            let mask = (1 << SPAN_SHIFT) - 1;
+            (range.start & mask).saturating_sub(offset)..(range.end & mask).saturating_sub(offset)
+        };
+        let at = |pos: usize| vec![(pos..pos, String::new())];
+
+        let (spans, notes): (Vec<(Range<usize>, String)>, Vec<String>) = match &err.inner {
+            ComposerErrorInner::HeaderValidationError(err)
+            | ComposerErrorInner::ShaderValidationError(err) => {
+                let spans = err
+                    .spans()
+                    .map(|(span, message)| {
+                        (map_span(span.to_range().unwrap_or(0..0)), message.clone())
+                    })
+                    .collect();
+                let mut notes = Vec::new();
+                let mut cause = err.source();
+                while let Some(err) = cause {
+                    notes.push(err.to_string());
+                    cause = err.source();
+                }
+                (spans, notes)
+            }
+            ComposerErrorInner::WgslParseError(err) => (
+                err.labels()
+                    .map(|(span, message)| {
+                        (
+                            map_span(span.to_range().unwrap_or(0..0)),
+                            message.to_owned(),
+                        )
+                    })
+                    .collect(),
+                vec![err.message().to_owned()],
+            ),
+            ComposerErrorInner::GlslParseError(errors) => (
+                errors
+                    .iter()
+                    .map(|err| {
+                        (
+                            map_span(err.meta.to_range().unwrap_or(0..0)),
+                            err.kind.to_string(),
+                        )
+                    })
+                    .collect(),
+                vec![],
+            ),
+            ComposerErrorInner::ImportNotFound(import, pos) => {
+                (at(*pos), vec![format!("missing import '{import}'")])
+            }
+            ComposerErrorInner::ImportParseError(import, pos) => {
+                (at(*pos), vec![format!("invalid import spec: '{import}'")])
+            }
+            ComposerErrorInner::InvalidIdentifier { at, .. } => (
+                vec![(
+                    map_span(at.to_range().unwrap_or(0..0)),
+                    err.inner.to_string(),
+                )],
+                vec![],
+            ),
+            ComposerErrorInner::DecorationInSource(range) => {
+                (vec![(range.clone(), String::new())], vec![])
+            }
+            ComposerErrorInner::NotEnoughEndIfs(pos)
+            | ComposerErrorInner::TooManyEndIfs(pos)
+            | ComposerErrorInner::ElseWithoutCondition(pos)
+            | ComposerErrorInner::UnknownShaderDef { pos, .. }
+            | ComposerErrorInner::UnknownShaderDefOperator { pos, .. }
+            | ComposerErrorInner::InvalidShaderDefComparisonValue { pos, .. }
+            | ComposerErrorInner::OverrideNotVirtual { pos, .. }
+            | ComposerErrorInner::GlslInvalidVersion(pos)
+            | ComposerErrorInner::DefineInModule(pos)
+            | ComposerErrorInner::InvalidShaderDefDefinitionValue { pos, .. } => (at(*pos), vec![]),
+            _ => (vec![], vec![]),
+        };
+
+        let spans = spans
+            .into_iter()
+            .map(|(range, message)| {
+                let range = range.start.min(source.len())..range.end.min(source.len());
+                let (line, column) = line_column(&source, range.start);
+                ShaderErrorSpan {
+                    range,
+                    line,
+                    column,
+                    message,
+                }
+            })
+            .collect();
+
+        Self {
+            pipeline,
+            label,
+            path: Some(err.source.path(composer).clone()),
+            source,
+            message: err.inner.to_string(),
+            spans,
+            notes,
+            report: err.emit_to_string(composer),
+        }
+    }
+
+    /// An error reported by wgpu when creating a shader module, which has no
+    /// location.
+    pub(crate) fn from_description(
+        pipeline: PipelineId,
+        label: Option<String>,
+        description: &str,
+    ) -> Self {
+        Self {
+            pipeline,
+            label,
+            path: None,
+            source: String::new(),
+            message: description.to_owned(),
+            spans: Vec::new(),
+            notes: Vec::new(),
+            report: description.to_owned(),
+        }
+    }
+}
+
+/// Returns the 1-based line and column of the byte `offset` of `source`.
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_resource::CachedRenderPipelineId;
+    use naga_oil::compose::{Composer, NagaModuleDescriptor};
+
+    #[test]
+    fn parse_errors_have_spans() {
+        let mut composer = Composer::default();
+        let source =
+            "@fragment\nfn main() -> @location(0) vec4<f32> {\n    return vec4(1.0, oops);\n}\n";
+        let err = composer
+            .make_naga_module(NagaModuleDescriptor {
+                source,
+                file_path: "broken.wgsl",
+                ..Default::default()
+            })
+            .unwrap_err();
+        let err = ShaderError::from_composer_error(
+            PipelineId::Render(CachedRenderPipelineId::INVALID),
+            None,
+            &err,
+            &composer,
+        );
+        assert_eq!(err.path.as_deref(), Some("broken.wgsl"));
+        let span = &err.spans[0];
+        assert_eq!((span.line, span.column), (3, 22));
+        assert_eq!(&err.source[span.range.clone()], "oops");
+    }
+
+    #[test]
+    fn line_columns_count_characters() {
+        assert_eq!(line_column("ab\ncdé f", 0), (1, 1));
+        assert_eq!(line_column("ab\ncdé f", 3), (2, 1));
+        assert_eq!(line_column("ab\ncdé f", 7), (2, 4));
+    }
+}
@@ -0,0 +1,82 @@
+use super::Shader;
+
+/// Creates a WGSL [`Shader`] embedded in the binary with `include_str!`. The
+/// path is relative to the file the macro is called in, and the shader is
+/// named after it, prefixed with the calling crate.
+///
+/// ```ignore
+/// let shader = piet_wgpu::embedded_shader!("shaders/outline.wgsl");
+/// pipeline_cache.set_shader(shader.id, &shader);
+/// ```
+///
+/// Shaders starting with a `#define_import_path` can be imported by the shaders
+/// added to the same [`PipelineCache`](crate::PipelineCache).
+#[macro_export]
+macro_rules! embedded_shader {
+    ($path:literal) => {
+        $crate::Shader::from_wgsl(
+            include_str!($path),
+            concat!(env!("CARGO_CRATE_NAME"), "/", $path),
+        )
+    };
+}
+
+/// The shader modules every [`PipelineCache`](crate::PipelineCache) starts
+/// with, so that any shader can import them:
+///
+/// - `piet_wgpu::transform`: affine transforms, and mapping pixels to clip space.
+/// - `piet_wgpu::sdf`: signed distances to circles, rounded rectangles and
+///   segments, and their antialiased coverage.
+/// - `piet_wgpu::color`: sRGB conversions, premultiplication and luminance.
+pub fn builtin_shaders() -> Vec<Shader> {
+    vec![
+        embedded_shader!("shaders/transform.wgsl"),
+        embedded_shader!("shaders/sdf.wgsl"),
+        embedded_shader!("shaders/color.wgsl"),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_resource::{validate_shader, ShaderImport};
+    use wgpu::Features;
+
+    #[test]
+    fn builtin_shaders_have_stable_import_paths() {
+        let import_paths: Vec<_> = builtin_shaders()
+            .iter()
+            .map(|shader| shader.import_path().clone())
+            .collect();
+        assert_eq!(
+            import_paths,
+            ["transform", "sdf", "color"]
+                .map(|module| ShaderImport::Custom(format!("piet_wgpu::{module}")))
+        );
+    }
+
+    #[test]
+    fn builtin_shaders_can_be_imported() {
+        let shader = Shader::from_wgsl(
+            "
+            #import piet_wgpu::transform::{Affine, transform_point, pixel_to_clip}
+            #import piet_wgpu::sdf::{rounded_rect, coverage}
+            #import piet_wgpu::color::{premultiply, srgb_to_linear}
+
+            @vertex
+            fn vertex(@location(0) position: vec2<f32>) -> @builtin(position) vec4<f32> {
+                let transform = Affine(mat2x2<f32>(2.0, 0.0, 0.0, 2.0), vec2<f32>(1.0, 1.0));
+                return pixel_to_clip(transform_point(transform, position), vec2<f32>(800.0, 600.0));
+            }
+
+            @fragment
+            fn fragment(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+                let alpha = coverage(rounded_rect(position.xy - 50.0, vec2<f32>(40.0), 8.0));
+                return premultiply(vec4<f32>(srgb_to_linear(vec3<f32>(0.5)), alpha));
+            }
+            ",
+            "builtin_shaders_can_be_imported.wgsl",
+        );
+        validate_shader(&[], &shader, &[], Features::empty()).unwrap();
+    }
+}
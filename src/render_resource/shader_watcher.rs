@@ -0,0 +1,91 @@
+use notify_debouncer_mini::{
+    new_debouncer,
+    notify::{RecommendedWatcher, RecursiveMode},
+    DebounceEventResult, Debouncer,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+use tracing::warn;
+use uuid::Uuid;
+
+use super::ShaderLoaderError;
+use crate::{HashMap, HashSet};
+
+/// How long a file has to stay unchanged before it is reloaded, as editors
+/// often write a file in several steps.
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Watches the files shaders were loaded from, and reports the shaders whose
+/// file changed.
+pub struct ShaderWatcher {
+    debouncer: Debouncer<RecommendedWatcher>,
+    changes: Receiver<PathBuf>,
+    shaders: HashMap<PathBuf, Uuid>,
+    /// The parent directories of the shaders. Directories are watched rather
+    /// than files, so that files which are replaced on save are still seen.
+    directories: HashSet<PathBuf>,
+}
+
+impl ShaderWatcher {
+    pub fn new() -> Result<Self, ShaderLoaderError> {
+        let (sender, changes) = mpsc::channel();
+        let debouncer = new_debouncer(
+            DEBOUNCE_TIMEOUT,
+            None,
+            move |result: DebounceEventResult| match result {
+                Ok(events) => {
+                    for event in events {
+                        let _ = sender.send(event.path);
+                    }
+                }
+                Err(errors) => {
+                    for error in errors {
+                        warn!("failed to watch shaders: {}", error);
+                    }
+                }
+            },
+        )?;
+        Ok(Self {
+            debouncer,
+            changes,
+            shaders: Default::default(),
+            directories: Default::default(),
+        })
+    }
+
+    /// Starts reporting changes to the file at `path` as changes to the
+    /// shader `id`.
+    pub fn watch(&mut self, path: &Path, id: Uuid) -> Result<(), ShaderLoaderError> {
+        let path = path.canonicalize()?;
+        if let Some(directory) = path.parent() {
+            if !self.directories.contains(directory) {
+                self.debouncer
+                    .watcher()
+                    .watch(directory, RecursiveMode::NonRecursive)?;
+                self.directories.insert(directory.to_owned());
+            }
+        }
+        self.shaders.insert(path, id);
+        Ok(())
+    }
+
+    pub fn unwatch(&mut self, id: Uuid) {
+        self.shaders.retain(|_, shader| *shader != id);
+    }
+
+    /// Returns the shaders whose file changed since the last call, with the
+    /// path of the file.
+    pub fn changed(&self) -> Vec<(Uuid, PathBuf)> {
+        let mut changed: Vec<_> = self
+            .changes
+            .try_iter()
+            .filter_map(|path| Some((*self.shaders.get(&path)?, path)))
+            .collect();
+        changed.sort_unstable();
+        changed.dedup();
+        changed
+    }
+}
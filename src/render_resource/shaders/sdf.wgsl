@@ -0,0 +1,30 @@
+#define_import_path piet_wgpu::sdf
+
+// Signed distances to shapes centered on the origin, negative inside.
+
+fn circle(point: vec2<f32>, radius: f32) -> f32 {
+    return length(point) - radius;
+}
+
+// `half_size` is half the width and height of the rectangle, and the corner
+// radius is clamped to fit in it.
+fn rounded_rect(point: vec2<f32>, half_size: vec2<f32>, radius: f32) -> f32 {
+    let r = min(radius, min(half_size.x, half_size.y));
+    let q = abs(point) - half_size + r;
+    return length(max(q, vec2<f32>(0.0))) + min(max(q.x, q.y), 0.0) - r;
+}
+
+// The distance to the segment from `a` to `b`, which is never negative.
+fn segment(point: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> f32 {
+    let pa = point - a;
+    let ba = b - a;
+    let h = clamp(dot(pa, ba) / max(dot(ba, ba), 1e-12), 0.0, 1.0);
+    return length(pa - ba * h);
+}
+
+// How much of a pixel a shape covers, from the distance to the shape in any
+// unit. Only valid in fragment shaders, as it uses derivatives.
+fn coverage(distance: f32) -> f32 {
+    let width = max(fwidth(distance), 1e-6);
+    return clamp(0.5 - distance / width, 0.0, 1.0);
+}
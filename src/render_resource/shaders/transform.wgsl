@@ -0,0 +1,34 @@
+#define_import_path piet_wgpu::transform
+
+// A 2D affine transform, as the coefficients `[a, b, c, d, e, f]` of a kurbo
+// `Affine`: `matrix` holds the columns (a, b) and (c, d), and `translation`
+// holds (e, f).
+struct Affine {
+    matrix: mat2x2<f32>,
+    translation: vec2<f32>,
+}
+
+fn affine(coefficients: array<f32, 6>) -> Affine {
+    return Affine(
+        mat2x2<f32>(coefficients[0], coefficients[1], coefficients[2], coefficients[3]),
+        vec2<f32>(coefficients[4], coefficients[5]),
+    );
+}
+
+fn transform_point(transform: Affine, point: vec2<f32>) -> vec2<f32> {
+    return transform.matrix * point + transform.translation;
+}
+
+fn transform_vector(transform: Affine, vector: vec2<f32>) -> vec2<f32> {
+    return transform.matrix * vector;
+}
+
+// Maps a position in pixels, with the origin at the top left of a target of
+// `size` pixels, to clip space.
+fn pixel_to_clip(position: vec2<f32>, size: vec2<f32>) -> vec4<f32> {
+    let ndc = vec2<f32>(
+        position.x / size.x * 2.0 - 1.0,
+        1.0 - position.y / size.y * 2.0,
+    );
+    return vec4<f32>(ndc, 0.0, 1.0);
+}
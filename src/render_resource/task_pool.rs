@@ -0,0 +1,172 @@
+use std::{
+    any::Any,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+};
+use thiserror::Error;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// The most threads a [`TaskPool`] starts, as pipeline compilation is mostly
+/// bound by the driver.
+const MAX_THREADS: usize = 4;
+
+/// A small pool of worker threads running the jobs spawned on it in order.
+///
+/// On `wasm32` there are no threads, and jobs run as soon as they are spawned.
+pub struct TaskPool {
+    sender: Sender<Job>,
+}
+
+impl TaskPool {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let threads = thread::available_parallelism()
+                .map_or(1, |threads| threads.get())
+                .clamp(1, MAX_THREADS);
+            let receiver = Arc::new(Mutex::new(receiver));
+            for index in 0..threads {
+                let receiver = receiver.clone();
+                thread::Builder::new()
+                    .name(format!("piet-wgpu pipeline compiler {index}"))
+                    .spawn(move || loop {
+                        let job = receiver
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .recv();
+                        // the pool was dropped
+                        let Ok(job) = job else { break };
+                        job();
+                    })
+                    .expect("failed to spawn a pipeline compiler thread");
+            }
+        }
+        #[cfg(target_arch = "wasm32")]
+        drop(receiver);
+        Self { sender }
+    }
+
+    /// Runs `job` on a worker thread, returning a task to poll for its result.
+    pub fn spawn<T: Send + 'static>(&self, job: impl FnOnce() -> T + Send + 'static) -> Task<T> {
+        let (sender, receiver) = mpsc::channel();
+        // a panicking job is reported through its task, the worker carries on
+        let job = move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(job)).map_err(TaskPanicked::new);
+            let _ = sender.send(result);
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        self.sender
+            .send(Box::new(job))
+            .expect("the pipeline compiler threads have exited");
+        #[cfg(target_arch = "wasm32")]
+        job();
+        Task {
+            receiver,
+            result: None,
+        }
+    }
+}
+
+impl Default for TaskPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The error of a [`Task`] whose job panicked.
+#[derive(Error, Debug, Clone)]
+#[error("the task panicked: {message}")]
+pub struct TaskPanicked {
+    pub message: String,
+}
+
+impl TaskPanicked {
+    fn new(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload
+                .downcast_ref::<&str>()
+                .map_or("unknown panic payload", |message| message)
+                .to_owned(),
+        };
+        Self { message }
+    }
+}
+
+/// The pending result of a job spawned on a [`TaskPool`]. Dropping the task
+/// discards the result once the job completes.
+pub struct Task<T> {
+    receiver: Receiver<Result<T, TaskPanicked>>,
+    result: Option<Result<T, TaskPanicked>>,
+}
+
+impl<T> Task<T> {
+    /// Returns the result of the job if it has completed, or an error if it
+    /// panicked.
+    ///
+    /// Once the result was returned, the task only returns errors.
+    pub fn poll(&mut self) -> Option<Result<T, TaskPanicked>> {
+        if let Some(result) = self.result.take() {
+            return Some(result);
+        }
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(TaskPanicked {
+                message: String::from("the result was already returned"),
+            })),
+        }
+    }
+
+    /// Waits for the job to complete, after which [`poll`](Self::poll)
+    /// returns its result.
+    pub fn wait(&mut self) {
+        if self.result.is_none() {
+            self.result = self.receiver.recv().ok();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Task<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Task").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tasks_complete_in_the_background() {
+        let pool = TaskPool::new();
+        let mut tasks: Vec<_> = (0..8).map(|i| pool.spawn(move || i * 2)).collect();
+        for (i, task) in tasks.iter_mut().enumerate() {
+            task.wait();
+            assert_eq!(task.poll().unwrap().unwrap(), i * 2);
+        }
+    }
+
+    #[test]
+    fn panicking_tasks_return_an_error() {
+        let pool = TaskPool::new();
+        let mut task = pool.spawn(|| -> u32 { panic!("no pipeline today") });
+        task.wait();
+        assert_eq!(
+            task.poll().unwrap().unwrap_err().message,
+            "no pipeline today"
+        );
+
+        // the worker survived
+        let mut task = pool.spawn(|| 1);
+        task.wait();
+        assert_eq!(task.poll().unwrap().unwrap(), 1);
+    }
+}
@@ -0,0 +1,307 @@
+use wgpu::TextureDescriptor;
+
+use crate::{
+    render_resource::{RenderDevice, Texture, TextureId, TextureView},
+    HashMap,
+};
+
+/// Textures that went unused for more frames than this are dropped by
+/// [`TexturePool::end_frame`].
+const MAX_UNUSED_FRAMES: usize = 3;
+
+/// A [`Texture`] handed out by the [`TexturePool`], along with a view of the
+/// whole texture.
+///
+/// The contents are undefined when it is handed out, as the allocation may
+/// have been used by an earlier pass.
+#[derive(Clone, Debug)]
+pub struct CachedTexture {
+    pub texture: Texture,
+    pub default_view: TextureView,
+}
+
+/// A request for a transient texture that is used by the passes in
+/// `first_pass..=last_pass`, see [`TexturePool::get_aliased`].
+#[derive(Clone, Debug)]
+pub struct TransientTextureRequest {
+    pub descriptor: TextureDescriptor<'static>,
+    pub first_pass: usize,
+    pub last_pass: usize,
+}
+
+/// Memory statistics of a [`TexturePool`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TexturePoolStats {
+    /// The number of textures allocated by the pool.
+    pub textures: usize,
+    /// The number of those textures that are handed out.
+    pub in_use: usize,
+    /// The estimated size of all textures allocated by the pool.
+    pub allocated_bytes: u64,
+    /// The most texture memory that was handed out at once during the last
+    /// finished frame.
+    pub peak_transient_bytes: u64,
+    /// The number of transient textures in the last finished frame that shared
+    /// their allocation with another one.
+    pub aliased: usize,
+}
+
+struct PooledTexture {
+    texture: CachedTexture,
+    size: u64,
+    taken: bool,
+    frames_since_last_use: usize,
+}
+
+/// A pool of render targets for effects that need temporary textures each
+/// frame, like blurs, layers and masks.
+///
+/// Textures are handed out by descriptor and returned to the pool by
+/// [`release`](Self::release) or at the latest by [`end_frame`](Self::end_frame),
+/// after which later requests with an equal descriptor reuse them.
+#[derive(Default)]
+pub struct TexturePool {
+    textures: HashMap<TextureDescriptor<'static>, Vec<PooledTexture>>,
+    in_use_bytes: u64,
+    peak_bytes: u64,
+    aliased: usize,
+    last_frame: TexturePoolStats,
+}
+
+impl TexturePool {
+    /// Hands out a texture matching `descriptor`, reusing a returned one if
+    /// possible.
+    pub fn get(
+        &mut self,
+        render_device: &RenderDevice,
+        descriptor: TextureDescriptor<'static>,
+    ) -> CachedTexture {
+        let textures = self.textures.entry(descriptor.clone()).or_default();
+        let pooled = match textures.iter_mut().find(|pooled| !pooled.taken) {
+            Some(pooled) => pooled,
+            None => {
+                let texture = render_device.create_texture(&descriptor);
+                let default_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                textures.push(PooledTexture {
+                    texture: CachedTexture {
+                        texture,
+                        default_view,
+                    },
+                    size: texture_size(&descriptor),
+                    taken: false,
+                    frames_since_last_use: 0,
+                });
+                textures.last_mut().unwrap()
+            }
+        };
+
+        pooled.taken = true;
+        pooled.frames_since_last_use = 0;
+        self.in_use_bytes += pooled.size;
+        self.peak_bytes = self.peak_bytes.max(self.in_use_bytes);
+        pooled.texture.clone()
+    }
+
+    /// Hands out a texture for each request, in order. Requests whose pass
+    /// ranges don't overlap and whose descriptors are equal share a texture,
+    /// so the memory of a pass's intermediate targets is reused by later passes.
+    pub fn get_aliased(
+        &mut self,
+        render_device: &RenderDevice,
+        requests: &[TransientTextureRequest],
+    ) -> Vec<CachedTexture> {
+        let (allocations, assignments) = plan_aliases(requests);
+        self.aliased += requests.len() - allocations.len();
+
+        let textures: Vec<CachedTexture> = allocations
+            .into_iter()
+            .map(|index| self.get(render_device, requests[index].descriptor.clone()))
+            .collect();
+        assignments
+            .into_iter()
+            .map(|allocation| textures[allocation].clone())
+            .collect()
+    }
+
+    /// Returns a texture to the pool before the end of the frame, so that
+    /// later passes can reuse it. Textures handed out for several requests by
+    /// [`get_aliased`](Self::get_aliased) only need to be released once.
+    pub fn release(&mut self, texture: &CachedTexture) {
+        if let Some(pooled) = self.find_mut(texture.texture.id()) {
+            if pooled.taken {
+                pooled.taken = false;
+                let size = pooled.size;
+                self.in_use_bytes -= size;
+            }
+        }
+    }
+
+    /// Returns all textures to the pool and drops those that have not been
+    /// used for a few frames. Call this once the frame has been submitted.
+    pub fn end_frame(&mut self) {
+        for textures in self.textures.values_mut() {
+            for pooled in textures.iter_mut() {
+                if !pooled.taken {
+                    pooled.frames_since_last_use += 1;
+                }
+                pooled.taken = false;
+            }
+            textures.retain(|pooled| pooled.frames_since_last_use < MAX_UNUSED_FRAMES);
+        }
+        self.textures.retain(|_, textures| !textures.is_empty());
+
+        self.last_frame = TexturePoolStats {
+            peak_transient_bytes: self.peak_bytes,
+            aliased: self.aliased,
+            ..self.stats()
+        };
+        self.in_use_bytes = 0;
+        self.peak_bytes = 0;
+        self.aliased = 0;
+    }
+
+    /// Returns the current allocations and the peak usage of the last frame.
+    pub fn stats(&self) -> TexturePoolStats {
+        let pooled = || self.textures.values().flatten();
+        TexturePoolStats {
+            textures: pooled().count(),
+            in_use: pooled().filter(|pooled| pooled.taken).count(),
+            allocated_bytes: pooled().map(|pooled| pooled.size).sum(),
+            ..self.last_frame
+        }
+    }
+
+    fn find_mut(&mut self, id: TextureId) -> Option<&mut PooledTexture> {
+        self.textures
+            .values_mut()
+            .flatten()
+            .find(|pooled| pooled.texture.texture.id() == id)
+    }
+}
+
+/// Assigns the requests to as few allocations as possible, such that
+/// requests sharing an allocation have equal descriptors and disjoint pass
+/// ranges. Returns the request that determines each allocation's descriptor
+/// and the allocation of each request.
+fn plan_aliases(requests: &[TransientTextureRequest]) -> (Vec<usize>, Vec<usize>) {
+    let mut order: Vec<usize> = (0..requests.len()).collect();
+    order.sort_by_key(|&index| requests[index].first_pass);
+
+    // the first request of each allocation, with the last pass using it
+    let mut allocations: Vec<(usize, usize)> = Vec::new();
+    let mut assignments = vec![0; requests.len()];
+    for index in order {
+        let request = &requests[index];
+        let free = allocations.iter().position(|&(first, last_pass)| {
+            requests[first].descriptor == request.descriptor && last_pass < request.first_pass
+        });
+        assignments[index] = match free {
+            Some(allocation) => {
+                allocations[allocation].1 = request.last_pass;
+                allocation
+            }
+            None => {
+                allocations.push((index, request.last_pass));
+                allocations.len() - 1
+            }
+        };
+    }
+
+    let allocations = allocations.into_iter().map(|(first, _)| first).collect();
+    (allocations, assignments)
+}
+
+/// Estimates the memory used by a texture with all its mip levels and samples.
+pub fn texture_size(descriptor: &TextureDescriptor) -> u64 {
+    let (block_width, block_height) = descriptor.format.block_dimensions();
+    // combined depth-stencil formats have no single block size
+    let block_size = descriptor.format.block_copy_size(None).unwrap_or(4);
+
+    let size: u64 = (0..descriptor.mip_level_count)
+        .filter_map(|level| descriptor.mip_level_size(level))
+        .map(|extent| {
+            let blocks_wide = extent.width.div_ceil(block_width) as u64;
+            let blocks_high = extent.height.div_ceil(block_height) as u64;
+            blocks_wide * blocks_high * extent.depth_or_array_layers as u64
+        })
+        .sum();
+    size * block_size as u64 * descriptor.sample_count as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(width: u32, height: u32) -> TextureDescriptor<'static> {
+        TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        }
+    }
+
+    fn request(
+        descriptor: TextureDescriptor<'static>,
+        first_pass: usize,
+        last_pass: usize,
+    ) -> TransientTextureRequest {
+        TransientTextureRequest {
+            descriptor,
+            first_pass,
+            last_pass,
+        }
+    }
+
+    #[test]
+    fn aliases_disjoint_lifetimes() {
+        // scene -> blur (two ping-pong targets) -> composite
+        let requests = [
+            request(descriptor(64, 64), 0, 1),
+            request(descriptor(64, 64), 1, 2),
+            request(descriptor(64, 64), 2, 3),
+            request(descriptor(64, 64), 3, 3),
+        ];
+        let (allocations, assignments) = plan_aliases(&requests);
+        assert_eq!(allocations, [0, 1]);
+        assert_eq!(assignments, [0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn does_not_alias_overlapping_or_different_textures() {
+        let requests = [
+            request(descriptor(64, 64), 0, 2),
+            request(descriptor(32, 32), 3, 3),
+            request(descriptor(64, 64), 1, 1),
+            request(descriptor(64, 64), 2, 4),
+        ];
+        let (allocations, assignments) = plan_aliases(&requests);
+        assert_eq!(allocations, [0, 2, 1]);
+        assert_eq!(assignments, [0, 2, 1, 1]);
+    }
+
+    #[test]
+    fn texture_sizes() {
+        assert_eq!(texture_size(&descriptor(16, 8)), 16 * 8 * 4);
+
+        let mipmapped = TextureDescriptor {
+            mip_level_count: 3,
+            ..descriptor(16, 8)
+        };
+        assert_eq!(texture_size(&mipmapped), (16 * 8 + 8 * 4 + 4 * 2) * 4);
+
+        let compressed = TextureDescriptor {
+            format: wgpu::TextureFormat::Bc1RgbaUnorm,
+            ..descriptor(6, 6)
+        };
+        assert_eq!(texture_size(&compressed), 2 * 2 * 8);
+    }
+}
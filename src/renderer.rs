@@ -1,14 +1,33 @@
-use std::iter;
-use wgpu::{Queue, Surface, SurfaceConfiguration, SurfaceTarget};
+use std::{
+    cell::{RefCell, RefMut},
+    path::PathBuf,
+    sync::Arc,
+};
+use tracing::error;
+use wgpu::{Surface, SurfaceConfiguration, SurfaceTarget};
 
-use crate::render_resource::{PipelineCache, RenderDevice};
+use crate::layer::{LayerRenderContext, LayerRenderer, LayerTarget};
+use crate::raster::{PathRasterizer, PathRendering};
+use crate::render_graph::{RenderGraph, RenderGraphRunner, SlotValue};
+use crate::render_resource::{
+    PipelineCache, RenderDevice, RenderQueue, ShaderDiskCache, TexturePool, TextureView,
+};
+use crate::text::{TextRenderer, WgpuText};
 
 pub struct WgpuRenderer<'a> {
     surface: Surface<'a>,
     pub device: RenderDevice,
-    pub queue: Queue,
+    pub queue: RenderQueue,
     pub config: SurfaceConfiguration,
     pipeline_cache: PipelineCache,
+    pub(crate) text: WgpuText,
+    pub(crate) text_renderer: RefCell<TextRenderer>,
+    pub(crate) layer_renderer: RefCell<LayerRenderer>,
+    /// The path rasterizer, created when paths are drawn with compute shaders.
+    pub(crate) path_rasterizer: RefCell<Option<PathRasterizer>>,
+    compute_supported: bool,
+    render_graph: RenderGraph,
+    texture_pool: RefCell<TexturePool>,
 }
 
 impl<'a> WgpuRenderer<'a> {
@@ -36,7 +55,8 @@ impl<'a> WgpuRenderer<'a> {
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    required_features: wgpu::Features::empty(),
+                    // subpixel antialiased text is blended per channel where available
+                    required_features: adapter.features() & wgpu::Features::DUAL_SOURCE_BLENDING,
                     // WebGL doesn't support all of wgpu's features, so if
                     // we're building for the web we'll have to disable some.
                     required_limits: if cfg!(target_arch = "wasm32") {
@@ -50,6 +70,11 @@ impl<'a> WgpuRenderer<'a> {
             .await
             .unwrap();
 
+        let compute_supported = adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+
         let surface_caps = surface.get_capabilities(&adapter);
         let preferred_format = surface_caps.formats[0];
         // Shader code in this tutorial assumes an Srgb surface texture. Using a different
@@ -62,7 +87,9 @@ impl<'a> WgpuRenderer<'a> {
             .find(|f| f.is_srgb())
             .unwrap_or(surface_caps.formats[0]);
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            // some blend modes composite against a copy of the surface
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | (surface_caps.usages & wgpu::TextureUsages::COPY_SRC),
             format: surface_format,
             width,
             height,
@@ -74,13 +101,23 @@ impl<'a> WgpuRenderer<'a> {
         surface.configure(&device, &config);
 
         let render_device = RenderDevice::from(device);
+        let mut pipeline_cache = PipelineCache::new(render_device.clone());
+        let text_renderer = TextRenderer::new(&render_device, &mut pipeline_cache, config.format);
+        let layer_renderer = LayerRenderer::new(&render_device, &mut pipeline_cache, config.format);
 
         Self {
             surface,
-            device: render_device.clone(),
-            queue,
+            device: render_device,
+            queue: RenderQueue(Arc::new(queue)),
             config,
-            pipeline_cache: PipelineCache::new(render_device.clone()),
+            pipeline_cache,
+            text: WgpuText::new(),
+            text_renderer: RefCell::new(text_renderer),
+            layer_renderer: RefCell::new(layer_renderer),
+            path_rasterizer: RefCell::new(None),
+            compute_supported,
+            render_graph: RenderGraph::default(),
+            texture_pool: RefCell::new(TexturePool::default()),
         }
     }
 
@@ -92,11 +129,104 @@ impl<'a> WgpuRenderer<'a> {
         }
     }
 
+    /// The cache holding the shaders and pipelines of the renderer, to which
+    /// custom shaders are added.
+    pub fn pipeline_cache(&self) -> &PipelineCache {
+        &self.pipeline_cache
+    }
+
+    pub fn pipeline_cache_mut(&mut self) -> &mut PipelineCache {
+        &mut self.pipeline_cache
+    }
+
+    /// Sets how filled and stroked paths are drawn, returning false if the
+    /// device doesn't support `mode`, which leaves the mode unchanged.
+    ///
+    /// Set it before creating the render context, as paths drawn with the
+    /// previous mode are not rendered.
+    pub fn set_path_rendering(&mut self, mode: PathRendering) -> bool {
+        let rasterizer = self.path_rasterizer.get_mut();
+        match mode {
+            PathRendering::Tessellation => *rasterizer = None,
+            PathRendering::Compute if !self.compute_supported => return false,
+            PathRendering::Compute => {
+                if rasterizer.is_none() {
+                    *rasterizer = Some(PathRasterizer::new(&self.device, &mut self.pipeline_cache));
+                }
+            }
+        }
+        true
+    }
+
+    /// How filled and stroked paths are drawn.
+    pub fn path_rendering(&self) -> PathRendering {
+        match *self.path_rasterizer.borrow() {
+            Some(_) => PathRendering::Compute,
+            None => PathRendering::Tessellation,
+        }
+    }
+
+    /// The graph run after the built-in pass each frame. If it has an input
+    /// node, the surface texture view is passed to it.
+    pub fn render_graph(&self) -> &RenderGraph {
+        &self.render_graph
+    }
+
+    pub fn render_graph_mut(&mut self) -> &mut RenderGraph {
+        &mut self.render_graph
+    }
+
+    /// The pool of temporary render targets, which are returned to it at the
+    /// end of each frame.
+    pub fn texture_pool(&self) -> RefMut<'_, TexturePool> {
+        self.texture_pool.borrow_mut()
+    }
+
+    /// Persists the composed shaders to `directory`, so that later runs skip
+    /// composing them. Call this before the first frame is rendered, as the
+    /// built-in pipelines are created then. `None` disables the cache.
+    pub fn set_shader_cache_directory(&mut self, directory: Option<PathBuf>) {
+        self.pipeline_cache
+            .set_disk_cache(directory.map(ShaderDiskCache::new));
+    }
+
+    /// Deletes the shaders persisted by
+    /// [`set_shader_cache_directory`](Self::set_shader_cache_directory).
+    pub fn clear_shader_cache(&self) -> std::io::Result<()> {
+        match self.pipeline_cache.disk_cache() {
+            Some(disk_cache) => disk_cache.clear(),
+            None => Ok(()),
+        }
+    }
+
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
         let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let view = TextureView::from(
+            output
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default()),
+        );
+
+        self.pipeline_cache.process_queue();
+        self.text_renderer.get_mut().prepare(
+            &self.device,
+            &self.queue,
+            &self.pipeline_cache,
+            &mut self.text.atlas.borrow_mut(),
+            [self.config.width, self.config.height],
+        );
+        self.layer_renderer
+            .get_mut()
+            .prepare(&self.device, &self.queue, &self.pipeline_cache);
+        if let Some(rasterizer) = self.path_rasterizer.get_mut() {
+            rasterizer.prepare(
+                &self.device,
+                &self.queue,
+                &self.pipeline_cache,
+                self.layer_renderer.get_mut().stack.layers(),
+                [self.config.width, self.config.height],
+            );
+        }
 
         let mut encoder = self
             .device
@@ -104,15 +234,109 @@ impl<'a> WgpuRenderer<'a> {
                 label: Some("Render Encoder"),
             });
 
-        self.queue.submit(iter::once(encoder.finish()));
+        self.layer_renderer.borrow().render(
+            &mut LayerRenderContext {
+                device: &self.device,
+                pipeline_cache: &self.pipeline_cache,
+                text_renderer: &self.text_renderer.borrow(),
+                rasterizer: self.path_rasterizer.borrow().as_ref(),
+                texture_pool: self.texture_pool.get_mut(),
+                viewport: [self.config.width, self.config.height],
+            },
+            &mut encoder,
+            LayerTarget {
+                view: &view,
+                texture: (self.config.usage)
+                    .contains(wgpu::TextureUsages::COPY_SRC)
+                    .then_some(&output.texture),
+            },
+            wgpu::Color::WHITE,
+        );
+
+        let mut command_buffers = vec![encoder.finish()];
+        command_buffers.extend(self.run_render_graph(view));
+        self.queue.submit(command_buffers);
+        self.texture_pool.get_mut().end_frame();
         output.present();
 
         Ok(())
     }
 
+    fn run_render_graph(&mut self, view: TextureView) -> Vec<wgpu::CommandBuffer> {
+        let mut graph = std::mem::take(&mut self.render_graph);
+        graph.update(self);
+
+        let inputs = if graph.get_input_node().is_some() {
+            vec![SlotValue::TextureView(view)]
+        } else {
+            Vec::new()
+        };
+        let command_buffers = match RenderGraphRunner::run(&graph, self, &inputs) {
+            Ok(command_buffers) => command_buffers,
+            Err(err) => {
+                error!("Error running render graph: {err}");
+                Vec::new()
+            }
+        };
+
+        self.render_graph = graph;
+        command_buffers
+    }
+
     pub fn input(&mut self) -> bool {
         false
     }
 
     pub fn update(&mut self) {}
 }
+
+/// The context passed to render graph nodes, which records their commands.
+///
+/// The command encoder is created when a node first asks for it, so nodes
+/// that only submit prerecorded command buffers don't create an empty one.
+pub struct RenderContext {
+    device: RenderDevice,
+    command_encoder: Option<wgpu::CommandEncoder>,
+    command_buffers: Vec<wgpu::CommandBuffer>,
+}
+
+impl RenderContext {
+    pub fn new(device: RenderDevice) -> Self {
+        Self {
+            device,
+            command_encoder: None,
+            command_buffers: Vec::new(),
+        }
+    }
+
+    pub fn render_device(&self) -> &RenderDevice {
+        &self.device
+    }
+
+    /// The current command encoder, created on first use.
+    pub fn command_encoder(&mut self) -> &mut wgpu::CommandEncoder {
+        self.command_encoder.get_or_insert_with(|| {
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor::default())
+        })
+    }
+
+    /// Appends a command buffer recorded elsewhere, keeping it ordered after
+    /// the commands encoded so far.
+    pub fn add_command_buffer(&mut self, command_buffer: wgpu::CommandBuffer) {
+        self.flush_encoder();
+        self.command_buffers.push(command_buffer);
+    }
+
+    /// Finishes the current encoder and returns every recorded command buffer.
+    pub fn finish(mut self) -> Vec<wgpu::CommandBuffer> {
+        self.flush_encoder();
+        self.command_buffers
+    }
+
+    fn flush_encoder(&mut self) {
+        if let Some(encoder) = self.command_encoder.take() {
+            self.command_buffers.push(encoder.finish());
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use etagere::{size2, BucketedAtlasAllocator};
+use swash::{
+    scale::{image::Content, Render, ScaleContext, Source, StrikeWith},
+    zeno::{Format, Vector},
+    GlyphId,
+};
+use tracing::warn;
+
+use super::font::Font;
+use crate::HashMap;
+
+/// The size pages start at.
+const INITIAL_PAGE_SIZE: u32 = 512;
+/// The size pages may grow to, which is the largest texture WebGL2 guarantees.
+const MAX_PAGE_SIZE: u32 = 2048;
+/// Space kept empty around each glyph, so that filtering does not bleed into
+/// its neighbours.
+const PADDING: i32 = 1;
+/// The number of horizontal positions a glyph is rasterized at within a
+/// pixel when subpixel positioning is enabled.
+pub(crate) const SUBPIXEL_STEPS: u8 = 4;
+
+/// How glyph outlines are antialiased.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextAntialiasing {
+    /// A single coverage value per pixel.
+    #[default]
+    Grayscale,
+    /// A coverage value per color channel, for LCD panels whose subpixels are
+    /// ordered red, green, blue from left to right.
+    SubpixelRgb,
+    /// A coverage value per color channel, for LCD panels whose subpixels are
+    /// ordered blue, green, red from left to right.
+    SubpixelBgr,
+}
+
+/// Options for rasterizing glyphs, set with
+/// [`WgpuText::set_text_rendering`](super::WgpuText::set_text_rendering).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextRendering {
+    pub antialiasing: TextAntialiasing,
+    /// Places glyphs at a quarter of a pixel horizontally instead of whole
+    /// pixels, when text is not rotated or skewed.
+    pub subpixel_positioning: bool,
+}
+
+/// How the texels of a glyph are interpreted when it is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum GlyphContent {
+    /// Coverage in a single channel, tinted with the text color.
+    Mask,
+    /// Unpremultiplied RGBA, such as a colour emoji, drawn as is.
+    Color,
+    /// Coverage per color channel, tinted with the text color.
+    Subpixel,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: u64,
+    glyph: GlyphId,
+    /// The bits of the rasterized size, in pixels per em.
+    size: u32,
+    /// The horizontal offset, in steps of 1 / [`SUBPIXEL_STEPS`] pixel.
+    offset: u8,
+}
+
+/// A glyph which has been rasterized into the atlas.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AtlasGlyph {
+    pub content: GlyphContent,
+    /// Position of the glyph in its page, in texels.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Offset of the left edge of the image from the glyph origin.
+    pub left: i32,
+    /// Offset of the top edge of the image above the baseline.
+    pub top: i32,
+}
+
+/// A texture worth of glyph images, kept in system memory until it is uploaded.
+pub(crate) struct AtlasPage {
+    allocator: BucketedAtlasAllocator,
+    channels: usize,
+    pub size: u32,
+    pub data: Vec<u8>,
+    /// Set when the data changed since the page was last uploaded.
+    pub dirty: bool,
+}
+
+impl AtlasPage {
+    fn new(channels: usize) -> Self {
+        let size = INITIAL_PAGE_SIZE;
+        Self {
+            allocator: BucketedAtlasAllocator::new(size2(size as i32, size as i32)),
+            channels,
+            size,
+            data: vec![0; (size * size) as usize * channels],
+            dirty: true,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.allocator.clear();
+        self.data.fill(0);
+        self.dirty = true;
+    }
+
+    /// Doubles the page size, keeping the glyphs in place.
+    fn grow(&mut self) -> bool {
+        if self.size >= MAX_PAGE_SIZE {
+            return false;
+        }
+        let old_size = self.size as usize;
+        let size = self.size * 2;
+        let mut data = vec![0; (size * size) as usize * self.channels];
+        let old_stride = old_size * self.channels;
+        let stride = size as usize * self.channels;
+        for (row, old_row) in data
+            .chunks_exact_mut(stride)
+            .zip(self.data.chunks_exact(old_stride))
+        {
+            row[..old_stride].copy_from_slice(old_row);
+        }
+        self.allocator.grow(size2(size as i32, size as i32));
+        self.size = size;
+        self.data = data;
+        self.dirty = true;
+        true
+    }
+
+    /// Copies an image into the page, returning its position.
+    fn insert(&mut self, width: u32, height: u32, image: &[u8]) -> Option<(u32, u32)> {
+        let requested = size2(width as i32 + PADDING * 2, height as i32 + PADDING * 2);
+        let allocation = loop {
+            if let Some(allocation) = self.allocator.allocate(requested) {
+                break allocation;
+            }
+            if !self.grow() {
+                return None;
+            }
+        };
+        let x = (allocation.rectangle.min.x + PADDING) as usize;
+        let y = (allocation.rectangle.min.y + PADDING) as usize;
+        let stride = self.size as usize * self.channels;
+        let row_len = width as usize * self.channels;
+        for (row, src) in image.chunks_exact(row_len).enumerate() {
+            let start = (y + row) * stride + x * self.channels;
+            self.data[start..start + row_len].copy_from_slice(src);
+        }
+        self.dirty = true;
+        Some((x as u32, y as u32))
+    }
+}
+
+/// Rasterizes glyphs on demand and packs them into an alpha page for outlines,
+/// an RGBA page for colour glyphs (COLR outlines and CBDT/sbix bitmaps) and an
+/// RGBA page for outlines with subpixel antialiasing.
+pub(crate) struct GlyphAtlas {
+    scale_context: ScaleContext,
+    glyphs: HashMap<GlyphKey, Option<AtlasGlyph>>,
+    options: TextRendering,
+    pub mask: AtlasPage,
+    pub color: AtlasPage,
+    pub subpixel: AtlasPage,
+}
+
+impl GlyphAtlas {
+    pub(crate) fn new() -> Self {
+        Self {
+            scale_context: ScaleContext::new(),
+            glyphs: Default::default(),
+            options: TextRendering::default(),
+            mask: AtlasPage::new(1),
+            color: AtlasPage::new(4),
+            subpixel: AtlasPage::new(4),
+        }
+    }
+
+    pub(crate) fn options(&self) -> TextRendering {
+        self.options
+    }
+
+    /// Changes how glyphs are rasterized, dropping the glyphs rasterized so far.
+    pub(crate) fn set_options(&mut self, options: TextRendering) {
+        if options != self.options {
+            self.options = options;
+            self.clear();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.glyphs.clear();
+        self.mask.clear();
+        self.color.clear();
+        self.subpixel.clear();
+    }
+
+    /// Returns the atlas entry of a glyph rasterized at `size` pixels per em and
+    /// shifted right by `offset` steps of 1 / [`SUBPIXEL_STEPS`] pixel, or `None`
+    /// if the glyph has no image, such as a space.
+    pub(crate) fn glyph(
+        &mut self,
+        font: &Font,
+        glyph: GlyphId,
+        size: f32,
+        offset: u8,
+    ) -> Option<AtlasGlyph> {
+        let key = GlyphKey {
+            font: font.key(),
+            glyph,
+            size: size.to_bits(),
+            offset,
+        };
+        if let Some(entry) = self.glyphs.get(&key) {
+            return *entry;
+        }
+        let entry = self.rasterize(font, glyph, size, offset);
+        self.glyphs.insert(key, entry);
+        entry
+    }
+
+    fn rasterize(
+        &mut self,
+        font: &Font,
+        glyph: GlyphId,
+        size: f32,
+        offset: u8,
+    ) -> Option<AtlasGlyph> {
+        let mut scaler = self
+            .scale_context
+            .builder(font.font_ref())
+            .size(size)
+            .hint(false)
+            .build();
+        let image = Render::new(&[
+            Source::ColorOutline(0),
+            Source::ColorBitmap(StrikeWith::BestFit),
+            Source::Outline,
+        ])
+        .format(match self.options.antialiasing {
+            TextAntialiasing::Grayscale => Format::Alpha,
+            TextAntialiasing::SubpixelRgb => Format::Subpixel,
+            TextAntialiasing::SubpixelBgr => Format::subpixel_bgra(),
+        })
+        .offset(Vector::new(offset as f32 / SUBPIXEL_STEPS as f32, 0.0))
+        .render(&mut scaler, glyph)?;
+        let placement = image.placement;
+        if placement.width == 0 || placement.height == 0 {
+            return None;
+        }
+
+        let content = match image.content {
+            Content::Color => GlyphContent::Color,
+            Content::Mask => GlyphContent::Mask,
+            Content::SubpixelMask => GlyphContent::Subpixel,
+        };
+        let inserted = {
+            let page = self.page(content);
+            page.insert(placement.width, placement.height, &image.data)
+        };
+        let (x, y) = match inserted {
+            Some(position) => position,
+            None => {
+                // the page is full at its largest size: start over, glyphs
+                // still in use are rasterized again as they are drawn
+                warn!("glyph atlas is full, evicting all glyphs");
+                self.clear();
+                self.page(content)
+                    .insert(placement.width, placement.height, &image.data)?
+            }
+        };
+        Some(AtlasGlyph {
+            content,
+            x,
+            y,
+            width: placement.width,
+            height: placement.height,
+            left: placement.left,
+            top: placement.top,
+        })
+    }
+
+    fn page(&mut self, content: GlyphContent) -> &mut AtlasPage {
+        match content {
+            GlyphContent::Mask => &mut self.mask,
+            GlyphContent::Color => &mut self.color,
+            GlyphContent::Subpixel => &mut self.subpixel,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::font::{FontCache, FontQuery};
+    use piet::{FontStyle, FontWeight};
+
+    const FONT: &[u8] = include_bytes!("../../tests/assets/Anaheim-Regular.ttf");
+
+    #[test]
+    fn lcd_antialiasing_rasterizes_subpixel_coverage() {
+        let mut fonts = FontCache::without_system_fonts();
+        let family = fonts.load_font(FONT).unwrap();
+        let font = fonts
+            .font(&FontQuery {
+                family,
+                weight: FontWeight::REGULAR,
+                style: FontStyle::Regular,
+            })
+            .unwrap();
+        let glyph = font.font_ref().charmap().map('a');
+
+        let mut atlas = GlyphAtlas::new();
+        let mask = atlas.glyph(&font, glyph, 16.0, 0).unwrap();
+        assert_eq!(mask.content, GlyphContent::Mask);
+        for antialiasing in [TextAntialiasing::SubpixelRgb, TextAntialiasing::SubpixelBgr] {
+            atlas.set_options(TextRendering {
+                antialiasing,
+                subpixel_positioning: true,
+            });
+            let lcd = atlas.glyph(&font, glyph, 16.0, 0).unwrap();
+            assert_eq!(lcd.content, GlyphContent::Subpixel);
+            // the offsets are rasterized apart
+            let shifted = atlas.glyph(&font, glyph, 16.0, 2).unwrap();
+            assert_ne!((shifted.x, shifted.y), (lcd.x, lcd.y));
+        }
+    }
+}
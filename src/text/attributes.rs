@@ -0,0 +1,297 @@
+use piet::{Color, FontFamily, FontStyle, FontWeight, TextAttribute};
+use std::{
+    hash::{Hash, Hasher},
+    ops::Range,
+};
+
+/// The resolved attributes of a run of text.
+///
+/// Two adjacent ranges with equal styles are shaped as a single run.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RunStyle {
+    pub font: FontFamily,
+    pub size: f64,
+    pub weight: FontWeight,
+    pub style: FontStyle,
+    pub color: Color,
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct Attributes {
+    pub(crate) defaults: piet::util::LayoutDefaults,
+    color: Vec<Span<Color>>,
+    font: Vec<Span<FontFamily>>,
+    size: Vec<Span<f64>>,
+    weight: Vec<Span<FontWeight>>,
+    style: Vec<Span<FontStyle>>,
+    underline: Vec<Span<bool>>,
+    strikethrough: Vec<Span<bool>>,
+}
+
+impl Clone for Attributes {
+    fn clone(&self) -> Self {
+        // `LayoutDefaults` is not `Clone`, so it is rebuilt attribute by attribute
+        let mut defaults = piet::util::LayoutDefaults::default();
+        defaults.set(TextAttribute::FontFamily(self.defaults.font.clone()));
+        defaults.set(TextAttribute::FontSize(self.defaults.font_size));
+        defaults.set(TextAttribute::Weight(self.defaults.weight));
+        defaults.set(TextAttribute::TextColor(self.defaults.fg_color));
+        defaults.set(TextAttribute::Style(self.defaults.style));
+        defaults.set(TextAttribute::Underline(self.defaults.underline));
+        defaults.set(TextAttribute::Strikethrough(self.defaults.strikethrough));
+        Self {
+            defaults,
+            color: self.color.clone(),
+            font: self.font.clone(),
+            size: self.size.clone(),
+            weight: self.weight.clone(),
+            style: self.style.clone(),
+            underline: self.underline.clone(),
+            strikethrough: self.strikethrough.clone(),
+        }
+    }
+}
+
+/// Sizes are compared and hashed by their bits, so that `Eq` holds for NaN
+/// and agrees with `Hash` for `0.0` and `-0.0`.
+fn size_bits(spans: &[Span<f64>]) -> impl Iterator<Item = (u64, &Range<usize>)> {
+    spans
+        .iter()
+        .map(|span| (span.payload.to_bits(), &span.range))
+}
+
+impl PartialEq for Attributes {
+    fn eq(&self, other: &Self) -> bool {
+        let (defaults, other_defaults) = (&self.defaults, &other.defaults);
+        defaults.font == other_defaults.font
+            && defaults.font_size.to_bits() == other_defaults.font_size.to_bits()
+            && defaults.weight == other_defaults.weight
+            && defaults.fg_color == other_defaults.fg_color
+            && defaults.style == other_defaults.style
+            && defaults.underline == other_defaults.underline
+            && defaults.strikethrough == other_defaults.strikethrough
+            && self.color == other.color
+            && self.font == other.font
+            && size_bits(&self.size).eq(size_bits(&other.size))
+            && self.weight == other.weight
+            && self.style == other.style
+            && self.underline == other.underline
+            && self.strikethrough == other.strikethrough
+    }
+}
+
+impl Eq for Attributes {}
+
+impl Hash for Attributes {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let defaults = &self.defaults;
+        defaults.font.hash(state);
+        defaults.font_size.to_bits().hash(state);
+        defaults.weight.hash(state);
+        defaults.fg_color.hash(state);
+        defaults.style.hash(state);
+        defaults.underline.hash(state);
+        defaults.strikethrough.hash(state);
+        self.color.hash(state);
+        self.font.hash(state);
+        for size in size_bits(&self.size) {
+            size.hash(state);
+        }
+        self.weight.hash(state);
+        self.style.hash(state);
+        self.underline.hash(state);
+        self.strikethrough.hash(state);
+    }
+}
+
+/// during construction, `Span`s represent font attributes that have been applied
+/// to ranges of the text; these are split into shaping runs as the layout is built.
+#[derive(Clone, PartialEq, Hash)]
+struct Span<T> {
+    payload: T,
+    range: Range<usize>,
+}
+
+impl<T> Span<T> {
+    fn new(payload: T, range: Range<usize>) -> Self {
+        Span { payload, range }
+    }
+
+    fn range_end(&self) -> usize {
+        self.range.end
+    }
+}
+
+/// Returns the payload of the last span containing `index`, as later
+/// attributes take precedence over earlier ones.
+fn lookup<T>(spans: &[Span<T>], index: usize) -> Option<&T> {
+    spans
+        .iter()
+        .rev()
+        .find(|span| span.range.contains(&index))
+        .map(|span| &span.payload)
+}
+
+impl Attributes {
+    pub(crate) fn add(&mut self, range: Range<usize>, attr: TextAttribute) {
+        match attr {
+            TextAttribute::FontFamily(font) => self.font.push(Span::new(font, range)),
+            TextAttribute::FontSize(size) => self.size.push(Span::new(size, range)),
+            TextAttribute::Weight(weight) => self.weight.push(Span::new(weight, range)),
+            TextAttribute::TextColor(color) => self.color.push(Span::new(color, range)),
+            TextAttribute::Style(style) => self.style.push(Span::new(style, range)),
+            TextAttribute::Underline(underline) => self.underline.push(Span::new(underline, range)),
+            TextAttribute::Strikethrough(strikethrough) => {
+                self.strikethrough.push(Span::new(strikethrough, range))
+            }
+        }
+    }
+
+    /// Overrides the color of the whole text, dropping any color spans.
+    pub(crate) fn set_color(&mut self, color: Color) {
+        self.color.clear();
+        self.defaults.fg_color = color;
+    }
+
+    pub(crate) fn color(&self, index: usize) -> &Color {
+        lookup(&self.color, index).unwrap_or(&self.defaults.fg_color)
+    }
+
+    pub(crate) fn size(&self, index: usize) -> f64 {
+        lookup(&self.size, index)
+            .copied()
+            .unwrap_or(self.defaults.font_size)
+    }
+
+    pub(crate) fn italic(&self, index: usize) -> bool {
+        matches!(
+            lookup(&self.style, index)
+                .copied()
+                .unwrap_or(self.defaults.style),
+            FontStyle::Italic
+        )
+    }
+
+    pub(crate) fn font(&self, index: usize) -> FontFamily {
+        lookup(&self.font, index)
+            .unwrap_or(&self.defaults.font)
+            .clone()
+    }
+
+    pub(crate) fn font_weight(&self, index: usize) -> FontWeight {
+        lookup(&self.weight, index)
+            .copied()
+            .unwrap_or(self.defaults.weight)
+    }
+
+    pub(crate) fn underline(&self, index: usize) -> bool {
+        lookup(&self.underline, index)
+            .copied()
+            .unwrap_or(self.defaults.underline)
+    }
+
+    pub(crate) fn strikethrough(&self, index: usize) -> bool {
+        lookup(&self.strikethrough, index)
+            .copied()
+            .unwrap_or(self.defaults.strikethrough)
+    }
+
+    /// Resolves every attribute at `index`.
+    pub(crate) fn run_style(&self, index: usize) -> RunStyle {
+        RunStyle {
+            font: self.font(index),
+            size: self.size(index),
+            weight: self.font_weight(index),
+            style: if self.italic(index) {
+                FontStyle::Italic
+            } else {
+                FontStyle::Regular
+            },
+            color: *self.color(index),
+            underline: self.underline(index),
+            strikethrough: self.strikethrough(index),
+        }
+    }
+
+    /// Splits `range` at every attribute boundary that falls inside it, merging
+    /// neighbouring pieces whose resolved style is the same.
+    pub(crate) fn runs(&self, range: Range<usize>) -> Vec<(Range<usize>, RunStyle)> {
+        let mut boundaries = vec![range.start, range.end];
+        let spans = span_bounds(&self.color)
+            .chain(span_bounds(&self.font))
+            .chain(span_bounds(&self.size))
+            .chain(span_bounds(&self.weight))
+            .chain(span_bounds(&self.style))
+            .chain(span_bounds(&self.underline))
+            .chain(span_bounds(&self.strikethrough));
+        for (start, end) in spans {
+            for offset in [start, end] {
+                if offset > range.start && offset < range.end {
+                    boundaries.push(offset);
+                }
+            }
+        }
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        let mut runs: Vec<(Range<usize>, RunStyle)> = Vec::new();
+        for window in boundaries.windows(2) {
+            let (start, end) = (window[0], window[1]);
+            let style = self.run_style(start);
+            match runs.last_mut() {
+                Some((last_range, last_style)) if *last_style == style => {
+                    last_range.end = end;
+                }
+                _ => runs.push((start..end, style)),
+            }
+        }
+        runs
+    }
+}
+
+fn span_bounds<T>(spans: &[Span<T>]) -> impl Iterator<Item = (usize, usize)> + '_ {
+    spans
+        .iter()
+        .map(|span| (span.range.start, span.range_end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_split_at_attribute_boundaries() {
+        let mut attrs = Attributes::default();
+        attrs.add(2..6, TextAttribute::Underline(true));
+        attrs.add(4..8, TextAttribute::Weight(FontWeight::BOLD));
+        // a span repeating the style of its neighbours doesn't split the run
+        attrs.add(8..10, TextAttribute::Underline(false));
+
+        let runs = attrs.runs(0..12);
+        let ranges: Vec<_> = runs.iter().map(|(range, _)| range.clone()).collect();
+        assert_eq!(ranges, [0..2, 2..4, 4..6, 6..8, 8..12]);
+        let (underline, bold): (Vec<_>, Vec<_>) = runs
+            .iter()
+            .map(|(_, style)| (style.underline, style.weight == FontWeight::BOLD))
+            .unzip();
+        assert_eq!(underline, [false, true, true, false, false]);
+        assert_eq!(bold, [false, false, true, true, false]);
+
+        // boundaries outside of the range are left out
+        let runs = attrs.runs(3..5);
+        let ranges: Vec<_> = runs.iter().map(|(range, _)| range.clone()).collect();
+        assert_eq!(ranges, [3..4, 4..5]);
+    }
+
+    #[test]
+    fn later_spans_take_precedence() {
+        let mut attrs = Attributes::default();
+        attrs.add(0..10, TextAttribute::FontSize(20.0));
+        attrs.add(5..10, TextAttribute::FontSize(30.0));
+        assert_eq!(attrs.size(2), 20.0);
+        assert_eq!(attrs.size(7), 30.0);
+        assert_eq!(attrs.size(12), attrs.defaults.font_size);
+    }
+}
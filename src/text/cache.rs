@@ -0,0 +1,173 @@
+use piet::TextAlignment;
+use std::rc::Rc;
+
+use super::{attributes::Attributes, WgpuTextLayout};
+use crate::{Hashed, PreHashMap};
+
+/// The number of frames a layout is kept for after it was last built.
+const DEFAULT_MAX_AGE: u64 = 60;
+
+/// Everything a layout is built from.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct LayoutKey {
+    pub text: Rc<str>,
+    pub attrs: Rc<Attributes>,
+    /// The bits of the maximum width.
+    pub max_width: u64,
+    pub alignment: u8,
+    pub is_mono: bool,
+    pub tab_width: usize,
+    /// The bits of the bounds.
+    pub bounds: Option<[u64; 2]>,
+}
+
+impl LayoutKey {
+    pub(crate) fn new(
+        text: Rc<str>,
+        attrs: Rc<Attributes>,
+        max_width: f64,
+        alignment: TextAlignment,
+        is_mono: bool,
+        tab_width: usize,
+        bounds: Option<[f64; 2]>,
+    ) -> Hashed<Self> {
+        Hashed::new(Self {
+            text,
+            attrs,
+            max_width: max_width.to_bits(),
+            alignment: match alignment {
+                TextAlignment::Start => 0,
+                TextAlignment::End => 1,
+                TextAlignment::Center => 2,
+                TextAlignment::Justified => 3,
+            },
+            is_mono,
+            tab_width,
+            bounds: bounds.map(|bounds| bounds.map(f64::to_bits)),
+        })
+    }
+}
+
+/// Counters describing how well the layout cache performs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LayoutCacheStats {
+    /// Layouts which were found in the cache.
+    pub hits: u64,
+    /// Layouts which had to be shaped.
+    pub misses: u64,
+    /// Layouts which were dropped after going unused.
+    pub evictions: u64,
+    /// Layouts currently in the cache.
+    pub entries: usize,
+}
+
+struct CachedLayout {
+    layout: WgpuTextLayout,
+    last_used: u64,
+}
+
+/// Keeps the layouts built during recent frames, so that text which is drawn
+/// every frame is only shaped once.
+pub(crate) struct LayoutCache {
+    layouts: PreHashMap<LayoutKey, CachedLayout>,
+    frame: u64,
+    pub max_age: u64,
+    stats: LayoutCacheStats,
+}
+
+impl LayoutCache {
+    pub(crate) fn new() -> Self {
+        Self {
+            layouts: Default::default(),
+            frame: 0,
+            max_age: DEFAULT_MAX_AGE,
+            stats: LayoutCacheStats::default(),
+        }
+    }
+
+    /// Returns a layout built with `key`, and marks it as used in this frame.
+    pub(crate) fn get(&mut self, key: &Hashed<LayoutKey>) -> Option<WgpuTextLayout> {
+        match self.layouts.get_mut(key) {
+            Some(cached) => {
+                cached.last_used = self.frame;
+                self.stats.hits += 1;
+                Some(cached.layout.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub(crate) fn insert(&mut self, key: Hashed<LayoutKey>, layout: WgpuTextLayout) {
+        self.layouts.insert(
+            key,
+            CachedLayout {
+                layout,
+                last_used: self.frame,
+            },
+        );
+    }
+
+    /// Drops every layout, such as when a font is loaded and the layouts may
+    /// resolve to different faces.
+    pub(crate) fn clear(&mut self) {
+        self.layouts.clear();
+    }
+
+    /// Starts a new frame, evicting the layouts which have not been used for
+    /// more than [`max_age`](Self::max_age) frames.
+    pub(crate) fn end_frame(&mut self) {
+        self.frame += 1;
+        let (frame, max_age) = (self.frame, self.max_age);
+        let before = self.layouts.len();
+        self.layouts
+            .retain(|_, cached| frame - cached.last_used <= max_age);
+        self.stats.evictions += (before - self.layouts.len()) as u64;
+    }
+
+    pub(crate) fn stats(&self) -> LayoutCacheStats {
+        LayoutCacheStats {
+            entries: self.layouts.len(),
+            ..self.stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piet::TextAttribute;
+
+    fn key(attrs: &Attributes) -> Hashed<LayoutKey> {
+        LayoutKey::new(
+            "text".into(),
+            Rc::new(attrs.clone()),
+            f64::INFINITY,
+            TextAlignment::Start,
+            false,
+            4,
+            None,
+        )
+    }
+
+    #[test]
+    fn equal_attributes_give_equal_keys() {
+        for size in [12.0, 0.0, -0.0, f64::NAN] {
+            let mut attrs = Attributes::default();
+            attrs.defaults.set(TextAttribute::FontSize(size));
+            attrs.add(0..2, TextAttribute::FontSize(size));
+            let (a, b) = (key(&attrs), key(&attrs));
+            assert!(a == b, "{size}");
+            assert_eq!(a.hash(), b.hash(), "{size}");
+        }
+
+        // keys which hash differently are not equal
+        let mut zero = Attributes::default();
+        zero.add(0..2, TextAttribute::FontSize(0.0));
+        let mut negative_zero = Attributes::default();
+        negative_zero.add(0..2, TextAttribute::FontSize(-0.0));
+        assert!(key(&zero) != key(&negative_zero));
+    }
+}
@@ -0,0 +1,424 @@
+use piet::{FontFamily, FontFamilyInner, FontStyle, FontWeight};
+use std::{ops::Range, sync::Arc};
+use swash::{
+    shape::ShapeContext,
+    text::{
+        cluster::{CharCluster, Emoji, Parser, Status, Token},
+        Script,
+    },
+    CacheKey, FontRef, Metrics,
+};
+use tracing::warn;
+
+use crate::HashMap;
+
+/// A font face which has been loaded into memory.
+///
+/// Cloning is cheap, the font data is shared.
+#[derive(Clone)]
+pub(crate) struct Font {
+    data: Arc<Vec<u8>>,
+    offset: u32,
+    key: CacheKey,
+}
+
+impl Font {
+    fn new(data: Vec<u8>, index: usize) -> Option<Self> {
+        let font = FontRef::from_index(&data, index)?;
+        let (offset, key) = (font.offset, font.key);
+        Some(Self {
+            data: Arc::new(data),
+            offset,
+            key,
+        })
+    }
+
+    /// Borrows the face as a swash [`FontRef`].
+    pub(crate) fn font_ref(&self) -> FontRef<'_> {
+        FontRef {
+            data: &self.data,
+            offset: self.offset,
+            key: self.key,
+        }
+    }
+
+    /// Returns the metrics of this face scaled to `size` pixels per em.
+    pub(crate) fn metrics(&self, size: f32) -> Metrics {
+        self.font_ref().metrics(&[]).scale(size)
+    }
+
+    /// Returns the advance of `c` at `size` pixels per em, if the face maps it.
+    pub(crate) fn advance(&self, c: char, size: f32) -> Option<f32> {
+        let font = self.font_ref();
+        let glyph = font.charmap().map(c);
+        (glyph != 0).then(|| font.glyph_metrics(&[]).scale(size).advance_width(glyph))
+    }
+
+    /// A key identifying the face, used to cache its glyphs.
+    pub(crate) fn key(&self) -> u64 {
+        self.key.value()
+    }
+}
+
+impl PartialEq for Font {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+/// The parameters used to pick a face out of the font database.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct FontQuery {
+    pub family: FontFamily,
+    pub weight: FontWeight,
+    pub style: FontStyle,
+}
+
+/// The families tried, in order, for characters the requested font does not cover.
+///
+/// Emoji are looked up in the emoji families first, other characters in the
+/// families registered for their script, and then both go through the general
+/// chain. A character no family covers is drawn with the `.notdef` glyph of the
+/// requested font.
+#[derive(Clone, Debug)]
+pub struct FontFallback {
+    pub families: Vec<FontFamily>,
+    pub scripts: HashMap<Script, Vec<FontFamily>>,
+    pub emoji: Vec<FontFamily>,
+}
+
+fn named(families: &[&str]) -> Vec<FontFamily> {
+    families
+        .iter()
+        .map(|name| FontFamily::new_unchecked(*name))
+        .collect()
+}
+
+impl Default for FontFallback {
+    fn default() -> Self {
+        let chinese = named(&[
+            "Noto Sans CJK SC",
+            "Source Han Sans SC",
+            "PingFang SC",
+            "Microsoft YaHei",
+            "WenQuanYi Micro Hei",
+        ]);
+        let japanese = named(&[
+            "Noto Sans CJK JP",
+            "Source Han Sans JP",
+            "Hiragino Sans",
+            "Yu Gothic",
+            "Meiryo",
+        ]);
+        let korean = named(&[
+            "Noto Sans CJK KR",
+            "Source Han Sans KR",
+            "Apple SD Gothic Neo",
+            "Malgun Gothic",
+        ]);
+        let mut scripts = HashMap::default();
+        scripts.insert(Script::Han, chinese.clone());
+        scripts.insert(Script::Bopomofo, chinese);
+        scripts.insert(Script::Hiragana, japanese.clone());
+        scripts.insert(Script::Katakana, japanese);
+        scripts.insert(Script::Hangul, korean);
+        scripts.insert(
+            Script::Arabic,
+            named(&["Noto Sans Arabic", "Geeza Pro", "Segoe UI", "DejaVu Sans"]),
+        );
+        scripts.insert(
+            Script::Hebrew,
+            named(&[
+                "Noto Sans Hebrew",
+                "Arial Hebrew",
+                "Segoe UI",
+                "DejaVu Sans",
+            ]),
+        );
+        scripts.insert(
+            Script::Devanagari,
+            named(&["Noto Sans Devanagari", "Kohinoor Devanagari", "Nirmala UI"]),
+        );
+        scripts.insert(
+            Script::Thai,
+            named(&["Noto Sans Thai", "Thonburi", "Leelawadee UI"]),
+        );
+        Self {
+            families: named(&[
+                "Noto Sans",
+                "DejaVu Sans",
+                "Arial Unicode MS",
+                "Noto Sans Symbols",
+                "Noto Sans Symbols 2",
+                "Segoe UI Symbol",
+            ]),
+            scripts,
+            emoji: named(&[
+                "Noto Color Emoji",
+                "Apple Color Emoji",
+                "Segoe UI Emoji",
+                "Twemoji Mozilla",
+                "Noto Emoji",
+            ]),
+        }
+    }
+}
+
+/// The font database shared by every [`WgpuText`](super::WgpuText) handle.
+pub(crate) struct FontCache {
+    db: fontdb::Database,
+    system_fonts_loaded: bool,
+    faces: HashMap<fontdb::ID, Font>,
+    queries: HashMap<FontQuery, Option<fontdb::ID>>,
+    /// Like `queries`, without substituting another family for a missing one.
+    fallback_queries: HashMap<FontQuery, Option<fontdb::ID>>,
+    pub(crate) fallback: FontFallback,
+    pub(crate) shape_context: ShapeContext,
+}
+
+impl FontCache {
+    pub(crate) fn new() -> Self {
+        Self {
+            db: fontdb::Database::new(),
+            system_fonts_loaded: false,
+            faces: Default::default(),
+            queries: Default::default(),
+            fallback_queries: Default::default(),
+            fallback: Default::default(),
+            shape_context: ShapeContext::new(),
+        }
+    }
+
+    /// A cache knowing only the fonts loaded into it, so that tests don't
+    /// depend on the fonts of the host.
+    #[cfg(test)]
+    pub(crate) fn without_system_fonts() -> Self {
+        Self {
+            system_fonts_loaded: true,
+            ..Self::new()
+        }
+    }
+
+    /// Scanning the system fonts is slow, so it is deferred until a family is
+    /// first looked up.
+    fn ensure_system_fonts(&mut self) {
+        if !self.system_fonts_loaded {
+            self.system_fonts_loaded = true;
+            self.db.load_system_fonts();
+        }
+    }
+
+    /// Looks up a family by name, returning `None` if no face of the family is known.
+    pub(crate) fn font_family(&mut self, family_name: &str) -> Option<FontFamily> {
+        self.ensure_system_fonts();
+        self.db
+            .faces()
+            .flat_map(|face| face.families.iter())
+            .find(|(name, _)| name.eq_ignore_ascii_case(family_name))
+            .map(|(name, _)| FontFamily::new_unchecked(name.as_str()))
+    }
+
+    /// Adds font data to the database, returning the family of its first face.
+    pub(crate) fn load_font(&mut self, data: &[u8]) -> Option<FontFamily> {
+        let ids = self
+            .db
+            .load_font_source(fontdb::Source::Binary(Arc::new(data.to_vec())));
+        // newly loaded faces may be a better match than earlier answers
+        self.queries.clear();
+        self.fallback_queries.clear();
+        ids.first()
+            .and_then(|id| self.db.face(*id))
+            .and_then(|face| face.families.first())
+            .map(|(name, _)| FontFamily::new_unchecked(name.as_str()))
+    }
+
+    /// Resolves a query to a face, falling back to any sans-serif face, and then to
+    /// any face at all, when the requested family is not available.
+    pub(crate) fn font(&mut self, query: &FontQuery) -> Option<Font> {
+        self.ensure_system_fonts();
+        let id = match self.queries.get(query) {
+            Some(id) => *id,
+            None => {
+                let id = self.query(query);
+                if id.is_none() {
+                    warn!("no font face found for {:?}", query);
+                }
+                self.queries.insert(query.clone(), id);
+                id
+            }
+        }?;
+        self.face(id)
+    }
+
+    /// Resolves a query to a face of exactly the requested family.
+    fn fallback_font(&mut self, query: &FontQuery) -> Option<Font> {
+        let id = match self.fallback_queries.get(query) {
+            Some(id) => *id,
+            None => {
+                let (family, weight, style) = Self::query_parts(query);
+                let id = self.db.query(&fontdb::Query {
+                    families: &[family],
+                    weight,
+                    style,
+                    ..Default::default()
+                });
+                self.fallback_queries.insert(query.clone(), id);
+                id
+            }
+        }?;
+        self.face(id)
+    }
+
+    fn query_parts(query: &FontQuery) -> (fontdb::Family<'_>, fontdb::Weight, fontdb::Style) {
+        let family = match query.family.inner() {
+            FontFamilyInner::Serif => fontdb::Family::Serif,
+            FontFamilyInner::SansSerif | FontFamilyInner::SystemUi => fontdb::Family::SansSerif,
+            FontFamilyInner::Monospace => fontdb::Family::Monospace,
+            FontFamilyInner::Named(name) => fontdb::Family::Name(name),
+            _ => fontdb::Family::SansSerif,
+        };
+        let style = match query.style {
+            FontStyle::Regular => fontdb::Style::Normal,
+            FontStyle::Italic => fontdb::Style::Italic,
+        };
+        (family, fontdb::Weight(query.weight.to_raw()), style)
+    }
+
+    fn query(&self, query: &FontQuery) -> Option<fontdb::ID> {
+        let (family, weight, style) = Self::query_parts(query);
+        let families = [family, fontdb::Family::SansSerif];
+        self.db
+            .query(&fontdb::Query {
+                families: &families,
+                weight,
+                style,
+                ..Default::default()
+            })
+            .or_else(|| {
+                // pick the closest face of whichever family is available
+                let name = &self.db.faces().next()?.families.first()?.0;
+                self.db.query(&fontdb::Query {
+                    families: &[fontdb::Family::Name(name)],
+                    weight,
+                    style,
+                    ..Default::default()
+                })
+            })
+    }
+
+    /// Replaces the fallback configuration.
+    pub(crate) fn set_fallback(&mut self, fallback: FontFallback) {
+        self.fallback = fallback;
+    }
+
+    /// Splits `range`, a run of a single script, into pieces which are each
+    /// covered by one font, going through the fallback chain for the
+    /// characters which the requested font does not support.
+    pub(crate) fn fallback_runs(
+        &mut self,
+        text: &str,
+        range: Range<usize>,
+        script: Script,
+        query: &FontQuery,
+    ) -> Vec<(Range<usize>, Option<Font>)> {
+        let primary = self.font(query);
+        let mut runs: Vec<(Range<usize>, Option<Font>)> = Vec::new();
+        let tokens = text[range.clone()]
+            .char_indices()
+            .map(|(offset, ch)| Token {
+                ch,
+                offset: (range.start + offset) as u32,
+                len: ch.len_utf8() as u8,
+                info: ch.into(),
+                data: 0,
+            });
+        let mut parser = Parser::new(script, tokens);
+        let mut cluster = CharCluster::new();
+        while parser.next(&mut cluster) {
+            let font = self
+                .cluster_font(&mut cluster, script, query, primary.as_ref())
+                .or_else(|| primary.clone());
+            let source = cluster.range();
+            let cluster_range = source.start as usize..source.end as usize;
+            match runs.last_mut() {
+                Some((run_range, run_font)) if *run_font == font => {
+                    run_range.end = cluster_range.end
+                }
+                _ => runs.push((cluster_range, font)),
+            }
+        }
+        // the parser skips nothing, but keep the runs contiguous regardless
+        if let Some((first, _)) = runs.first_mut() {
+            first.start = range.start;
+        }
+        if let Some((last, _)) = runs.last_mut() {
+            last.end = range.end;
+        }
+        runs
+    }
+
+    /// Returns the first font of the fallback chain which maps every character
+    /// of the cluster, or the one mapping the most of them.
+    fn cluster_font(
+        &mut self,
+        cluster: &mut CharCluster,
+        script: Script,
+        query: &FontQuery,
+        primary: Option<&Font>,
+    ) -> Option<Font> {
+        // keycap characters such as digits are emoji too, but only
+        // get a colour presentation when asked for one
+        let is_emoji = match cluster.info().emoji() {
+            Emoji::Color => true,
+            Emoji::Default => !cluster.mapped_chars().iter().all(|c| c.ch.is_ascii()),
+            Emoji::None | Emoji::Text => false,
+        };
+        let mut best = None;
+        if let Some(primary) = primary {
+            let charmap = primary.font_ref().charmap();
+            match cluster.map(|ch| charmap.map(ch)) {
+                // colour emoji take precedence over monochrome glyphs of the text font
+                Status::Complete if !is_emoji => return Some(primary.clone()),
+                Status::Complete | Status::Keep => best = Some(primary.clone()),
+                Status::Discard => {}
+            }
+        }
+
+        let mut families = Vec::new();
+        if is_emoji {
+            families.extend(self.fallback.emoji.iter().cloned());
+        }
+        if let Some(script_families) = self.fallback.scripts.get(&script) {
+            families.extend(script_families.iter().cloned());
+        }
+        families.extend(self.fallback.families.iter().cloned());
+
+        for family in families {
+            let Some(font) = self.fallback_font(&FontQuery {
+                family,
+                ..query.clone()
+            }) else {
+                continue;
+            };
+            let charmap = font.font_ref().charmap();
+            match cluster.map(|ch| charmap.map(ch)) {
+                Status::Complete => return Some(font),
+                Status::Keep if best.is_none() => best = Some(font),
+                _ => {}
+            }
+        }
+        best
+    }
+
+    fn face(&mut self, id: fontdb::ID) -> Option<Font> {
+        if let Some(font) = self.faces.get(&id) {
+            return Some(font.clone());
+        }
+        let font = self
+            .db
+            .with_face_data(id, |data, index| Font::new(data.to_vec(), index as usize))
+            .flatten()?;
+        self.faces.insert(id, font.clone());
+        Some(font)
+    }
+}
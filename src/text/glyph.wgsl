@@ -0,0 +1,114 @@
+// Draws glyph quads out of the glyph atlas.
+//
+// Each instance is a parallelogram in device pixels, so that glyphs follow the
+// transform they were drawn with. Mask glyphs are tinted with the instance
+// color, colour glyphs keep their own colors and only take its opacity. Solid
+// instances, for underlines and strikethroughs, are filled with the color.
+//
+// Subpixel glyphs carry a coverage per color channel. With DUAL_SOURCE_BLENDING
+// the coverage is the second blend source, so that each channel of the target
+// is blended on its own; otherwise it is averaged into a single coverage.
+
+#import piet_wgpu::transform::pixel_to_clip
+
+struct Viewport {
+    // the size of the render target in pixels
+    width: f32,
+    height: f32,
+}
+
+@group(0) @binding(0) var<uniform> viewport: Viewport;
+@group(0) @binding(1) var mask_atlas: texture_2d<f32>;
+@group(0) @binding(2) var color_atlas: texture_2d<f32>;
+@group(0) @binding(3) var subpixel_atlas: texture_2d<f32>;
+@group(0) @binding(4) var atlas_sampler: sampler;
+
+const CONTENT_COLOR: u32 = 1u;
+const CONTENT_SUBPIXEL: u32 = 2u;
+const CONTENT_SOLID: u32 = 3u;
+
+struct Instance {
+    @location(0) origin: vec2<f32>,
+    @location(1) axis_x: vec2<f32>,
+    @location(2) axis_y: vec2<f32>,
+    // xy: top left corner in texels, zw: size in texels
+    @location(3) uv_rect: vec4<f32>,
+    // premultiplied, linear
+    @location(4) color: vec4<f32>,
+    @location(5) content: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) content: u32,
+}
+
+@vertex
+fn vertex(@builtin(vertex_index) vertex_index: u32, instance: Instance) -> VertexOutput {
+    // a triangle strip over the corners (0, 0), (1, 0), (0, 1), (1, 1)
+    let corner = vec2<f32>(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+    let position = instance.origin + instance.axis_x * corner.x + instance.axis_y * corner.y;
+
+    var out: VertexOutput;
+    out.position = pixel_to_clip(position, vec2<f32>(viewport.width, viewport.height));
+    out.uv = instance.uv_rect.xy + instance.uv_rect.zw * corner;
+    out.color = instance.color;
+    out.content = instance.content;
+    return out;
+}
+
+// the branches are not uniform, so atlases are sampled without derivatives
+
+fn sample_subpixel(uv: vec2<f32>) -> vec3<f32> {
+    let size = vec2<f32>(textureDimensions(subpixel_atlas));
+    return textureSampleLevel(subpixel_atlas, atlas_sampler, uv / size, 0.0).rgb;
+}
+
+// shades mask and colour glyphs and solid quads, as premultiplied colors
+fn shade(in: VertexOutput) -> vec4<f32> {
+    if in.content == CONTENT_SOLID {
+        return in.color;
+    }
+    if in.content == CONTENT_COLOR {
+        let size = vec2<f32>(textureDimensions(color_atlas));
+        let texel = textureSampleLevel(color_atlas, atlas_sampler, in.uv / size, 0.0);
+        return vec4<f32>(texel.rgb * texel.a, texel.a) * in.color.a;
+    }
+    let size = vec2<f32>(textureDimensions(mask_atlas));
+    let coverage = textureSampleLevel(mask_atlas, atlas_sampler, in.uv / size, 0.0).r;
+    return in.color * coverage;
+}
+
+#ifdef DUAL_SOURCE_BLENDING
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // how much of the target each channel covers
+    @location(0) @second_blend_source coverage: vec4<f32>,
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> FragmentOutput {
+    var out: FragmentOutput;
+    if in.content == CONTENT_SUBPIXEL {
+        let coverage = sample_subpixel(in.uv);
+        let alpha = (coverage.r + coverage.g + coverage.b) / 3.0;
+        out.color = vec4<f32>(in.color.rgb * coverage, in.color.a * alpha);
+        out.coverage = vec4<f32>(coverage, alpha) * in.color.a;
+    } else {
+        out.color = shade(in);
+        out.coverage = vec4<f32>(out.color.a);
+    }
+    return out;
+}
+#else
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    if in.content == CONTENT_SUBPIXEL {
+        let coverage = sample_subpixel(in.uv);
+        return in.color * ((coverage.r + coverage.g + coverage.b) / 3.0);
+    }
+    return shade(in);
+}
+#endif
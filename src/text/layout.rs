@@ -0,0 +1,1507 @@
+use piet::kurbo::Line;
+use piet::Color;
+use piet::{
+    kurbo::{Point, Rect, Size, Vec2},
+    HitTestPoint, HitTestPosition, LineMetric, RenderContext, TextAlignment, TextAttribute,
+    TextLayout, TextLayoutBuilder, TextStorage,
+};
+use std::{
+    cell::RefCell,
+    ops::{Range, RangeBounds},
+    rc::Rc,
+};
+use swash::{
+    shape::Direction,
+    text::{Codepoint, Script},
+    GlyphId,
+};
+use unicode_bidi::{BidiInfo, Level};
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
+use unicode_width::UnicodeWidthChar;
+
+use super::{
+    atlas::GlyphAtlas,
+    attributes::{Attributes, RunStyle},
+    cache::LayoutKey,
+    font::{Font, FontCache, FontQuery},
+    render::{snap_to_subpixel, GlyphInstance},
+    WgpuText,
+};
+use crate::context::WgpuRenderContext;
+
+/// The tab stop interval used by [`TextLayoutBuilder::build`], in spaces.
+const DEFAULT_TAB_WIDTH: usize = 8;
+
+/// A shaped glyph, positioned relative to the start of its cluster.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Glyph {
+    pub id: GlyphId,
+    pub x: f32,
+    pub y: f32,
+    pub advance: f32,
+}
+
+/// The smallest piece of text which can be placed, such as a grapheme or a ligature.
+#[derive(Clone, Debug)]
+pub(crate) struct Cluster {
+    /// Byte range of the cluster in the layout text.
+    pub range: Range<usize>,
+    /// Range of the cluster glyphs in [`Run::glyphs`].
+    pub glyphs: Range<usize>,
+    pub advance: f64,
+    pub is_whitespace: bool,
+}
+
+/// Font metrics of a run, scaled to its font size.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct RunMetrics {
+    pub ascent: f64,
+    pub descent: f64,
+    pub leading: f64,
+    /// Distance from the baseline to the top of the underline, positive upwards.
+    pub underline_offset: f64,
+    /// Distance from the baseline to the top of the strikethrough, positive upwards.
+    pub strikeout_offset: f64,
+    pub stroke_size: f64,
+}
+
+impl RunMetrics {
+    fn from_font(font: &Font, size: f64) -> Self {
+        let metrics = font.metrics(size as f32);
+        Self {
+            ascent: metrics.ascent as f64,
+            descent: metrics.descent as f64,
+            leading: metrics.leading as f64,
+            underline_offset: metrics.underline_offset as f64,
+            strikeout_offset: metrics.strikeout_offset as f64,
+            stroke_size: metrics.stroke_size as f64,
+        }
+    }
+
+    /// Approximate metrics, used when no font face could be found.
+    fn fallback(size: f64) -> Self {
+        Self {
+            ascent: size * 0.8,
+            descent: size * 0.2,
+            leading: 0.0,
+            underline_offset: -size * 0.1,
+            strikeout_offset: size * 0.3,
+            stroke_size: size / 14.0,
+        }
+    }
+}
+
+/// A range of text shaped with a single font, style, script and direction.
+///
+/// Clusters are kept in logical order, even in right-to-left runs.
+#[derive(Clone)]
+pub(crate) struct Run {
+    pub font: Option<Font>,
+    pub style: RunStyle,
+    /// The bidi embedding level of the run, odd levels are right-to-left.
+    pub level: Level,
+    pub metrics: RunMetrics,
+    /// The advance of a space in the primary font of the run, which is the
+    /// width of a cell in monospace mode and the unit of tab stops.
+    pub cell: f64,
+    pub glyphs: Vec<Glyph>,
+    pub clusters: Vec<Cluster>,
+}
+
+impl Run {
+    /// Shapes `range` of `text` with `font`, in the direction of the bidi
+    /// `level` and the script of the item it belongs to, with cells of `cell`
+    /// pixels.
+    fn shape(
+        fonts: &mut FontCache,
+        text: &str,
+        range: Range<usize>,
+        style: RunStyle,
+        (level, script): (Level, Script),
+        font: Option<Font>,
+        cell: f64,
+    ) -> Self {
+        let mut glyphs = Vec::new();
+        let mut clusters = Vec::new();
+        let metrics = match &font {
+            Some(font) => {
+                let mut shaper = fonts
+                    .shape_context
+                    .builder(font.font_ref())
+                    .script(script)
+                    .direction(if level.is_rtl() {
+                        Direction::RightToLeft
+                    } else {
+                        Direction::LeftToRight
+                    })
+                    .size(style.size as f32)
+                    .build();
+                shaper.add_str(&text[range.clone()]);
+                shaper.shape_with(|cluster| {
+                    let source = cluster.source.to_range();
+                    let first_glyph = glyphs.len();
+                    glyphs.extend(cluster.glyphs.iter().map(|glyph| Glyph {
+                        id: glyph.id,
+                        x: glyph.x,
+                        y: glyph.y,
+                        advance: glyph.advance,
+                    }));
+                    clusters.push(Cluster {
+                        range: range.start + source.start..range.start + source.end,
+                        glyphs: first_glyph..glyphs.len(),
+                        advance: cluster.advance() as f64,
+                        is_whitespace: cluster.info.is_whitespace(),
+                    });
+                });
+                RunMetrics::from_font(font, style.size)
+            }
+            None => {
+                // keep one empty cluster per character so that offsets can still be resolved
+                clusters.extend(text[range.clone()].char_indices().map(|(offset, c)| {
+                    let start = range.start + offset;
+                    Cluster {
+                        range: start..start + c.len_utf8(),
+                        glyphs: 0..0,
+                        advance: 0.0,
+                        is_whitespace: c.is_whitespace(),
+                    }
+                }));
+                RunMetrics::fallback(style.size)
+            }
+        };
+
+        Self {
+            font,
+            style,
+            level,
+            metrics,
+            cell,
+            glyphs,
+            clusters,
+        }
+    }
+
+    /// Gives every cluster a whole number of cells, two for wide characters
+    /// such as CJK ideographs, and centers its glyphs within them.
+    fn align_to_cells(&mut self, text: &str) {
+        for cluster in &mut self.clusters {
+            let cells: usize = text[cluster.range.clone()]
+                .chars()
+                .filter_map(UnicodeWidthChar::width)
+                .sum();
+            let advance = cells as f64 * self.cell;
+            let offset = ((advance - cluster.advance) / 2.0) as f32;
+            for glyph in &mut self.glyphs[cluster.glyphs.clone()] {
+                glyph.x += offset;
+            }
+            cluster.advance = advance;
+        }
+    }
+}
+
+/// Widens the tabs of a paragraph so that each one reaches the next tab stop,
+/// placed every `tab_width` cells from the start of the paragraph.
+fn expand_tabs(runs: &mut [Run], text: &str, tab_width: usize) {
+    let mut x = 0.0;
+    for run in runs {
+        let interval = run.cell * tab_width as f64;
+        for cluster in &mut run.clusters {
+            if interval > 0.0 && &text[cluster.range.clone()] == "\t" {
+                // tolerate rounding when the tab starts right on a stop
+                let stop = ((x / interval + 1e-6).floor() + 1.0) * interval;
+                cluster.advance = stop - x;
+                // the font may only have a missing glyph box for the tab
+                cluster.glyphs.end = cluster.glyphs.start;
+                cluster.is_whitespace = true;
+            }
+            x += cluster.advance;
+        }
+    }
+}
+
+/// Splits `range` wherever the bidi level or the script changes, so that each
+/// piece can be shaped in a single direction. Characters without a script of
+/// their own, such as spaces and punctuation, join the surrounding script.
+fn itemize(
+    text: &str,
+    range: Range<usize>,
+    levels: &[Level],
+    levels_offset: usize,
+) -> Vec<(Range<usize>, Level, Script)> {
+    let mut items: Vec<(Range<usize>, Level, Script)> = Vec::new();
+    let mut current: Option<(usize, Level, Option<Script>)> = None;
+    for (offset, c) in text[range.clone()].char_indices() {
+        let index = range.start + offset;
+        let level = levels[index - levels_offset];
+        let script = match c.script() {
+            Script::Common | Script::Inherited | Script::Unknown => None,
+            script => Some(script),
+        };
+        match &mut current {
+            Some((_, current_level, current_script))
+                if *current_level == level
+                    && (script.is_none()
+                        || current_script.is_none()
+                        || *current_script == script) =>
+            {
+                if current_script.is_none() {
+                    *current_script = script;
+                }
+            }
+            _ => {
+                if let Some((start, level, script)) = current.take() {
+                    items.push((start..index, level, script.unwrap_or(Script::Latin)));
+                }
+                current = Some((index, level, script));
+            }
+        }
+    }
+    if let Some((start, level, script)) = current {
+        items.push((start..range.end, level, script.unwrap_or(Script::Latin)));
+    }
+    items
+}
+
+/// The part of a [`Run`] which was placed on a line.
+#[derive(Clone, Debug)]
+pub(crate) struct LineRun {
+    /// Index of the run in [`WgpuTextLayout::runs`].
+    pub run: usize,
+    /// Range of the clusters in [`Run::clusters`].
+    pub clusters: Range<usize>,
+    /// The level used for reordering, which is the paragraph level for
+    /// trailing whitespace and the run level otherwise.
+    pub level: Level,
+    /// Offset of the left edge from the start of the line.
+    pub x: f64,
+    pub width: f64,
+}
+
+impl LineRun {
+    /// Returns the indices of the run clusters in visual order, left to right.
+    pub(crate) fn visual_clusters(&self) -> impl Iterator<Item = usize> {
+        let clusters = self.clusters.clone();
+        let rtl = self.level.is_rtl();
+        (0..clusters.len()).map(move |index| {
+            if rtl {
+                clusters.end - 1 - index
+            } else {
+                clusters.start + index
+            }
+        })
+    }
+}
+
+/// Reorders the runs of a line from logical to visual order, following rule
+/// L2 of the Unicode bidi algorithm, and places them left to right starting at `x`.
+fn reorder_line(runs: &mut [LineRun], mut x: f64) {
+    let max_level = runs.iter().map(|run| run.level.number()).max();
+    let min_level = runs.iter().map(|run| run.level.number()).min();
+    if let (Some(max_level), Some(min_level)) = (max_level, min_level) {
+        let lowest_odd = min_level | 1;
+        for level in (lowest_odd..=max_level).rev() {
+            let mut start = 0;
+            while start < runs.len() {
+                if runs[start].level.number() < level {
+                    start += 1;
+                    continue;
+                }
+                let end = runs[start..]
+                    .iter()
+                    .position(|run| run.level.number() < level)
+                    .map_or(runs.len(), |len| start + len);
+                runs[start..end].reverse();
+                start = end;
+            }
+        }
+    }
+    for run in runs {
+        run.x = x;
+        x += run.width;
+    }
+}
+
+/// Drops the clusters of a line which start past `width`, the right edge of
+/// the layout bounds.
+fn clip_line(line: &mut LayoutLine, runs: &[Run], width: f64) {
+    let limit = width - line.x;
+    line.runs.retain(|line_run| line_run.x < limit);
+    for line_run in &mut line.runs {
+        let clusters = &runs[line_run.run].clusters;
+        let mut x = line_run.x;
+        let mut kept = 0;
+        for index in line_run.visual_clusters() {
+            if x >= limit {
+                break;
+            }
+            x += clusters[index].advance;
+            kept += 1;
+        }
+        // the visual order of a right-to-left run starts at its logical end
+        if line_run.level.is_rtl() {
+            line_run.clusters.start = line_run.clusters.end - kept;
+        } else {
+            line_run.clusters.end = line_run.clusters.start + kept;
+        }
+        line_run.width = x - line_run.x;
+    }
+    let limit = limit.max(0.0);
+    line.width = line.width.min(limit);
+    line.full_width = line.full_width.min(limit);
+}
+
+#[derive(Clone)]
+pub(crate) struct LayoutLine {
+    pub metric: LineMetric,
+    /// Whether the paragraph of the line is right-to-left.
+    pub rtl: bool,
+    /// Offset of the line from the left of the layout, as set by the alignment.
+    pub x: f64,
+    /// Width of the line, excluding trailing whitespace. In a right-to-left
+    /// paragraph the whitespace hangs to the left of the line.
+    pub width: f64,
+    /// Width of the line, including trailing whitespace.
+    pub full_width: f64,
+    /// The runs of the line, in visual order.
+    pub runs: Vec<LineRun>,
+}
+
+/// A text paragraph, delimited by hard line breaks.
+struct Paragraph {
+    /// Range of the paragraph text, without the line break.
+    content: Range<usize>,
+    /// End of the paragraph, including the line break.
+    end: usize,
+    /// The base bidi level, resolved from the paragraph text.
+    level: Level,
+}
+
+fn paragraphs(text: &str) -> Vec<Paragraph> {
+    let mut paragraphs = Vec::new();
+    let mut start = 0;
+    for (index, _) in text.match_indices('\n') {
+        let content_end = if index > start && text[..index].ends_with('\r') {
+            index - 1
+        } else {
+            index
+        };
+        paragraphs.push(Paragraph {
+            content: start..content_end,
+            end: index + 1,
+            level: Level::ltr(),
+        });
+        start = index + 1;
+    }
+    paragraphs.push(Paragraph {
+        content: start..text.len(),
+        end: text.len(),
+        level: Level::ltr(),
+    });
+    paragraphs
+}
+
+/// A shaped and line broken text.
+///
+/// Cloning is cheap, clones share the text, runs and lines, and layouts built
+/// from the same text and attributes are shared through the layout cache.
+#[derive(Clone)]
+pub struct WgpuTextLayout {
+    /// The font database and glyph atlas, which are not held through a
+    /// [`WgpuText`] so that cached layouts do not keep the cache alive.
+    fonts: Rc<RefCell<FontCache>>,
+    pub(crate) atlas: Rc<RefCell<GlyphAtlas>>,
+    text: Rc<str>,
+    width: f64,
+    alignment: TextAlignment,
+    attrs: Rc<Attributes>,
+    pub(crate) runs: Rc<Vec<Run>>,
+    pub(crate) lines: Rc<Vec<LayoutLine>>,
+    size: Size,
+    trailing_whitespace_width: f64,
+}
+
+impl WgpuTextLayout {
+    pub fn new(text: impl Into<Rc<str>>, state: WgpuText) -> Self {
+        Self {
+            fonts: state.fonts,
+            atlas: state.atlas,
+            text: text.into(),
+            width: f64::MAX,
+            alignment: TextAlignment::Start,
+            attrs: Rc::new(Attributes::default()),
+            runs: Default::default(),
+            lines: Default::default(),
+            size: Size::ZERO,
+            trailing_whitespace_width: 0.0,
+        }
+    }
+
+    fn set_width(&mut self, width: f64) {
+        self.width = width;
+    }
+
+    fn set_alignment(&mut self, alignment: TextAlignment) {
+        self.alignment = alignment;
+    }
+
+    fn set_attrs(&mut self, attrs: Rc<Attributes>) {
+        self.attrs = attrs;
+    }
+
+    /// Overrides the color of the whole text, without reshaping it.
+    pub fn set_color(&mut self, color: &Color) {
+        Rc::make_mut(&mut self.attrs).set_color(*color);
+        for run in Rc::make_mut(&mut self.runs) {
+            run.style.color = *color;
+        }
+    }
+
+    pub(crate) fn rebuild(&mut self, is_mono: bool, tab_width: usize, bounds: Option<[f64; 2]>) {
+        let fonts = self.fonts.clone();
+        let mut fonts = fonts.borrow_mut();
+        let mut runs = Vec::new();
+        let mut lines = Vec::new();
+        let mut y = 0.0;
+        for mut paragraph in paragraphs(&self.text) {
+            let bidi = BidiInfo::new(&self.text[paragraph.content.clone()], None);
+            let base_level = bidi
+                .paragraphs
+                .first()
+                .map_or_else(Level::ltr, |info| info.level);
+            let first_run = runs.len();
+            for (range, style) in self.attrs.runs(paragraph.content.clone()) {
+                let query = FontQuery {
+                    family: style.font.clone(),
+                    weight: style.weight,
+                    style: style.style,
+                };
+                let cell = fonts
+                    .font(&query)
+                    .and_then(|font| font.advance(' ', style.size as f32))
+                    .map_or(style.size / 2.0, |advance| advance as f64);
+                let items = itemize(&self.text, range, &bidi.levels, paragraph.content.start);
+                for (range, level, script) in items {
+                    for (range, font) in fonts.fallback_runs(&self.text, range, script, &query) {
+                        let mut run = Run::shape(
+                            &mut fonts,
+                            &self.text,
+                            range,
+                            style.clone(),
+                            (level, script),
+                            font,
+                            cell,
+                        );
+                        if is_mono {
+                            run.align_to_cells(&self.text);
+                        }
+                        runs.push(run);
+                    }
+                }
+            }
+            expand_tabs(&mut runs[first_run..], &self.text, tab_width);
+            paragraph.level = base_level;
+            self.break_lines(&mut fonts, &runs, first_run, &paragraph, &mut lines, &mut y);
+            // the following paragraphs would fall below the bounds
+            if bounds.is_some_and(|bounds| y >= bounds[1]) {
+                break;
+            }
+        }
+        if let Some(bounds) = bounds {
+            lines.retain(|line| line.metric.y_offset < bounds[1]);
+        }
+
+        let max_line_width = lines
+            .iter()
+            .fold(0.0f64, |width, line| width.max(line.width));
+        // an unbounded layout is aligned within its widest line
+        let align_width = if self.width < f64::MAX {
+            self.width
+        } else {
+            max_line_width
+        };
+        for line in &mut lines {
+            let slack = (align_width - line.width).max(0.0);
+            // the start of a right-to-left line is on its right
+            line.x = match (self.alignment, line.rtl) {
+                (TextAlignment::Start | TextAlignment::Justified, false)
+                | (TextAlignment::End, true) => 0.0,
+                (TextAlignment::Start | TextAlignment::Justified, true)
+                | (TextAlignment::End, false) => slack,
+                (TextAlignment::Center, _) => slack / 2.0,
+            };
+            if let Some(bounds) = bounds {
+                clip_line(line, &runs, bounds[0]);
+            }
+        }
+
+        self.size = Size::new(
+            lines
+                .iter()
+                .fold(0.0f64, |width, line| width.max(line.x + line.width)),
+            bounds.map_or(y, |bounds| y.min(bounds[1])),
+        );
+        self.trailing_whitespace_width = lines
+            .iter()
+            .fold(0.0f64, |width, line| width.max(line.x + line.full_width));
+        self.runs = Rc::new(runs);
+        self.lines = Rc::new(lines);
+    }
+
+    /// Greedily fills lines with the clusters of a paragraph, breaking after
+    /// whitespace when a line would exceed the maximum width.
+    fn break_lines(
+        &self,
+        fonts: &mut FontCache,
+        runs: &[Run],
+        first_run: usize,
+        paragraph: &Paragraph,
+        lines: &mut Vec<LayoutLine>,
+        y: &mut f64,
+    ) {
+        let clusters = runs[first_run..]
+            .iter()
+            .enumerate()
+            .flat_map(|(index, run)| {
+                (0..run.clusters.len()).map(move |cluster| (first_run + index, cluster))
+            })
+            .collect::<Vec<_>>();
+        let cluster = |(run, cluster): (usize, usize)| &runs[run].clusters[cluster];
+
+        if clusters.is_empty() {
+            let style = self.attrs.run_style(paragraph.content.start);
+            let metrics = fonts
+                .font(&FontQuery {
+                    family: style.font.clone(),
+                    weight: style.weight,
+                    style: style.style,
+                })
+                .map(|font| RunMetrics::from_font(&font, style.size))
+                .unwrap_or_else(|| RunMetrics::fallback(style.size));
+            let start = paragraph.content.start;
+            lines.push(LayoutLine {
+                rtl: paragraph.level.is_rtl(),
+                ..Self::make_line(
+                    start..paragraph.end,
+                    paragraph.end - start,
+                    metrics,
+                    Vec::new(),
+                    0.0,
+                    0.0,
+                    y,
+                )
+            });
+            return;
+        }
+
+        let mut start = 0;
+        while start < clusters.len() {
+            let mut end = clusters.len();
+            let mut width = 0.0;
+            let mut break_after_whitespace = None;
+            for (index, item) in clusters.iter().enumerate().skip(start) {
+                let cluster = cluster(*item);
+                if cluster.is_whitespace {
+                    // whitespace may hang past the end of the line
+                    width += cluster.advance;
+                    break_after_whitespace = Some(index + 1);
+                    continue;
+                }
+                if width + cluster.advance > self.width && index > start {
+                    end = break_after_whitespace.unwrap_or(index);
+                    break;
+                }
+                width += cluster.advance;
+            }
+
+            let placed = &clusters[start..end];
+            let is_last = end == clusters.len();
+            let text_start = if start == 0 {
+                paragraph.content.start
+            } else {
+                cluster(placed[0]).range.start
+            };
+            let text_end = if is_last {
+                paragraph.end
+            } else {
+                cluster(placed[placed.len() - 1]).range.end
+            };
+            let trailing = placed
+                .iter()
+                .rev()
+                .take_while(|item| cluster(**item).is_whitespace)
+                .count();
+            let trailing_start = if trailing == placed.len() {
+                text_start
+            } else {
+                cluster(placed[placed.len() - trailing - 1]).range.end
+            };
+
+            let mut line_runs: Vec<LineRun> = Vec::new();
+            let mut x = 0.0;
+            let mut visible_width = 0.0;
+            let mut metrics: Option<RunMetrics> = None;
+            for (index, (run, cluster_index)) in placed.iter().copied().enumerate() {
+                let advance = runs[run].clusters[cluster_index].advance;
+                // trailing whitespace is reset to the paragraph level (rule L1)
+                let level = if index < placed.len() - trailing {
+                    runs[run].level
+                } else {
+                    paragraph.level
+                };
+                match line_runs.last_mut() {
+                    Some(line_run) if line_run.run == run && line_run.level == level => {
+                        line_run.clusters.end = cluster_index + 1;
+                        line_run.width += advance;
+                    }
+                    _ => {
+                        let run_metrics = runs[run].metrics;
+                        metrics = Some(match metrics {
+                            Some(metrics) => RunMetrics {
+                                ascent: metrics.ascent.max(run_metrics.ascent),
+                                descent: metrics.descent.max(run_metrics.descent),
+                                leading: metrics.leading.max(run_metrics.leading),
+                                ..metrics
+                            },
+                            None => run_metrics,
+                        });
+                        line_runs.push(LineRun {
+                            run,
+                            clusters: cluster_index..cluster_index + 1,
+                            level,
+                            x,
+                            width: advance,
+                        });
+                    }
+                }
+                x += advance;
+                if index < placed.len() - trailing {
+                    visible_width = x;
+                }
+            }
+
+            // whitespace hangs off the end of the line, which is on the left
+            // in a right-to-left paragraph
+            let rtl = paragraph.level.is_rtl();
+            reorder_line(&mut line_runs, if rtl { visible_width - x } else { 0.0 });
+            lines.push(LayoutLine {
+                rtl,
+                ..Self::make_line(
+                    text_start..text_end,
+                    text_end - trailing_start,
+                    metrics.unwrap_or_default(),
+                    line_runs,
+                    visible_width,
+                    x,
+                    y,
+                )
+            });
+            start = end;
+        }
+    }
+
+    fn make_line(
+        range: Range<usize>,
+        trailing_whitespace: usize,
+        metrics: RunMetrics,
+        runs: Vec<LineRun>,
+        width: f64,
+        full_width: f64,
+        y: &mut f64,
+    ) -> LayoutLine {
+        let height = metrics.ascent + metrics.descent + metrics.leading;
+        let metric = LineMetric {
+            start_offset: range.start,
+            end_offset: range.end,
+            trailing_whitespace,
+            baseline: metrics.ascent,
+            height,
+            y_offset: *y,
+        };
+        *y += height;
+        LayoutLine {
+            metric,
+            rtl: false,
+            x: 0.0,
+            width,
+            full_width,
+            runs,
+        }
+    }
+
+    /// Returns the underline and strikethrough rects of the layout, with their colors.
+    pub(crate) fn decorations(&self) -> Vec<(Rect, &Color)> {
+        let mut decorations = Vec::new();
+        for line in self.lines.iter() {
+            let baseline = line.metric.y_offset + line.metric.baseline;
+            for line_run in &line.runs {
+                let run = &self.runs[line_run.run];
+                let x0 = line.x + line_run.x;
+                let x1 = x0 + line_run.width;
+                let thickness = run.metrics.stroke_size.max(1.0);
+                if run.style.underline {
+                    let y0 = baseline - run.metrics.underline_offset;
+                    decorations.push((Rect::new(x0, y0, x1, y0 + thickness), &run.style.color));
+                }
+                if run.style.strikethrough {
+                    let y0 = baseline - run.metrics.strikeout_offset;
+                    decorations.push((Rect::new(x0, y0, x1, y0 + thickness), &run.style.color));
+                }
+            }
+        }
+        decorations
+    }
+
+    /// Visits every glyph of the layout, in visual order, with the font of its
+    /// run and the pen position it is placed from: the point on the baseline
+    /// before the glyph offsets are applied, relative to the layout origin.
+    pub(crate) fn for_each_glyph(&self, mut f: impl FnMut(&Run, &Font, &Glyph, [f64; 2])) {
+        for line in self.lines.iter() {
+            let baseline = line.metric.y_offset + line.metric.baseline;
+            for line_run in &line.runs {
+                let run = &self.runs[line_run.run];
+                let Some(font) = &run.font else {
+                    continue;
+                };
+                let mut x = line.x + line_run.x;
+                for index in line_run.visual_clusters() {
+                    let cluster = &run.clusters[index];
+                    let mut pen_x = x;
+                    for glyph in &run.glyphs[cluster.glyphs.clone()] {
+                        f(run, font, glyph, [pen_x, baseline]);
+                        pen_x += glyph.advance as f64;
+                    }
+                    x += cluster.advance;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn draw_text(&self, ctx: &mut WgpuRenderContext, translate: [f32; 2]) {
+        let translate = Vec2::new(translate[0] as f64, translate[1] as f64);
+        let transform = ctx.current_transform();
+        // rasterize at the device scale so that glyphs stay sharp when zoomed
+        let scale = transform.determinant().abs().sqrt().max(f64::EPSILON);
+        {
+            let mut atlas = self.atlas.borrow_mut();
+            let subpixel_positioning = atlas.options().subpixel_positioning;
+            let mut text_renderer = ctx.renderer.text_renderer.borrow_mut();
+            self.for_each_glyph(|run, font, glyph, [x, y]| {
+                let mut origin = [
+                    translate.x + x + glyph.x as f64,
+                    translate.y + y - glyph.y as f64,
+                ];
+                let mut offset = 0;
+                if subpixel_positioning {
+                    if let Some(snapped) = snap_to_subpixel(origin, transform) {
+                        (origin, offset) = snapped;
+                    }
+                }
+                let size = (run.style.size * scale) as f32;
+                if let Some(entry) = atlas.glyph(font, glyph.id, size, offset) {
+                    text_renderer.push(GlyphInstance::new(
+                        &entry,
+                        origin,
+                        scale,
+                        transform,
+                        &run.style.color,
+                    ));
+                }
+            });
+            for (rect, color) in self.decorations() {
+                text_renderer.push(GlyphInstance::solid(rect + translate, transform, color));
+            }
+        }
+    }
+
+    /// Returns the vertical caret line spanning the full height of the line
+    /// which contains `text_pos`.
+    pub fn cursor_line_for_text_position(&self, text_pos: usize) -> Line {
+        let position = self.hit_test_text_position(text_pos);
+        let Some(line) = self.lines.get(position.line) else {
+            return Line::new(Point::ZERO, Point::ZERO);
+        };
+        let x = position.point.x;
+        let y = line.metric.y_offset;
+        Line::new((x, y), (x, y + line.metric.height))
+    }
+
+    /// Moves `idx` back to the closest grapheme boundary, so that carets are
+    /// never placed inside a character or a combining sequence.
+    fn grapheme_boundary(&self, idx: usize) -> usize {
+        let mut idx = idx.min(self.text.len());
+        while !self.text.is_char_boundary(idx) {
+            idx -= 1;
+        }
+        let mut cursor = GraphemeCursor::new(idx, self.text.len(), true);
+        match cursor.is_boundary(&self.text, 0) {
+            Ok(false) => cursor
+                .prev_boundary(&self.text, 0)
+                .ok()
+                .flatten()
+                .unwrap_or(0),
+            _ => idx,
+        }
+    }
+
+    /// Returns the index of the line containing the text position `idx`.
+    ///
+    /// An offset at the end of a soft-wrapped line belongs to the following line.
+    fn line_for_index(&self, idx: usize) -> usize {
+        self.lines
+            .iter()
+            .position(|line| idx < line.metric.end_offset)
+            .unwrap_or(self.lines.len().saturating_sub(1))
+    }
+
+    /// Returns the last text position whose caret is drawn on `line`.
+    fn line_caret_end(&self, line_number: usize) -> usize {
+        let metric = &self.lines[line_number].metric;
+        let line_text = &self.text[metric.range()];
+        if line_number + 1 == self.lines.len() {
+            metric.end_offset
+        } else if line_text.ends_with("\r\n") {
+            metric.end_offset - 2
+        } else if line_text.ends_with('\n') {
+            metric.end_offset - 1
+        } else {
+            // a soft break: stay before the last grapheme, which is usually
+            // the hanging whitespace
+            line_text
+                .grapheme_indices(true)
+                .next_back()
+                .map(|(offset, _)| metric.start_offset + offset)
+                .filter(|offset| *offset > metric.start_offset)
+                .unwrap_or(metric.end_offset)
+        }
+    }
+
+    /// Visits the clusters of a line in visual order, left to right, with the
+    /// position of their left edge and whether they are laid out right-to-left.
+    fn visual_clusters<'a>(
+        &'a self,
+        line: &'a LayoutLine,
+    ) -> impl Iterator<Item = (f64, &'a Cluster, bool)> + 'a {
+        line.runs.iter().flat_map(move |line_run| {
+            let run = &self.runs[line_run.run];
+            let rtl = line_run.level.is_rtl();
+            let mut x = line.x + line_run.x;
+            line_run.visual_clusters().map(move |index| {
+                let cluster = &run.clusters[index];
+                let left = x;
+                x += cluster.advance;
+                (left, cluster, rtl)
+            })
+        })
+    }
+
+    /// Returns the horizontal position of the caret at `idx`, which must be on `line`.
+    ///
+    /// The caret sits on the leading edge of the cluster at `idx`, or on the
+    /// trailing edge of the cluster before it at the end of a line.
+    fn offset_x(&self, line: &LayoutLine, idx: usize) -> f64 {
+        let mut trailing_edge = None;
+        for (x, cluster, rtl) in self.visual_clusters(line) {
+            if cluster.range.contains(&idx) {
+                let offset = cluster.advance * self.grapheme_fraction(cluster, idx);
+                return if rtl {
+                    x + cluster.advance - offset
+                } else {
+                    x + offset
+                };
+            }
+            if cluster.range.end == idx {
+                trailing_edge = Some(if rtl { x } else { x + cluster.advance });
+            }
+        }
+        trailing_edge.unwrap_or(line.x)
+    }
+
+    /// Returns the fraction of the cluster advance which is logically before `idx`.
+    fn grapheme_fraction(&self, cluster: &Cluster, idx: usize) -> f64 {
+        if idx >= cluster.range.end {
+            return 1.0;
+        }
+        let graphemes = self.cluster_graphemes(cluster);
+        let before = graphemes.iter().filter(|offset| **offset < idx).count();
+        before as f64 / graphemes.len() as f64
+    }
+
+    /// Returns the start offsets of the graphemes in a cluster. A cluster holds
+    /// more than one grapheme when the font joins them into a ligature, in which
+    /// case its advance is shared evenly between them.
+    fn cluster_graphemes(&self, cluster: &Cluster) -> Vec<usize> {
+        self.text[cluster.range.clone()]
+            .grapheme_indices(true)
+            .map(|(offset, _)| cluster.range.start + offset)
+            .collect()
+    }
+}
+
+pub struct WgpuTextLayoutBuilder {
+    width: f64,
+    alignment: TextAlignment,
+    state: WgpuText,
+    text: String,
+    attrs: Attributes,
+}
+
+impl WgpuTextLayoutBuilder {
+    pub(crate) fn new(text: impl TextStorage, state: WgpuText) -> Self {
+        Self {
+            width: f64::MAX,
+            alignment: TextAlignment::Start,
+            text: text.as_str().to_string(),
+            attrs: Default::default(),
+            state,
+        }
+    }
+
+    fn add(&mut self, attr: TextAttribute, range: Range<usize>) {
+        self.attrs.add(range, attr);
+    }
+
+    /// Builds the layout with explicit layout options.
+    ///
+    /// In monospace mode every character advances by a whole number of cells,
+    /// the width of a space in the font, and wide characters such as CJK
+    /// ideographs take two cells. Tabs advance to the next stop, every
+    /// `tab_width` cells. When `bounds` is given, as a width and a height, the
+    /// lines and glyphs outside of it are left out of the layout.
+    pub fn build_with_info(
+        self,
+        is_mono: bool,
+        tab_width: usize,
+        bounds: Option<[f64; 2]>,
+    ) -> WgpuTextLayout {
+        let text: Rc<str> = self.text.into();
+        let attrs = Rc::new(self.attrs);
+        let key = LayoutKey::new(
+            text.clone(),
+            attrs.clone(),
+            self.width,
+            self.alignment,
+            is_mono,
+            tab_width,
+            bounds,
+        );
+        if let Some(text_layout) = self.state.layouts.borrow_mut().get(&key) {
+            return text_layout;
+        }
+
+        let mut text_layout = WgpuTextLayout::new(text, self.state.clone());
+        text_layout.set_attrs(attrs);
+        text_layout.set_width(self.width);
+        text_layout.set_alignment(self.alignment);
+        text_layout.rebuild(is_mono, tab_width, bounds);
+        self.state
+            .layouts
+            .borrow_mut()
+            .insert(key, text_layout.clone());
+        text_layout
+    }
+
+    /// Builds the layout, clipped to a box of `bounds` width and height.
+    pub fn build_with_bounds(self, bounds: [f64; 2]) -> WgpuTextLayout {
+        self.build_with_info(false, DEFAULT_TAB_WIDTH, Some(bounds))
+    }
+}
+
+impl TextLayoutBuilder for WgpuTextLayoutBuilder {
+    type Out = WgpuTextLayout;
+
+    fn max_width(mut self, width: f64) -> Self {
+        self.width = width;
+        self
+    }
+
+    fn alignment(mut self, alignment: TextAlignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    fn default_attribute(mut self, attribute: impl Into<TextAttribute>) -> Self {
+        let attribute = attribute.into();
+        self.attrs.defaults.set(attribute);
+        self
+    }
+
+    fn range_attribute(
+        mut self,
+        range: impl std::ops::RangeBounds<usize>,
+        attribute: impl Into<TextAttribute>,
+    ) -> Self {
+        let range = piet::util::resolve_range(range, self.text.len());
+        let attribute = attribute.into();
+        self.add(attribute, range);
+        self
+    }
+
+    fn build(self) -> Result<Self::Out, piet::Error> {
+        Ok(self.build_with_info(false, DEFAULT_TAB_WIDTH, None))
+    }
+}
+
+impl TextLayout for WgpuTextLayout {
+    fn size(&self) -> Size {
+        self.size
+    }
+
+    fn trailing_whitespace_width(&self) -> f64 {
+        self.trailing_whitespace_width
+    }
+
+    fn image_bounds(&self) -> Rect {
+        self.lines
+            .iter()
+            .map(|line| {
+                Rect::new(
+                    line.x,
+                    line.metric.y_offset,
+                    line.x + line.width,
+                    line.metric.y_offset + line.metric.height,
+                )
+            })
+            .chain(self.decorations().into_iter().map(|(rect, _)| rect))
+            .reduce(|bounds, rect| bounds.union(rect))
+            .unwrap_or_default()
+    }
+
+    fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn line_text(&self, line_number: usize) -> Option<&str> {
+        self.lines
+            .get(line_number)
+            .map(|line| &self.text[line.metric.range()])
+    }
+
+    fn line_metric(&self, line_number: usize) -> Option<LineMetric> {
+        self.lines.get(line_number).map(|line| line.metric.clone())
+    }
+
+    fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn hit_test_point(&self, point: Point) -> HitTestPoint {
+        let Some(last_line) = self.lines.last() else {
+            return HitTestPoint::default();
+        };
+        let bottom = last_line.metric.y_offset + last_line.metric.height;
+        let line_number = self
+            .lines
+            .iter()
+            .position(|line| point.y < line.metric.y_offset + line.metric.height)
+            .unwrap_or(self.lines.len() - 1);
+        let line = &self.lines[line_number];
+        let is_inside_y = point.y >= 0.0 && point.y < bottom;
+
+        let caret_end = self.line_caret_end(line_number);
+        let mut rightmost = None;
+        for (x, cluster, rtl) in self.visual_clusters(line) {
+            if point.x < x + cluster.advance {
+                // snap to the closest grapheme boundary within the cluster
+                let graphemes = self.cluster_graphemes(cluster);
+                let grapheme_width = cluster.advance / graphemes.len() as f64;
+                let from_left = ((point.x - x) / grapheme_width).round() as usize;
+                let nth = if rtl {
+                    graphemes.len().saturating_sub(from_left)
+                } else {
+                    from_left
+                };
+                let idx = graphemes
+                    .get(nth)
+                    .copied()
+                    .unwrap_or(cluster.range.end)
+                    .min(caret_end);
+                return HitTestPoint::new(idx, is_inside_y && point.x >= x);
+            }
+            rightmost = Some((cluster, rtl));
+        }
+        let idx = match rightmost {
+            Some((cluster, true)) => cluster.range.start,
+            Some((cluster, false)) => cluster.range.end.min(caret_end),
+            None => line.metric.start_offset,
+        };
+        HitTestPoint::new(idx, false)
+    }
+
+    fn hit_test_text_position(&self, idx: usize) -> HitTestPosition {
+        let idx = self.grapheme_boundary(idx);
+        let line_number = self.line_for_index(idx);
+        let Some(line) = self.lines.get(line_number) else {
+            return HitTestPosition::default();
+        };
+        let point = Point::new(
+            self.offset_x(line, idx),
+            line.metric.y_offset + line.metric.baseline,
+        );
+        HitTestPosition::new(point, line_number)
+    }
+
+    fn rects_for_range(&self, range: impl RangeBounds<usize>) -> Vec<Rect> {
+        let range = piet::util::resolve_range(range, self.text.len());
+        let mut rects = Vec::new();
+        for line in self.lines.iter() {
+            if line.metric.end_offset <= range.start {
+                continue;
+            }
+            if line.metric.start_offset >= range.end {
+                break;
+            }
+            let y0 = line.metric.y_offset;
+            let y1 = y0 + line.metric.height;
+            // in mixed-direction text a logical range may be visually discontiguous
+            let mut current: Option<Rect> = None;
+            for (x, cluster, rtl) in self.visual_clusters(line) {
+                let start = range.start.max(cluster.range.start);
+                let end = range.end.min(cluster.range.end);
+                if start >= end {
+                    continue;
+                }
+                let from = cluster.advance * self.grapheme_fraction(cluster, start);
+                let to = cluster.advance * self.grapheme_fraction(cluster, end);
+                let (x0, x1) = if rtl {
+                    (x + cluster.advance - to, x + cluster.advance - from)
+                } else {
+                    (x + from, x + to)
+                };
+                match &mut current {
+                    Some(rect) if (rect.x1 - x0).abs() < 1e-6 => rect.x1 = x1,
+                    _ => {
+                        rects.extend(current.take());
+                        current = Some(Rect::new(x0, y0, x1, y1));
+                    }
+                }
+            }
+            rects.extend(current);
+        }
+        rects
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piet::{FontFamily, Text, TextLayoutBuilder};
+
+    const FONT: &[u8] = include_bytes!("../../tests/assets/Anaheim-Regular.ttf");
+    /// Covers Hebrew, which the first font lacks, and is in its fallback chain.
+    const FALLBACK_FONT: &[u8] = include_bytes!("../../tests/assets/DejaVuSans.ttf");
+
+    /// A factory knowing only the bundled fonts, with the family of the first.
+    fn factory() -> (WgpuText, FontFamily) {
+        let mut factory = WgpuText::new();
+        *factory.fonts.borrow_mut() = FontCache::without_system_fonts();
+        let family = factory.load_font(FONT).unwrap();
+        factory.load_font(FALLBACK_FONT).unwrap();
+        (factory, family)
+    }
+
+    fn layout(text: &str, max_width: f64) -> WgpuTextLayout {
+        let (mut factory, family) = factory();
+        factory
+            .new_text_layout(text.to_string())
+            .font(family, 16.0)
+            .max_width(max_width)
+            .build()
+            .unwrap()
+    }
+
+    fn grapheme_boundaries(text: &str) -> Vec<usize> {
+        text.grapheme_indices(true)
+            .map(|(offset, _)| offset)
+            .chain([text.len()])
+            .collect()
+    }
+
+    #[test]
+    fn text_position_multi_line() {
+        let layout = layout("hello\nworld", f64::MAX);
+        assert_eq!(layout.line_count(), 2);
+
+        let start = layout.hit_test_text_position(0);
+        assert_eq!(start.line, 0);
+        assert_eq!(start.point.x, 0.0);
+
+        // the position before the line break stays on the first line
+        let end = layout.hit_test_text_position(5);
+        assert_eq!(end.line, 0);
+        assert!(end.point.x > 0.0);
+
+        let second = layout.hit_test_text_position(6);
+        assert_eq!(second.line, 1);
+        assert_eq!(second.point.x, 0.0);
+        assert!(second.point.y > end.point.y);
+
+        let last = layout.hit_test_text_position(100);
+        assert_eq!(last.line, 1);
+        assert!(last.point.x > 0.0);
+    }
+
+    #[test]
+    fn text_position_snaps_to_graphemes() {
+        // 'ñ' is two bytes and "e\u{301}" is a single grapheme of three bytes
+        let text = "añe\u{301}b";
+        let layout = layout(text, f64::MAX);
+        let x = |idx| layout.hit_test_text_position(idx).point.x;
+        assert_eq!(x(2), x(1));
+        assert_eq!(x(4), x(3));
+        assert_eq!(x(5), x(3));
+        assert!(x(6) > x(3));
+    }
+
+    #[test]
+    fn hit_test_point_round_trip() {
+        for text in [
+            "hello\nworld",
+            "añe\u{301}b\r\n€😀 ç",
+            "wrapped text on lines",
+        ] {
+            let layout = layout(text, 60.0);
+            for idx in grapheme_boundaries(text) {
+                let position = layout.hit_test_text_position(idx);
+                let hit = layout.hit_test_point(position.point);
+                assert_eq!(hit.idx, idx, "{text:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn hit_test_point_outside() {
+        let layout = layout("hello\nworld", f64::MAX);
+        let size = layout.size();
+
+        let hit = layout.hit_test_point(Point::new(-10.0, 1.0));
+        assert_eq!(hit.idx, 0);
+        assert!(!hit.is_inside);
+
+        let hit = layout.hit_test_point(Point::new(size.width + 10.0, 1.0));
+        assert_eq!(hit.idx, 5);
+        assert!(!hit.is_inside);
+
+        let hit = layout.hit_test_point(Point::new(1.0, size.height + 10.0));
+        assert_eq!(hit.idx, 6);
+        assert!(!hit.is_inside);
+
+        let hit = layout.hit_test_point(Point::new(size.width + 10.0, size.height + 10.0));
+        assert_eq!(hit.idx, 11);
+
+        let hit = layout.hit_test_point(Point::new(1.0, 1.0));
+        assert_eq!(hit.idx, 0);
+        assert!(hit.is_inside);
+    }
+
+    #[test]
+    fn hit_test_point_soft_wrap() {
+        let layout = layout("hello world", 40.0);
+        assert_eq!(layout.line_count(), 2);
+        let first = layout.line_metric(0).unwrap();
+
+        // clicking past the end of a wrapped line keeps the caret on that line
+        let hit = layout.hit_test_point(Point::new(1000.0, first.baseline));
+        assert_eq!(hit.idx, 5);
+        assert_eq!(layout.hit_test_text_position(hit.idx).line, 0);
+        assert_eq!(layout.hit_test_text_position(6).line, 1);
+    }
+
+    #[test]
+    fn cursor_line_spans_line_height() {
+        let layout = layout("hello world\nnext", 40.0);
+        for idx in grapheme_boundaries(layout.text()) {
+            let position = layout.hit_test_text_position(idx);
+            let metric = layout.line_metric(position.line).unwrap();
+            let cursor = layout.cursor_line_for_text_position(idx);
+            assert_eq!(cursor.p0.x, position.point.x);
+            assert_eq!(cursor.p1.x, position.point.x);
+            assert_eq!(cursor.p0.y, metric.y_offset);
+            assert_eq!(cursor.p1.y, metric.y_offset + metric.height);
+        }
+
+        let end = layout.cursor_line_for_text_position(layout.text().len());
+        let last = layout.line_metric(layout.line_count() - 1).unwrap();
+        assert!(end.p0.x > 0.0);
+        assert_eq!(end.p0.y, last.y_offset);
+    }
+
+    #[test]
+    fn decorations_cover_their_runs() {
+        let (mut factory, family) = factory();
+        let layout = factory
+            .new_text_layout("hello world")
+            .font(family, 16.0)
+            .range_attribute(0..5, TextAttribute::Underline(true))
+            .range_attribute(6..11, TextAttribute::Strikethrough(true))
+            .range_attribute(6..11, TextAttribute::TextColor(Color::RED))
+            .build()
+            .unwrap();
+        let x = |idx| layout.hit_test_text_position(idx).point.x;
+        let decorations = layout.decorations();
+        assert_eq!(decorations.len(), 2);
+
+        let metric = layout.line_metric(0).unwrap();
+        let baseline = metric.y_offset + metric.baseline;
+        let (underline, color) = decorations[0];
+        assert_eq!((underline.x0, underline.x1), (0.0, x(5)));
+        assert!(underline.y0 > baseline - 0.5 && underline.y1 < metric.height);
+        assert!(underline.height() >= 1.0);
+        assert_eq!(*color, layout.attrs.defaults.fg_color);
+
+        let (strikethrough, color) = decorations[1];
+        assert_eq!((strikethrough.x0, strikethrough.x1), (x(6), x(11)));
+        assert!(strikethrough.y1 < baseline && strikethrough.y0 > metric.y_offset);
+        assert_eq!(*color, Color::RED);
+    }
+
+    #[test]
+    fn mixed_direction_caret() {
+        // "abc " 0..4, three hebrew letters of two bytes 4..10, " def" 10..14
+        let text = "abc \u{5d0}\u{5d1}\u{5d2} def";
+        let layout = layout(text, f64::MAX);
+        let x = |idx| layout.hit_test_text_position(idx).point.x;
+
+        // the hebrew letters are drawn with the bundled fallback font
+        let hebrew = layout
+            .runs
+            .iter()
+            .find(|run| run.clusters[0].range.start == 4)
+            .unwrap();
+        let font = hebrew.font.as_ref().unwrap();
+        assert!(font.advance('\u{5d0}', 16.0).is_some());
+
+        // the right-to-left run is drawn from its right edge
+        assert!(x(4) > x(6));
+        assert!(x(6) > x(8));
+        assert!(x(8) > x(3));
+        assert!(x(12) > x(4));
+
+        for idx in [1, 6, 8, 12, 14] {
+            let hit = layout.hit_test_point(layout.hit_test_text_position(idx).point);
+            assert_eq!(hit.idx, idx, "at {idx}");
+        }
+    }
+
+    #[test]
+    fn mixed_direction_selection() {
+        let text = "abc \u{5d0}\u{5d1}\u{5d2} def";
+        let layout = layout(text, f64::MAX);
+        let x = |idx| layout.hit_test_text_position(idx).point.x;
+
+        let hebrew = layout.rects_for_range(4..10);
+        assert_eq!(hebrew.len(), 1);
+        assert!(hebrew[0].x0 < x(8));
+        assert!((hebrew[0].x1 - x(4)).abs() < 1e-6);
+
+        // the first hebrew letter is visually apart from the latin text before it
+        let rects = layout.rects_for_range(0..6);
+        assert_eq!(rects.len(), 2);
+        assert_eq!(rects[0].x0, 0.0);
+        assert!((rects[1].x1 - x(4)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn right_to_left_paragraph() {
+        let text = "\u{5e9}\u{5dc}\u{5d5}\u{5dd} world";
+        let layout = layout(text, 300.0);
+        let line = &layout.lines[0];
+        assert!(line.rtl);
+        // start alignment puts a right-to-left line against the right edge
+        assert!(line.x > 0.0);
+        assert!((line.x + line.width - 300.0).abs() < 1e-6);
+
+        let start = layout.hit_test_text_position(0).point.x;
+        assert!((start - 300.0).abs() < 1e-6);
+        let hit = layout.hit_test_point(Point::new(299.0, 1.0));
+        assert_eq!(hit.idx, 0);
+    }
+
+    fn mono_layout(text: &str, tab_width: usize, bounds: Option<[f64; 2]>) -> WgpuTextLayout {
+        let (mut factory, family) = factory();
+        factory
+            .new_text_layout(text.to_string())
+            .font(family, 16.0)
+            .build_with_info(true, tab_width, bounds)
+    }
+
+    #[test]
+    fn monospace_cells() {
+        let layout = mono_layout("il\u{4e2d}m", 4, None);
+        let cell = layout.runs[0].cell;
+        let x = |idx| layout.hit_test_text_position(idx).point.x;
+        assert!((x(1) - cell).abs() < 1e-6);
+        assert!((x(2) - 2.0 * cell).abs() < 1e-6);
+        // the ideograph is two cells wide
+        assert!((x(5) - 4.0 * cell).abs() < 1e-6);
+        assert!((layout.size().width - 5.0 * cell).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tabs_reach_the_next_stop() {
+        let layout = mono_layout("a\tb\n\tc\nabcd\te", 4, None);
+        let cell = layout.runs[0].cell;
+        let x = |idx| layout.hit_test_text_position(idx).point.x;
+        assert!((x(2) - 4.0 * cell).abs() < 1e-6);
+        assert!((x(5) - 4.0 * cell).abs() < 1e-6);
+        // a tab starting on a stop advances to the following one
+        assert!((x(12) - 8.0 * cell).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bounds_clip_lines_and_glyphs() {
+        let full = mono_layout("abcdef\nghi\njkl", 4, None);
+        let cell = full.runs[0].cell;
+        let height = full.lines[0].metric.height;
+        let layout = mono_layout("abcdef\nghi\njkl", 4, Some([2.5 * cell, height * 1.5]));
+        assert_eq!(layout.line_count(), 2);
+        assert!((layout.size().width - 2.5 * cell).abs() < 1e-6);
+        assert!((layout.size().height - height * 1.5).abs() < 1e-6);
+        // the third glyph starts inside the bounds, the fourth does not
+        assert_eq!(layout.lines[0].runs[0].clusters, 0..3);
+    }
+
+    #[test]
+    fn layouts_are_cached() {
+        let (mut factory, family) = factory();
+        let build = |factory: &mut WgpuText, text: &str| {
+            factory
+                .new_text_layout(text.to_string())
+                .font(family.clone(), 16.0)
+                .build()
+                .unwrap()
+        };
+        let first = build(&mut factory, "label");
+        let second = build(&mut factory, "label");
+        assert!(Rc::ptr_eq(&first.runs, &second.runs));
+        build(&mut factory, "other");
+        let stats = factory.layout_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 2, 2));
+
+        factory.set_layout_cache_max_age(1);
+        factory.end_frame();
+        build(&mut factory, "label");
+        factory.end_frame();
+        let stats = factory.layout_cache_stats();
+        assert_eq!((stats.evictions, stats.entries), (1, 1));
+    }
+
+    #[test]
+    fn layouts_in_use_survive_nested_states() {
+        let (mut factory, family) = factory();
+        factory.set_layout_cache_max_age(1);
+        for _ in 0..4 {
+            // a frame drawing the layout inside many save and restore pairs,
+            // which leave the cache alone; finishing the frame ages it once
+            for _ in 0..8 {
+                factory
+                    .new_text_layout("label")
+                    .font(family.clone(), 16.0)
+                    .build()
+                    .unwrap();
+            }
+            factory.end_frame();
+        }
+        let stats = factory.layout_cache_stats();
+        assert_eq!((stats.misses, stats.evictions, stats.entries), (1, 0, 1));
+    }
+}
@@ -0,0 +1,121 @@
+use piet::{FontFamily, Text};
+use std::{cell::RefCell, rc::Rc};
+use swash::text::Script;
+
+mod atlas;
+mod attributes;
+mod cache;
+mod font;
+mod layout;
+mod path;
+mod render;
+
+use atlas::GlyphAtlas;
+pub use atlas::{TextAntialiasing, TextRendering};
+use cache::LayoutCache;
+pub use cache::LayoutCacheStats;
+use font::FontCache;
+pub use font::FontFallback;
+pub use layout::{WgpuTextLayout, WgpuTextLayoutBuilder};
+pub use path::{PathTextOptions, PathTextOverflow};
+pub(crate) use render::{linear_premultiplied, TextRenderer};
+
+/// The text factory, shared by every layout it creates.
+///
+/// Cloning is cheap, all clones refer to the same font database and layout cache.
+#[derive(Clone)]
+pub struct WgpuText {
+    pub(crate) fonts: Rc<RefCell<FontCache>>,
+    pub(crate) atlas: Rc<RefCell<GlyphAtlas>>,
+    pub(crate) layouts: Rc<RefCell<LayoutCache>>,
+}
+
+impl WgpuText {
+    pub(crate) fn new() -> Self {
+        Self {
+            fonts: Rc::new(RefCell::new(FontCache::new())),
+            atlas: Rc::new(RefCell::new(GlyphAtlas::new())),
+            layouts: Rc::new(RefCell::new(LayoutCache::new())),
+        }
+    }
+
+    /// Returns how glyphs are rasterized.
+    pub fn text_rendering(&self) -> TextRendering {
+        self.atlas.borrow().options()
+    }
+
+    /// Changes how glyphs are rasterized. Grayscale antialiasing on whole
+    /// pixels is the default.
+    ///
+    /// Subpixel antialiasing is drawn as grayscale on devices without dual
+    /// source blending.
+    pub fn set_text_rendering(&mut self, options: TextRendering) {
+        self.atlas.borrow_mut().set_options(options);
+    }
+
+    /// Returns the hit and miss counters of the layout cache.
+    pub fn layout_cache_stats(&self) -> LayoutCacheStats {
+        self.layouts.borrow().stats()
+    }
+
+    /// Sets the number of frames a layout is kept for after it was last built.
+    pub fn set_layout_cache_max_age(&mut self, frames: u64) {
+        self.layouts.borrow_mut().max_age = frames;
+    }
+
+    /// Evicts the layouts which went unused for too long, called once per frame.
+    pub(crate) fn end_frame(&self) {
+        self.layouts.borrow_mut().end_frame();
+    }
+
+    /// Returns the families used for characters the requested font lacks.
+    pub fn font_fallback(&self) -> FontFallback {
+        self.fonts.borrow().fallback.clone()
+    }
+
+    /// Replaces the families used for characters the requested font lacks.
+    ///
+    /// Layouts which are already built keep the fonts they were shaped with.
+    pub fn set_font_fallback(&mut self, fallback: FontFallback) {
+        self.fonts.borrow_mut().set_fallback(fallback);
+        self.layouts.borrow_mut().clear();
+    }
+
+    /// Sets the families tried first for characters of `script`.
+    pub fn set_script_fallback(
+        &mut self,
+        script: Script,
+        families: impl IntoIterator<Item = FontFamily>,
+    ) {
+        let mut fallback = self.font_fallback();
+        fallback
+            .scripts
+            .insert(script, families.into_iter().collect());
+        self.set_font_fallback(fallback);
+    }
+}
+
+impl Text for WgpuText {
+    type TextLayoutBuilder = WgpuTextLayoutBuilder;
+    type TextLayout = WgpuTextLayout;
+
+    fn font_family(&mut self, family_name: &str) -> Option<FontFamily> {
+        self.fonts.borrow_mut().font_family(family_name)
+    }
+
+    fn load_font(&mut self, data: &[u8]) -> Result<FontFamily, piet::Error> {
+        let family = self
+            .fonts
+            .borrow_mut()
+            .load_font(data)
+            .ok_or(piet::Error::FontLoadingFailed)?;
+        // cached layouts may have been shaped with a fallback for this font
+        self.layouts.borrow_mut().clear();
+        Ok(family)
+    }
+
+    fn new_text_layout(&mut self, text: impl piet::TextStorage) -> Self::TextLayoutBuilder {
+        let state = self.clone();
+        Self::TextLayoutBuilder::new(text, state)
+    }
+}
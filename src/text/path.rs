@@ -0,0 +1,200 @@
+use piet::{
+    kurbo::{flatten, Affine, PathEl, Point, Shape, Vec2},
+    RenderContext, TextLayout,
+};
+
+use super::{render::GlyphInstance, WgpuTextLayout};
+use crate::context::WgpuRenderContext;
+
+/// Tolerance used to flatten curves into segments, in user space units.
+const FLATTEN_TOLERANCE: f64 = 0.1;
+
+/// What happens to the glyphs which do not fit on the path.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathTextOverflow {
+    /// Glyphs whose center falls past the end of the path are not drawn.
+    #[default]
+    Clip,
+    /// Glyphs past the end of the path continue in a straight line, in the
+    /// direction the path ends in.
+    Extend,
+    /// The text is scaled down uniformly until it fits on the path.
+    Fit,
+}
+
+/// How a layout is placed along a path by
+/// [`WgpuRenderContext::draw_text_on_path`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PathTextOptions {
+    /// Distance along the path at which the text starts.
+    pub offset: f64,
+    pub overflow: PathTextOverflow,
+}
+
+/// A straight piece of a flattened path.
+struct Segment {
+    start: Point,
+    /// Unit vector along the segment.
+    direction: Vec2,
+    /// Arc length of the path at the start of the segment.
+    distance: f64,
+    length: f64,
+}
+
+/// A path flattened into segments, so that points can be found by arc length.
+///
+/// The gaps between subpaths are not part of the length.
+struct ArcLengthPath {
+    segments: Vec<Segment>,
+    length: f64,
+}
+
+impl ArcLengthPath {
+    fn new(path: impl IntoIterator<Item = PathEl>) -> Self {
+        let mut segments = Vec::new();
+        let mut length = 0.0;
+        let mut subpath_start = Point::ZERO;
+        let mut last = Point::ZERO;
+        flatten(path, FLATTEN_TOLERANCE, |el| {
+            let end = match el {
+                PathEl::MoveTo(point) => {
+                    subpath_start = point;
+                    last = point;
+                    return;
+                }
+                PathEl::LineTo(point) => point,
+                PathEl::ClosePath => subpath_start,
+                // flattening only emits lines
+                PathEl::QuadTo(..) | PathEl::CurveTo(..) => return,
+            };
+            let segment_length = (end - last).hypot();
+            if segment_length > 0.0 {
+                segments.push(Segment {
+                    start: last,
+                    direction: (end - last) / segment_length,
+                    distance: length,
+                    length: segment_length,
+                });
+                length += segment_length;
+            }
+            last = end;
+        });
+        Self { segments, length }
+    }
+
+    /// Returns the point at `distance` along the path and the direction of the
+    /// path there. Distances beyond either end continue along the end segments
+    /// if `extend` is set, and have no point otherwise.
+    fn sample(&self, distance: f64, extend: bool) -> Option<(Point, Vec2)> {
+        if !extend && !(0.0..=self.length).contains(&distance) {
+            return None;
+        }
+        let index = self
+            .segments
+            .partition_point(|segment| segment.distance + segment.length < distance);
+        let segment = self.segments.get(index).or_else(|| self.segments.last())?;
+        let point = segment.start + segment.direction * (distance - segment.distance);
+        Some((point, segment.direction))
+    }
+}
+
+impl WgpuTextLayout {
+    /// Draws the glyphs of the layout along `path`, see
+    /// [`WgpuRenderContext::draw_text_on_path`].
+    pub(crate) fn draw_on_path(
+        &self,
+        ctx: &mut WgpuRenderContext,
+        path: impl Shape,
+        options: PathTextOptions,
+    ) {
+        let path = ArcLengthPath::new(path.path_elements(FLATTEN_TOLERANCE));
+        let Some(first_line) = self.lines.first() else {
+            return;
+        };
+        let first_baseline = first_line.metric.y_offset + first_line.metric.baseline;
+        let width = self.size().width;
+        let fit = match options.overflow {
+            PathTextOverflow::Fit if width > 0.0 => {
+                ((path.length - options.offset) / width).clamp(0.0, 1.0)
+            }
+            _ => 1.0,
+        };
+        if fit <= 0.0 {
+            return;
+        }
+        let extend = options.overflow == PathTextOverflow::Extend;
+        let transform = ctx.current_transform();
+
+        let mut atlas = self.atlas.borrow_mut();
+        let mut text_renderer = ctx.renderer.text_renderer.borrow_mut();
+        self.for_each_glyph(|run, font, glyph, [x, y]| {
+            // each glyph is placed by its center, and turned to follow the path there
+            let half_advance = glyph.advance as f64 / 2.0;
+            let center = options.offset + (x + half_advance) * fit;
+            let Some((point, direction)) = path.sample(center, extend) else {
+                return;
+            };
+            let glyph_transform = transform
+                * Affine::translate(point.to_vec2())
+                * Affine::rotate(direction.atan2())
+                * Affine::scale(fit);
+            let scale = glyph_transform.determinant().abs().sqrt().max(f64::EPSILON);
+            // later lines are stacked below the path
+            let origin = [
+                glyph.x as f64 - half_advance,
+                y - first_baseline - glyph.y as f64,
+            ];
+            let size = (run.style.size * scale) as f32;
+            if let Some(entry) = atlas.glyph(font, glyph.id, size, 0) {
+                text_renderer.push(GlyphInstance::new(
+                    &entry,
+                    origin,
+                    scale,
+                    glyph_transform,
+                    &run.style.color,
+                ));
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use piet::kurbo::{BezPath, Line};
+
+    #[test]
+    fn sample_by_arc_length() {
+        let mut path = BezPath::new();
+        path.move_to((0.0, 0.0));
+        path.line_to((10.0, 0.0));
+        path.line_to((10.0, 10.0));
+        // the gap to the second subpath does not count
+        path.move_to((100.0, 100.0));
+        path.line_to((100.0, 110.0));
+        let path = ArcLengthPath::new(path);
+        assert_eq!(path.length, 30.0);
+
+        let (point, direction) = path.sample(15.0, false).unwrap();
+        assert_eq!(point, Point::new(10.0, 5.0));
+        assert_eq!(direction, Vec2::new(0.0, 1.0));
+        let (point, _) = path.sample(25.0, false).unwrap();
+        assert_eq!(point, Point::new(100.0, 105.0));
+
+        assert!(path.sample(31.0, false).is_none());
+        let (point, _) = path.sample(35.0, true).unwrap();
+        assert_eq!(point, Point::new(100.0, 115.0));
+        let (point, _) = path.sample(-5.0, true).unwrap();
+        assert_eq!(point, Point::new(-5.0, 0.0));
+    }
+
+    #[test]
+    fn curves_are_measured() {
+        let line = ArcLengthPath::new(Line::new((0.0, 0.0), (3.0, 4.0)).path_elements(0.1));
+        assert_eq!(line.length, 5.0);
+        let circle = piet::kurbo::Circle::new((0.0, 0.0), 10.0);
+        let circle = ArcLengthPath::new(circle.path_elements(0.1));
+        let circumference = 20.0 * std::f64::consts::PI;
+        assert!((circle.length - circumference).abs() < 0.5);
+    }
+}
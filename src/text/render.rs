@@ -0,0 +1,630 @@
+use bytemuck::{Pod, Zeroable};
+use piet::{
+    kurbo::{Affine, Rect},
+    Color,
+};
+use std::ops::Range;
+use wgpu::{
+    BindGroupLayoutEntry, BindingType, BlendComponent, BlendFactor, BlendOperation, BlendState,
+    BufferUsages, ColorTargetState, ColorWrites, Extent3d, ImageCopyTexture, ImageDataLayout,
+    MultisampleState, Origin3d, PrimitiveState, PrimitiveTopology, RenderPass, SamplerBindingType,
+    ShaderStages, TextureAspect, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureViewDimension, VertexFormat, VertexStepMode,
+};
+
+use super::atlas::{
+    AtlasGlyph, AtlasPage, GlyphAtlas, GlyphContent, TextAntialiasing, SUBPIXEL_STEPS,
+};
+use crate::render_resource::{
+    BindGroup, BindGroupEntries, BindGroupLayout, BufferVec, CachedRenderPipelineId, FragmentState,
+    PipelineCache, RenderDevice, RenderPipeline, RenderPipelineDescriptor, RenderQueue, Sampler,
+    Shader, ShaderDefVal, Texture, TextureView, VertexBufferLayout, VertexState,
+};
+
+/// Blends each channel of the target by the coverage of the matching channel,
+/// given as the second output of the fragment shader.
+const DUAL_SOURCE_BLENDING: BlendState = BlendState {
+    color: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::OneMinusSrc1,
+        operation: BlendOperation::Add,
+    },
+    alpha: BlendComponent {
+        src_factor: BlendFactor::One,
+        dst_factor: BlendFactor::OneMinusSrc1Alpha,
+        operation: BlendOperation::Add,
+    },
+};
+
+/// One glyph quad, as read by `glyph.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+pub(crate) struct GlyphInstance {
+    /// Top left corner of the glyph image, in device pixels.
+    pub origin: [f32; 2],
+    /// The edges of the glyph image, in device pixels.
+    pub axis_x: [f32; 2],
+    pub axis_y: [f32; 2],
+    /// Position and size of the glyph image in its atlas page, in texels.
+    pub uv_rect: [f32; 4],
+    /// Premultiplied linear color.
+    pub color: [f32; 4],
+    pub content: u32,
+}
+
+impl GlyphInstance {
+    /// Places the image of `glyph`, whose origin is at `origin` in user space.
+    ///
+    /// `scale` is the factor the glyph was rasterized at, relative to user space.
+    pub(crate) fn new(
+        glyph: &AtlasGlyph,
+        origin: [f64; 2],
+        scale: f64,
+        transform: Affine,
+        color: &Color,
+    ) -> Self {
+        let [a, b, c, d, e, f] = transform.as_coeffs();
+        let x = origin[0] + glyph.left as f64 / scale;
+        let y = origin[1] - glyph.top as f64 / scale;
+        let width = glyph.width as f64 / scale;
+        let height = glyph.height as f64 / scale;
+        Self {
+            origin: [(a * x + c * y + e) as f32, (b * x + d * y + f) as f32],
+            axis_x: [(a * width) as f32, (b * width) as f32],
+            axis_y: [(c * height) as f32, (d * height) as f32],
+            uv_rect: [
+                glyph.x as f32,
+                glyph.y as f32,
+                glyph.width as f32,
+                glyph.height as f32,
+            ],
+            color: linear_premultiplied(color),
+            content: match glyph.content {
+                GlyphContent::Mask => 0,
+                GlyphContent::Color => 1,
+                GlyphContent::Subpixel => 2,
+            },
+        }
+    }
+
+    /// A quad filled with `color`, such as an underline, covering `rect` in
+    /// user space.
+    pub(crate) fn solid(rect: Rect, transform: Affine, color: &Color) -> Self {
+        let [a, b, c, d, e, f] = transform.as_coeffs();
+        let (x, y) = (rect.x0, rect.y0);
+        let (width, height) = (rect.width(), rect.height());
+        Self {
+            origin: [(a * x + c * y + e) as f32, (b * x + d * y + f) as f32],
+            axis_x: [(a * width) as f32, (b * width) as f32],
+            axis_y: [(c * height) as f32, (d * height) as f32],
+            uv_rect: [0.0; 4],
+            color: linear_premultiplied(color),
+            content: 3,
+        }
+    }
+}
+
+/// Moves the origin of a glyph, in user space, onto the pixel grid of the target.
+///
+/// Returns the moved origin and the horizontal offset the glyph should be
+/// rasterized at, in steps of 1 / [`SUBPIXEL_STEPS`] pixel, or `None` when the
+/// transform rotates, skews or flips the text and no grid can be followed.
+pub(crate) fn snap_to_subpixel(origin: [f64; 2], transform: Affine) -> Option<([f64; 2], u8)> {
+    let [a, b, c, d, e, f] = transform.as_coeffs();
+    if b != 0.0 || c != 0.0 || a <= 0.0 || d <= 0.0 {
+        return None;
+    }
+    let steps = SUBPIXEL_STEPS as f64;
+    let x = a * origin[0] + e;
+    let snapped = (x * steps).round() / steps;
+    let whole = snapped.floor();
+    let offset = ((snapped - whole) * steps) as u8;
+    // the baseline is kept on a whole pixel
+    let y = d * origin[1] + f;
+    Some((
+        [origin[0] + (whole - x) / a, origin[1] + (y.round() - y) / d],
+        offset,
+    ))
+}
+
+/// Converts an sRGB color into the premultiplied linear color blended by the pipeline.
+pub(crate) fn linear_premultiplied(color: &Color) -> [f32; 4] {
+    fn linear(c: f64) -> f32 {
+        let c = if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        };
+        c as f32
+    }
+    let (r, g, b, a) = color.as_rgba();
+    let a = a as f32;
+    [linear(r) * a, linear(g) * a, linear(b) * a, a]
+}
+
+/// The size of the render target in pixels, as read by `glyph.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+struct Viewport {
+    width: f32,
+    height: f32,
+}
+
+/// The GPU copy of an atlas page.
+struct PageTexture {
+    texture: Texture,
+    view: TextureView,
+    size: u32,
+}
+
+impl PageTexture {
+    fn new(device: &RenderDevice, page: &AtlasPage, format: TextureFormat, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: Extent3d {
+                width: page.size,
+                height: page.size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        Self {
+            texture,
+            view,
+            size: page.size,
+        }
+    }
+
+    fn upload(&self, queue: &RenderQueue, page: &AtlasPage) {
+        let bytes_per_texel = page.data.len() as u32 / (page.size * page.size);
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &page.data,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(page.size * bytes_per_texel),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: page.size,
+                height: page.size,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
+/// Draws the glyphs recorded by [`WgpuTextLayout::draw_text`](super::WgpuTextLayout)
+/// out of the glyph atlas.
+pub(crate) struct TextRenderer {
+    layout: BindGroupLayout,
+    pipeline: GlyphPipeline,
+    /// The pipeline for subpixel antialiasing, when the device supports dual
+    /// source blending.
+    dual_source_pipeline: Option<GlyphPipeline>,
+    /// Whether the glyphs of this frame are drawn with subpixel antialiasing.
+    use_subpixel: bool,
+    sampler: Sampler,
+    /// Holds the one [`Viewport`] of the frame.
+    viewport: BufferVec<Viewport>,
+    instances: BufferVec<GlyphInstance>,
+    mask: Option<PageTexture>,
+    color: Option<PageTexture>,
+    subpixel: Option<PageTexture>,
+    bind_group: Option<BindGroup>,
+}
+
+/// A pipeline of the [`TextRenderer`], with its descriptor to queue it again
+/// when it was evicted from the [`PipelineCache`].
+struct GlyphPipeline {
+    descriptor: RenderPipelineDescriptor,
+    id: CachedRenderPipelineId,
+}
+
+impl GlyphPipeline {
+    fn new(pipeline_cache: &PipelineCache, descriptor: RenderPipelineDescriptor) -> Self {
+        Self {
+            id: pipeline_cache.queue_render_pipeline(descriptor.clone()),
+            descriptor,
+        }
+    }
+
+    fn queue_if_evicted(&mut self, pipeline_cache: &PipelineCache) {
+        if !pipeline_cache.contains_render_pipeline(self.id) {
+            self.id = pipeline_cache.queue_render_pipeline(self.descriptor.clone());
+        }
+    }
+}
+
+fn glyph_shader() -> Shader {
+    Shader::from_wgsl(include_str!("glyph.wgsl"), "piet_wgpu/text/glyph.wgsl")
+}
+
+impl TextRenderer {
+    pub(crate) fn new(
+        device: &RenderDevice,
+        pipeline_cache: &mut PipelineCache,
+        format: TextureFormat,
+    ) -> Self {
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("glyph_bind_group_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1),
+                texture_entry(2),
+                texture_entry(3),
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let shader = glyph_shader();
+        pipeline_cache.set_shader(shader.id, &shader);
+        let queue_pipeline = |label: &'static str, shader_defs: Vec<ShaderDefVal>, blend| {
+            GlyphPipeline::new(
+                pipeline_cache,
+                RenderPipelineDescriptor {
+                    label: Some(label.into()),
+                    layout: vec![layout.clone()],
+                    push_constant_ranges: Vec::new(),
+                    vertex: VertexState {
+                        shader: shader.clone(),
+                        shader_defs: shader_defs.clone(),
+                        entry_point: "vertex".into(),
+                        buffers: vec![VertexBufferLayout::from_vertex_formats(
+                            VertexStepMode::Instance,
+                            [
+                                VertexFormat::Float32x2,
+                                VertexFormat::Float32x2,
+                                VertexFormat::Float32x2,
+                                VertexFormat::Float32x4,
+                                VertexFormat::Float32x4,
+                                VertexFormat::Uint32,
+                            ],
+                        )],
+                    },
+                    primitive: PrimitiveState {
+                        topology: PrimitiveTopology::TriangleStrip,
+                        ..Default::default()
+                    },
+                    depth_stencil: None,
+                    multisample: MultisampleState::default(),
+                    fragment: Some(FragmentState {
+                        shader: shader.clone(),
+                        shader_defs,
+                        entry_point: "fragment".into(),
+                        targets: vec![Some(ColorTargetState {
+                            format,
+                            blend: Some(blend),
+                            write_mask: ColorWrites::ALL,
+                        })],
+                    }),
+                },
+            )
+        };
+        let pipeline = queue_pipeline(
+            "glyph_pipeline",
+            Vec::new(),
+            BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+        );
+        let dual_source_pipeline = device
+            .features()
+            .contains(wgpu::Features::DUAL_SOURCE_BLENDING)
+            .then(|| {
+                queue_pipeline(
+                    "glyph_dual_source_pipeline",
+                    vec!["DUAL_SOURCE_BLENDING".into()],
+                    DUAL_SOURCE_BLENDING,
+                )
+            });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("glyph_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let mut viewport = BufferVec::new(BufferUsages::UNIFORM);
+        viewport.set_label(Some("glyph_viewport"));
+        let mut instances = BufferVec::new(BufferUsages::VERTEX);
+        instances.set_label(Some("glyph_instances"));
+
+        Self {
+            layout,
+            pipeline,
+            dual_source_pipeline,
+            use_subpixel: false,
+            sampler,
+            viewport,
+            instances,
+            mask: None,
+            color: None,
+            subpixel: None,
+            bind_group: None,
+        }
+    }
+
+    /// Drops the glyphs recorded so far.
+    pub(crate) fn clear(&mut self) {
+        self.instances.clear();
+    }
+
+    pub(crate) fn push(&mut self, instance: GlyphInstance) {
+        self.instances.push(instance);
+    }
+
+    /// The number of glyphs recorded so far.
+    pub(crate) fn len(&self) -> u32 {
+        self.instances.len() as u32
+    }
+
+    /// Uploads the recorded glyphs and the atlas pages which changed.
+    pub(crate) fn prepare(
+        &mut self,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        pipeline_cache: &PipelineCache,
+        atlas: &mut GlyphAtlas,
+        viewport: [u32; 2],
+    ) {
+        self.pipeline.queue_if_evicted(pipeline_cache);
+        if let Some(pipeline) = &mut self.dual_source_pipeline {
+            pipeline.queue_if_evicted(pipeline_cache);
+        }
+
+        self.viewport.clear();
+        self.viewport.push(Viewport {
+            width: viewport[0] as f32,
+            height: viewport[1] as f32,
+        });
+        self.viewport.write_buffer(device, queue);
+        self.instances.write_buffer(device, queue);
+        self.use_subpixel = atlas.options().antialiasing != TextAntialiasing::Grayscale;
+
+        let mut pages_changed = false;
+        for (texture, page, format, label) in [
+            (
+                &mut self.mask,
+                &mut atlas.mask,
+                TextureFormat::R8Unorm,
+                "glyph_mask_atlas",
+            ),
+            (
+                &mut self.color,
+                &mut atlas.color,
+                TextureFormat::Rgba8UnormSrgb,
+                "glyph_color_atlas",
+            ),
+            (
+                &mut self.subpixel,
+                &mut atlas.subpixel,
+                TextureFormat::Rgba8Unorm,
+                "glyph_subpixel_atlas",
+            ),
+        ] {
+            if texture
+                .as_ref()
+                .is_none_or(|texture| texture.size != page.size)
+            {
+                *texture = Some(PageTexture::new(device, page, format, label));
+                page.dirty = true;
+                pages_changed = true;
+            }
+            if page.dirty {
+                if let Some(texture) = texture {
+                    texture.upload(queue, page);
+                }
+                page.dirty = false;
+            }
+        }
+
+        if pages_changed || self.bind_group.is_none() {
+            if let (Some(mask), Some(color), Some(subpixel)) =
+                (&self.mask, &self.color, &self.subpixel)
+            {
+                self.bind_group = Some(device.create_bind_group(
+                    "glyph_bind_group",
+                    &self.layout,
+                    &BindGroupEntries::sequential((
+                        // written above, so it exists
+                        self.viewport.buffer().unwrap().as_entire_buffer_binding(),
+                        &mask.view,
+                        &color.view,
+                        &subpixel.view,
+                        &self.sampler,
+                    )),
+                ));
+            }
+        }
+    }
+
+    /// Returns the pipeline the glyphs of this frame are drawn with, once it is
+    /// compiled.
+    fn pipeline<'a>(&self, pipeline_cache: &'a PipelineCache) -> Option<&'a RenderPipeline> {
+        // without dual source blending, subpixel coverage is drawn as grayscale,
+        // which is also the fallback while the dual source pipeline is compiling
+        self.dual_source_pipeline
+            .as_ref()
+            .filter(|_| self.use_subpixel)
+            .and_then(|pipeline| pipeline_cache.get_render_pipeline(pipeline.id))
+            .or_else(|| pipeline_cache.get_render_pipeline(self.pipeline.id))
+    }
+
+    /// Draws the recorded glyphs in `instances`.
+    pub(crate) fn render<'a>(
+        &'a self,
+        pass: &mut RenderPass<'a>,
+        pipeline_cache: &'a PipelineCache,
+        instances: Range<u32>,
+    ) {
+        if instances.is_empty() {
+            return;
+        }
+        let (Some(pipeline), Some(bind_group), Some(buffer)) = (
+            self.pipeline(pipeline_cache),
+            &self.bind_group,
+            self.instances.buffer(),
+        ) else {
+            return;
+        };
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, bind_group, &[]);
+        pass.set_vertex_buffer(0, *buffer.slice(..));
+        pass.draw(0..4, instances);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        render_resource::{test_device, validate_shader, PipelineCacheError},
+        text::TextRendering,
+    };
+    use wgpu::Features;
+
+    #[test]
+    fn origins_snap_to_quarter_pixels() {
+        let snap = |x, y, transform| snap_to_subpixel([x, y], transform).unwrap();
+        assert_eq!(snap(10.0, 20.0, Affine::IDENTITY), ([10.0, 20.0], 0));
+        assert_eq!(snap(10.25, 20.25, Affine::IDENTITY), ([10.0, 20.0], 1));
+        assert_eq!(snap(10.75, 20.0, Affine::IDENTITY), ([10.0, 20.0], 3));
+        // to the nearest step, carrying into the next pixel
+        assert_eq!(snap(10.625, 20.75, Affine::IDENTITY), ([10.0, 21.0], 3));
+        assert_eq!(snap(10.875, 20.0, Affine::IDENTITY), ([11.0, 20.0], 0));
+        // the grid is the one of the target, with the origin in user space
+        let transform = Affine::translate((0.25, 0.5)) * Affine::scale(2.0);
+        assert_eq!(snap(5.0, 5.0, transform), ([4.875, 5.25], 1));
+    }
+
+    #[test]
+    fn origins_only_snap_under_scales_and_translations() {
+        for transform in [
+            Affine::rotate(0.1),
+            Affine::skew(0.2, 0.0),
+            Affine::FLIP_X,
+            Affine::scale_non_uniform(1.0, -1.0),
+            Affine::translate((2.0, 0.0)) * Affine::rotate(std::f64::consts::PI),
+        ] {
+            assert_eq!(snap_to_subpixel([3.3, 4.4], transform), None);
+        }
+    }
+
+    #[test]
+    fn subpixel_antialiasing_falls_back_to_grayscale() {
+        let Some((device, queue)) = test_device() else {
+            return;
+        };
+        let mut pipeline_cache = PipelineCache::new(device.clone());
+        let mut renderer =
+            TextRenderer::new(&device, &mut pipeline_cache, TextureFormat::Rgba8Unorm);
+        let mut atlas = GlyphAtlas::new();
+        let lcd = TextRendering {
+            antialiasing: TextAntialiasing::SubpixelRgb,
+            ..TextRendering::default()
+        };
+        atlas.set_options(lcd);
+        pipeline_cache.block_on_queue();
+        renderer.prepare(&device, &queue, &pipeline_cache, &mut atlas, [16, 16]);
+        let pipeline_id = |renderer: &TextRenderer, pipeline_cache: &PipelineCache| {
+            renderer.pipeline(pipeline_cache).unwrap().id()
+        };
+        let grayscale = pipeline_cache
+            .get_render_pipeline(renderer.pipeline.id)
+            .unwrap()
+            .id();
+        if !device.features().contains(Features::DUAL_SOURCE_BLENDING) {
+            assert!(renderer.dual_source_pipeline.is_none());
+            assert_eq!(pipeline_id(&renderer, &pipeline_cache), grayscale);
+        }
+
+        // stand in for the dual source pipeline of a device with the feature
+        let descriptor = renderer.pipeline.descriptor.clone();
+        renderer.dual_source_pipeline = Some(GlyphPipeline::new(&pipeline_cache, descriptor));
+        // which is only used once it is compiled
+        assert_eq!(pipeline_id(&renderer, &pipeline_cache), grayscale);
+        pipeline_cache.block_on_queue();
+        let dual_source = pipeline_id(&renderer, &pipeline_cache);
+        assert_ne!(dual_source, grayscale);
+
+        // and for subpixel antialiasing only
+        atlas.set_options(TextRendering::default());
+        renderer.prepare(&device, &queue, &pipeline_cache, &mut atlas, [16, 16]);
+        assert_eq!(pipeline_id(&renderer, &pipeline_cache), grayscale);
+        let bgr = TextRendering {
+            antialiasing: TextAntialiasing::SubpixelBgr,
+            ..lcd
+        };
+        atlas.set_options(bgr);
+        renderer.prepare(&device, &queue, &pipeline_cache, &mut atlas, [16, 16]);
+        assert_eq!(pipeline_id(&renderer, &pipeline_cache), dual_source);
+    }
+
+    #[test]
+    fn solid_quads_cover_their_rect() {
+        let rect = Rect::new(1.0, 2.0, 5.0, 3.0);
+        let instance = GlyphInstance::solid(rect, Affine::scale(2.0), &Color::BLACK);
+        assert_eq!(instance.origin, [2.0, 4.0]);
+        assert_eq!(instance.axis_x, [8.0, 0.0]);
+        assert_eq!(instance.axis_y, [0.0, 2.0]);
+        assert_eq!(instance.color, [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(instance.content, 3);
+    }
+
+    #[test]
+    fn glyph_shader_permutations_validate() {
+        let shader = glyph_shader();
+        validate_shader(&[], &shader, &[], Features::empty()).unwrap();
+        validate_shader(
+            &[],
+            &shader,
+            &["DUAL_SOURCE_BLENDING".into()],
+            Features::DUAL_SOURCE_BLENDING,
+        )
+        .unwrap();
+        // the dual source variant needs the feature
+        assert!(matches!(
+            validate_shader(
+                &[],
+                &shader,
+                &["DUAL_SOURCE_BLENDING".into()],
+                Features::empty()
+            ),
+            Err(PipelineCacheError::Validation(_))
+        ));
+    }
+}